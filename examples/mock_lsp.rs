@@ -0,0 +1,293 @@
+// a tiny language server to try the LSP client with, it speaks over stdio:
+//     cargo build --example mock_lsp
+//     smile: lsp rust target/debug/examples/mock_lsp
+// TODO marks a warning and FIXME an error, hover tells about the word under
+// the cursor, the definition of a word is its first occurrence, rename
+// replaces all of them and the completions are the words of the document,
+// with `--fail-init` it answers `initialize` with an error
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut len = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(v) = header.strip_prefix("Content-Length:") {
+            len = v.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: Value) {
+    let body = message.to_string();
+    let mut out = io::stdout().lock();
+    let _ = write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = out.flush();
+}
+
+// the byte index of the UTF-16 column `col`
+fn byte_idx(line: &str, col: u64) -> usize {
+    let mut used = 0;
+    for (i, c) in line.char_indices() {
+        if used >= col {
+            return i;
+        }
+        used += c.len_utf16() as u64;
+    }
+    line.len()
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+fn position(v: &Value) -> (usize, u64) {
+    (
+        v["line"].as_u64().unwrap_or(0) as usize,
+        v["character"].as_u64().unwrap_or(0),
+    )
+}
+
+fn apply_change(text: &mut String, change: &Value) {
+    let new = change["text"].as_str().unwrap_or_default();
+    let Some(range) = change.get("range") else {
+        *text = new.to_string();
+        return;
+    };
+    let offset = |text: &str, (line, col): (usize, u64)| {
+        let mut start = 0;
+        for (i, l) in text.split('\n').enumerate() {
+            if i == line {
+                return start + byte_idx(l, col);
+            }
+            start += l.len() + 1;
+        }
+        text.len()
+    };
+    let start = offset(text, position(&range["start"]));
+    let end = offset(text, position(&range["end"]));
+    text.replace_range(start..end.max(start), new);
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// the word at the position
+fn word_at(text: &str, (line, col): (usize, u64)) -> Option<String> {
+    let line = text.split('\n').nth(line)?;
+    let at = byte_idx(line, col);
+    let start = line[..at]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(at, |(i, _)| i);
+    let end = line[at..]
+        .char_indices()
+        .find(|(_, c)| !is_word(*c))
+        .map_or(line.len(), |(i, _)| at + i);
+    (start < end).then(|| line[start..end].to_string())
+}
+
+// the ranges of the whole word occurrences of `word`
+fn occurrences(text: &str, word: &str) -> Vec<Value> {
+    let mut ranges = vec![];
+    for (y, line) in text.split('\n').enumerate() {
+        for (i, _) in line.match_indices(word) {
+            let before = line[..i].chars().next_back();
+            let after = line[i + word.len()..].chars().next();
+            if before.is_some_and(is_word) || after.is_some_and(is_word) {
+                continue;
+            }
+            let x = utf16_len(&line[..i]);
+            ranges.push(json!({
+                "start": { "line": y, "character": x },
+                "end": { "line": y, "character": x + utf16_len(word) },
+            }));
+        }
+    }
+    ranges
+}
+
+fn diagnostics(text: &str) -> Vec<Value> {
+    let mut found = vec![];
+    for (y, line) in text.split('\n').enumerate() {
+        for (mark, severity) in [("FIXME", 1), ("TODO", 2)] {
+            if let Some(i) = line.find(mark) {
+                let x = utf16_len(&line[..i]);
+                found.push(json!({
+                    "range": {
+                        "start": { "line": y, "character": x },
+                        "end": { "line": y, "character": x + mark.len() },
+                    },
+                    "severity": severity,
+                    "source": "mock",
+                    "message": format!("{} left here", mark),
+                }));
+            }
+        }
+    }
+    found
+}
+
+fn publish(uri: &str, text: &str) {
+    send(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics(text) },
+    }));
+}
+
+fn main() {
+    let fail_init = std::env::args().any(|a| a == "--fail-init");
+    let mut docs: HashMap<String, String> = HashMap::new();
+    let mut stdin = io::stdin().lock();
+    while let Some(msg) = read_message(&mut stdin) {
+        let params = &msg["params"];
+        // `mock.count` gets the uri as its argument
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .or_else(|| params["arguments"][0].as_str())
+            .unwrap_or_default();
+        let text = docs.get(uri).cloned().unwrap_or_default();
+        let word = word_at(&text, position(&params["position"]));
+        let result = match msg["method"].as_str().unwrap_or_default() {
+            "initialize" if fail_init => {
+                send(json!({
+                    "jsonrpc": "2.0",
+                    "id": msg["id"],
+                    "error": { "code": -32603, "message": "no workspace" },
+                }));
+                continue;
+            }
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": { "openClose": true, "change": 2, "save": true },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                    "codeActionProvider": true,
+                    "completionProvider": {},
+                    "executeCommandProvider": { "commands": ["mock.count"] },
+                },
+                "serverInfo": { "name": "mock" },
+            }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                docs.insert(uri.to_string(), text.to_string());
+                publish(uri, text);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let mut text = text;
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    apply_change(&mut text, change);
+                }
+                publish(uri, &text);
+                docs.insert(uri.to_string(), text);
+                continue;
+            }
+            "textDocument/didClose" => {
+                docs.remove(uri);
+                continue;
+            }
+            "textDocument/hover" => match word {
+                Some(word) => {
+                    let count = occurrences(&text, &word).len();
+                    json!({ "contents": format!("{}: used {} times", word, count) })
+                }
+                None => Value::Null,
+            },
+            "textDocument/definition" => {
+                match word.and_then(|w| occurrences(&text, &w).first().cloned()) {
+                    Some(range) => json!({ "uri": uri, "range": range }),
+                    None => Value::Null,
+                }
+            }
+            "textDocument/references" => {
+                let ranges = word.map(|w| occurrences(&text, &w)).unwrap_or_default();
+                ranges
+                    .into_iter()
+                    .map(|range| json!({ "uri": uri, "range": range }))
+                    .collect()
+            }
+            "textDocument/rename" => {
+                let new_name = params["newName"].as_str().unwrap_or_default();
+                let edits: Vec<Value> = word
+                    .map(|w| occurrences(&text, &w))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|range| json!({ "range": range, "newText": new_name }))
+                    .collect();
+                json!({ "changes": { uri: edits } })
+            }
+            // a fix of each TODO on the line, and a command
+            "textDocument/codeAction" => {
+                let mut actions = vec![];
+                for d in params["context"]["diagnostics"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                {
+                    if d["message"].as_str().is_some_and(|m| m.starts_with("TODO")) {
+                        actions.push(json!({
+                            "title": "Mark it DONE",
+                            "kind": "quickfix",
+                            "edit": { "changes": { uri: [{ "range": d["range"], "newText": "DONE" }] } },
+                        }));
+                    }
+                }
+                actions.push(json!({
+                    "title": "Count the lines",
+                    "command": "mock.count",
+                    "arguments": [uri],
+                }));
+                Value::Array(actions)
+            }
+            // the words of the document, and a call which inserts more than its label
+            "textDocument/completion" => {
+                let mut words: Vec<&str> = text
+                    .split(|c: char| !is_word(c))
+                    .filter(|w| w.len() > 2)
+                    .collect();
+                words.sort_unstable();
+                words.dedup();
+                let mut items: Vec<Value> = words
+                    .into_iter()
+                    .map(|w| json!({ "label": w, "kind": 1 }))
+                    .collect();
+                items.push(json!({ "label": "mock_call", "insertText": "mock_call()", "kind": 3 }));
+                json!({ "isIncomplete": false, "items": items })
+            }
+            "workspace/executeCommand" => {
+                let lines = text.split('\n').count();
+                send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "window/showMessage",
+                    "params": { "type": 3, "message": format!("{} lines", lines) },
+                }));
+                Value::Null
+            }
+            "shutdown" => Value::Null,
+            "exit" => return,
+            _ => Value::Null,
+        };
+        // only the requests are answered
+        if let Some(id) = msg.get("id") {
+            send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+        }
+    }
+}
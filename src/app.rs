@@ -2,10 +2,19 @@
 
 use crate::buffer::BufferManager;
 use crate::command::*;
+use crate::completion::Completion;
+use crate::diffview::DiffView;
 use crate::error::BufferError;
 use crate::error::*;
+use crate::event_loop::EventLoop;
+use crate::explorer::Explorer;
 use crate::layout::layout_manager::*;
+use crate::lsp::LspManager;
+use crate::make::Make;
 use crate::popup::Popups;
+use crate::quickfix::Quickfix;
+#[cfg(unix)]
+use crate::terminal::TerminalManager;
 
 #[derive(Debug)]
 pub enum Screen {
@@ -32,10 +41,27 @@ pub struct App {
     pub scroll_offset: (usize, usize),
     pub scroll_threshold: (usize, usize),
     pub should_exit: bool,
+    pub events: EventLoop,
+    // off during the dark half of a blink
+    pub cursor_shown: bool,
+    pub lsp: LspManager,
+    // the completion menu, open while typing in insert mode
+    pub completion: Option<Completion>,
+    pub quickfix: Quickfix,
+    // the command run with `make` or `run`, and its output
+    pub make: Make,
+    // the two panes compared side by side
+    pub diff: Option<DiffView>,
+    // the programs running in terminal panes
+    #[cfg(unix)]
+    pub terminals: TerminalManager,
+    // the tree of the working directory in the sidebar
+    pub explorer: Explorer,
 }
 
 impl App {
     pub fn new() -> Self {
+        let events = EventLoop::new();
         Self {
             popups: Popups::new(),
             buf_manager: BufferManager::new(),
@@ -47,10 +73,20 @@ impl App {
             scroll_threshold: (0, 0),
             cursor_pos: (0, 0),
             should_exit: false,
+            lsp: LspManager::new(events.sender()),
+            make: Make::new(events.sender()),
+            #[cfg(unix)]
+            terminals: TerminalManager::new(events.sender()),
+            explorer: Explorer::new(),
+            events,
+            cursor_shown: true,
+            completion: None,
+            quickfix: Quickfix::new(),
+            diff: None,
         }
     }
 
-    pub fn init(&mut self, path: Option<String>) -> Result<(), LayoutError> {
+    pub fn init(&mut self, path: Option<String>, read_only: bool) -> Result<(), LayoutError> {
         let id = if let Some(p) = path {
             match self.buf_manager.add_new_buffer_from_path(p) {
                 Err(e) => {
@@ -70,10 +106,19 @@ impl App {
 
         self.layout_manager.init(id);
         self.current_screen = Screen::Editor;
+
+        let buf = self.buf_manager.get_buffer_mut(id)?;
+        buf.read_only |= read_only;
+        if buf.has_newer_swap() {
+            let name = buf.name.clone();
+            self.command
+                .ask_recover(&mut self.current_screen, id, &name);
+        }
         Ok(())
     }
 
     pub fn from(screen: Screen, buf_manager: BufferManager) -> Self {
+        let events = EventLoop::new();
         Self {
             popups: Popups::new(),
             buf_manager,
@@ -85,6 +130,16 @@ impl App {
             scroll_threshold: (0, 0),
             cursor_pos: (0, 0),
             should_exit: false,
+            lsp: LspManager::new(events.sender()),
+            make: Make::new(events.sender()),
+            #[cfg(unix)]
+            terminals: TerminalManager::new(events.sender()),
+            explorer: Explorer::new(),
+            events,
+            cursor_shown: true,
+            completion: None,
+            quickfix: Quickfix::new(),
+            diff: None,
         }
     }
 }
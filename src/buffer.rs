@@ -1,15 +1,27 @@
 #![allow(dead_code)]
 use crate::command::op::*;
+use crate::diagnostic::Diagnostic;
+use crate::diff::Hunk;
+use crate::encoding::*;
 use crate::error::*;
+use crate::fileio;
+use crate::git::GitInfo;
+use crate::largefile::{self, LargeFile};
+use crate::linecache::{LineCache, LineLayout};
+use crate::lsp::protocol::{self, DocChange};
+use crate::options::BufferOptions;
+use crate::swap;
 use crate::utils::*;
 use color_eyre::Result;
+use encoding_rs::Encoding;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fs::{self, File, read_to_string};
-use std::io::{self, Write};
+use std::fs;
+use std::io;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use unicode_segmentation::UnicodeSegmentation;
-use unicode_width::UnicodeWidthStr;
+use std::time::Instant;
 
 pub struct Buffer {
     pub id: usize,
@@ -21,6 +33,41 @@ pub struct Buffer {
     pub path: Option<PathBuf>,
     pub file_info: Option<FileInfo>,
     pub saved: bool,
+    // edits are refused
+    pub read_only: bool,
+    pub options: BufferOptions,
+    // edits which haven't been written into the swap file yet
+    pub swap_pending: bool,
+    pub swap_written_at: Instant,
+    // the swap file was written or recovered by this buffer, one found on
+    // opening is left alone until the user decides about it
+    pub swap_owned: bool,
+    // a newer version on the disk the user already knows about
+    pub disk_conflict: Option<DiskState>,
+    // set in the large file mode, the unloaded lines of `content` are empty
+    pub large: Option<LargeFile>,
+    // a file over the large file size read as a whole, it isn't UTF-8
+    pub large_refused: bool,
+    // filled while drawing, which only borrows the buffer
+    pub line_cache: RefCell<LineCache>,
+    // the problems found in the content, by a language server for example
+    pub diagnostics: Vec<Diagnostic>,
+    // the edits since the language server last heard of the buffer
+    pub changes: Vec<DocChange>,
+    // counts the changes of the content, what a command made of an older
+    // one is dropped
+    pub version: u64,
+    // what the format run by the last save did, the panes move their
+    // cursors with the lines and a failed one is told
+    pub format_result: Option<Result<Vec<Hunk>, String>>,
+    // the repository of the file and the lines changed since its HEAD
+    pub git: Option<GitInfo>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DiskChange {
+    Changed(DiskState),
+    Deleted,
 }
 
 impl Buffer {
@@ -35,8 +82,23 @@ impl Buffer {
             path: None,
             file_info: Some(FileInfo::new()),
             saved: true,
+            read_only: false,
+            options: BufferOptions::new(),
+            swap_pending: false,
+            swap_written_at: Instant::now(),
+            swap_owned: false,
+            disk_conflict: None,
+            large: None,
+            large_refused: false,
+            line_cache: RefCell::new(LineCache::new()),
+            diagnostics: vec![],
+            changes: vec![],
+            version: 0,
+            format_result: None,
+            git: None,
         }
     }
+    #[allow(clippy::redundant_closure)]
     pub fn from_content(content: &str, name: &str, id: usize) -> Self {
         let c: Vec<String> = content.split('\n').map(String::from).collect();
         let v: Vec<Arc<str>> = c.clone().into_iter().map(|s| Arc::<str>::from(s)).collect();
@@ -50,26 +112,35 @@ impl Buffer {
             path: None,
             file_info: Some(FileInfo::new()),
             saved: true,
+            read_only: false,
+            options: BufferOptions::new(),
+            swap_pending: false,
+            swap_written_at: Instant::now(),
+            swap_owned: false,
+            disk_conflict: None,
+            large: None,
+            large_refused: false,
+            line_cache: RefCell::new(LineCache::new()),
+            diagnostics: vec![],
+            changes: vec![],
+            version: 0,
+            format_result: None,
+            git: None,
         }
     }
+    #[allow(clippy::redundant_closure)]
     pub fn from_file<P: AsRef<Path>>(path: P, id: usize) -> Result<Self, BufferError> {
         let path_ref = path.as_ref();
         if !path_ref.is_file() {
             return Err(BufferError::NotAFile);
         }
-        let content_str = read_to_string(path_ref)?;
-
-        let mut content: Vec<String> = content_str.lines().map(|s| s.to_string()).collect();
+        let (content, info) = Self::read_file(path_ref, None)?;
         let v: Vec<Arc<str>> = content
             .clone()
             .into_iter()
             .map(|s| Arc::<str>::from(s))
             .collect();
 
-        if content.is_empty() {
-            content.push(String::new());
-        }
-
         let name = path_ref
             .file_name()
             .and_then(|n| n.to_str())
@@ -83,12 +154,172 @@ impl Buffer {
             history_ptr: 0,
             name: Arc::from(name),
             path: Some(path_ref.to_path_buf()),
-            file_info: None,
+            file_info: Some(info),
             saved: true,
+            read_only: false,
+            options: BufferOptions::new(),
+            swap_pending: false,
+            swap_written_at: Instant::now(),
+            swap_owned: false,
+            disk_conflict: None,
+            large: None,
+            large_refused: false,
+            line_cache: RefCell::new(LineCache::new()),
+            diagnostics: vec![],
+            changes: vec![],
+            version: 0,
+            format_result: None,
+            git: None,
         };
         s.refresh_file_info().unwrap();
+        // files we can't write are opened read-only
+        s.read_only = s.file_info.as_ref().is_some_and(|i| i.read_only);
         Ok(s)
     }
+    // open a file in the large file mode, the lines are indexed in the background
+    pub fn from_large_file<P: AsRef<Path>>(path: P, id: usize) -> Result<Self, BufferError> {
+        let path_ref = path.as_ref();
+        if !path_ref.is_file() {
+            return Err(BufferError::NotAFile);
+        }
+        let large = LargeFile::open(path_ref)?;
+
+        let name = path_ref
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("Untitled");
+        let mut s = Self::new(name, id);
+        s.path = Some(path_ref.to_path_buf());
+
+        let mut info = FileInfo::new();
+        info.bom = large.has_bom();
+        info.format = if large.crlf {
+            FileFormat::DOS
+        } else {
+            FileFormat::UNIX
+        };
+        info.size = large.total;
+        info.disk = DiskState::from_path(path_ref);
+        s.file_info = Some(info);
+        s.large = Some(large);
+
+        s.refresh_file_info().unwrap();
+        s.read_only = s.file_info.as_ref().is_some_and(|i| i.read_only);
+        Ok(s)
+    }
+
+    pub fn is_large(&self) -> bool {
+        self.large.is_some()
+    }
+
+    // take the lines indexed in the background, returns whether there were any
+    pub fn poll_large(&mut self) -> Result<bool, BufferError> {
+        let Some(large) = &mut self.large else {
+            return Ok(false);
+        };
+        let indexed = large.indexed;
+        let (offsets, done) = large.poll().map_err(io::Error::other)?;
+        for offset in offsets {
+            large.offsets.push(offset);
+            large.loaded.push(false);
+            self.content.push(String::new());
+        }
+
+        if done {
+            // the line after the last line ending is empty
+            let final_newline = self.content.len() > 1
+                && large.offsets.last() == Some(&large.total)
+                && large.loaded.last() == Some(&false);
+            if final_newline {
+                large.offsets.pop();
+                large.loaded.pop();
+                self.content.pop();
+            }
+            if let Some(info) = &mut self.file_info {
+                info.final_newline = final_newline;
+            }
+        }
+        Ok(done || large.indexed != indexed)
+    }
+
+    // read the lines in `range` which aren't in memory yet, returns whether
+    // there were any
+    pub fn load_lines(&mut self, range: Range<usize>) -> Result<bool, BufferError> {
+        let Some(large) = &mut self.large else {
+            return Ok(false);
+        };
+        let mut loaded = false;
+        let end = range.end.min(self.content.len());
+        for y in range.start.min(end)..end {
+            if !large.loaded[y] {
+                self.content[y] = large.read_line_at(large.offsets[y])?;
+                large.loaded[y] = true;
+                self.line_cache.get_mut().invalidate(y);
+                loaded = true;
+            }
+        }
+        Ok(loaded)
+    }
+
+    // keep the line index of the large file mode in step with `content`
+    fn large_remove_lines(&mut self, range: Range<usize>) {
+        if let Some(large) = &mut self.large {
+            large.offsets.drain(range.clone());
+            large.loaded.drain(range);
+        }
+    }
+
+    fn large_insert_lines(&mut self, at: usize, count: usize) {
+        if let Some(large) = &mut self.large {
+            for _ in 0..count {
+                large.offsets.insert(at, 0);
+                large.loaded.insert(at, true);
+            }
+        }
+    }
+
+    // read and decode a file, the encoding is detected if it isn't given
+    fn read_file(
+        path: &Path,
+        enc: Option<&'static Encoding>,
+    ) -> Result<(Vec<String>, FileInfo), BufferError> {
+        let bytes = fs::read(path)?;
+        let enc = enc.unwrap_or_else(|| detect_encoding(&bytes));
+        let (content, mut info) = FileInfo::parse(&decode(&bytes, enc));
+        info.encoding = enc;
+        info.size = bytes.len() as u64;
+        info.disk = DiskState::new(path, &bytes);
+        Ok((content, info))
+    }
+
+    // read the file again, decoding it with the given encoding
+    pub fn reopen_with_encoding(&mut self, enc: &'static Encoding) -> Result<(), BufferError> {
+        let path = self.path.clone().ok_or(BufferError::InvalidPath)?;
+        if self.is_large() {
+            // large files are always read as UTF-8
+            if enc != encoding_rs::UTF_8 {
+                return Err(BufferError::InvalidEncoding(enc.name().to_string()));
+            }
+            let mut reopened = Self::from_large_file(&path, self.id)?;
+            reopened.options = self.options.clone();
+            reopened.version = self.version + 1;
+            *self = reopened;
+            return Ok(());
+        }
+        let (content, info) = Self::read_file(&path, Some(enc))?;
+        self.content = content;
+        self.line_cache.get_mut().clear();
+        self.changes.push(DocChange::Full);
+        self.version += 1;
+        self.file_info = Some(info);
+        self.op_stack.clear();
+        self.saved = true;
+        self.disk_conflict = None;
+        self.refresh_file_info()
+            .map_err(|_| BufferError::InvalidPath)?;
+        Ok(())
+    }
+
     pub fn change_name(&mut self, name: &str) {
         self.name = Arc::from(name);
         self.path = Some(format!("./{}", name).into());
@@ -122,7 +353,8 @@ impl Buffer {
         start_pos: (usize, usize),
         end_pos: (usize, usize),
     ) -> Result<String, BufferError> {
-        let ((sx, sy), (ex, ey)) = if start_pos <= end_pos {
+        // the positions are (x, y), the line decides first
+        let ((sx, sy), (ex, ey)) = if (start_pos.1, start_pos.0) <= (end_pos.1, end_pos.0) {
             (start_pos, end_pos)
         } else {
             (end_pos, start_pos)
@@ -131,6 +363,8 @@ impl Buffer {
         if sy >= self.content.len() || ey >= self.content.len() {
             return Err(BufferError::InvalidPosition);
         }
+        // the deleted text is kept for undo
+        self.load_lines(sy..(ey + 1))?;
 
         let mut deleted = String::new();
 
@@ -163,6 +397,7 @@ impl Buffer {
         let remaining = last_line[end_byte..].to_string();
 
         self.content.drain((sy + 1)..=ey);
+        self.large_remove_lines((sy + 1)..(ey + 1));
 
         self.content[sy].push_str(&remaining);
 
@@ -205,6 +440,7 @@ impl Buffer {
 
         let last_line = format!("{}{}", lines.last().unwrap(), tail);
         self.content.insert(insert_index, last_line);
+        self.large_insert_lines(y + 1, lines.len() - 1);
 
         Ok(())
     }
@@ -248,9 +484,12 @@ impl Buffer {
             println!("[Warning]: Change content at a invalid position.");
             return Err(BufferError::InvalidPosition);
         }
+        self.load_lines(y..(y + 1))?;
         let rm_str = self.content.remove(y);
-        if self.content.len() <= 0 {
+        self.large_remove_lines(y..(y + 1));
+        if self.content.is_empty() {
             self.content.push(String::new());
+            self.large_insert_lines(0, 1);
         };
         Ok(rm_str)
     }
@@ -260,12 +499,19 @@ impl Buffer {
             println!("[Warning]: Change content at a invalid position.");
             return Err(BufferError::InvalidPosition);
         }
-        Ok(self.content.insert(y, String::from(str)))
+        self.content.insert(y, String::from(str));
+        self.large_insert_lines(y, 1);
+        Ok(())
     }
 
     pub fn save(&mut self) -> io::Result<()> {
-        if let Some(ref path) = self.path {
-            self.save_to(path.clone())
+        if let Some(path) = self.path.clone() {
+            // the file is written even if the formatter failed
+            self.format_result = None;
+            if self.options.format_on_save && !self.read_only && self.formatter().is_some() {
+                self.format_result = Some(self.format());
+            }
+            self.save_to(path)
         } else {
             Err(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -275,16 +521,102 @@ impl Buffer {
     }
 
     pub fn save_to<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "The buffer is read-only, use `set noreadonly`",
+            ));
+        }
         let path_ref = path.as_ref();
-        let mut file = File::create(path_ref)?;
+        if self.is_large() {
+            return self.save_large_to(path_ref);
+        }
+        let data = self.encode_content()?;
+
+        fileio::backup(
+            path_ref,
+            self.options.backup,
+            self.options.backup_dir.as_deref(),
+        )?;
+        fileio::atomic_write(path_ref, &data)?;
+
+        self.finish_save(path_ref, DiskState::new(path_ref, &data));
+        Ok(())
+    }
+
+    // stream the lines into the file, the unloaded ones are copied from the
+    // file which was opened
+    fn save_large_to(&mut self, path_ref: &Path) -> io::Result<()> {
+        let Self {
+            content,
+            large,
+            file_info,
+            options,
+            ..
+        } = self;
+        let (Some(large), Some(info)) = (large.as_mut(), file_info.as_ref()) else {
+            return Ok(());
+        };
+        if large.is_indexing() {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "The file is still being loaded",
+            ));
+        }
 
-        for (i, line) in self.content.iter().enumerate() {
-            file.write_all(line.as_bytes())?;
-            if i < self.content.len() - 1 {
-                file.write_all(b"\n")?;
+        fileio::backup(path_ref, options.backup, options.backup_dir.as_deref())?;
+        fileio::atomic_write_with(path_ref, |w| {
+            if info.bom {
+                w.write_all(&encode("\u{feff}", info.encoding)?)?;
             }
+            let ending = encode(info.format.line_ending(), info.encoding)?;
+            for (y, line) in content.iter().enumerate() {
+                if y > 0 {
+                    w.write_all(&ending)?;
+                }
+                if large.loaded[y] {
+                    w.write_all(&encode(line, info.encoding)?)?;
+                } else {
+                    let line = large.read_line_at(large.offsets[y])?;
+                    w.write_all(&encode(&line, info.encoding)?)?;
+                }
+            }
+            if info.final_newline {
+                w.write_all(&ending)?;
+            }
+            Ok(())
+        })?;
+
+        self.finish_save(path_ref, DiskState::from_path(path_ref));
+        Ok(())
+    }
+
+    // write the file through a privileged helper like `sudo tee`
+    pub fn save_with_helper(&mut self) -> io::Result<()> {
+        let path = self.path.clone().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "No file path bound to this buffer")
+        })?;
+        if self.is_large() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Large files can't be saved with the write helper",
+            ));
         }
+        let data = self.encode_content()?;
+        fileio::write_with_helper(&path, &data, &self.options.write_helper)?;
+        self.finish_save(&path, DiskState::new(&path, &data));
+        Ok(())
+    }
+
+    fn encode_content(&self) -> io::Result<Vec<u8>> {
+        match &self.file_info {
+            Some(info) => encode(&info.join(&self.content), info.encoding),
+            None => Ok(self.content.join("\n").into_bytes()),
+        }
+    }
 
+    #[allow(clippy::redundant_closure)]
+    fn finish_save(&mut self, path_ref: &Path, disk: Option<DiskState>) {
         if self.path.is_none() {
             self.path = Some(path_ref.to_path_buf());
             if let Some(name) = path_ref.file_name().and_then(|n| n.to_str()) {
@@ -294,7 +626,15 @@ impl Buffer {
         if let Err(e) = self.refresh_file_info() {
             println!("error: {:?}", e);
         };
+        if let Some(info) = &mut self.file_info {
+            info.disk = disk;
+        }
+        self.disk_conflict = None;
         self.saved = true;
+        self.remove_swap();
+        if self.is_large() {
+            return;
+        }
         self.history.truncate(self.history_ptr + 1);
         let v: Vec<Arc<str>> = self
             .content
@@ -304,7 +644,6 @@ impl Buffer {
             .collect();
         self.history.push(v);
         self.history_ptr += 1;
-        Ok(())
     }
 
     // change saved to false and add new content to history
@@ -313,13 +652,20 @@ impl Buffer {
         self.op_stack.push(op);
     }
 
+    pub fn line_layout(&self, line_idx: usize) -> Arc<LineLayout> {
+        self.line_cache.borrow_mut().get(
+            line_idx,
+            &self.content[line_idx],
+            self.options.tab_width,
+        )
+    }
+
     pub fn get_visual_width_upto(&self, line_idx: usize, char_idx: usize) -> usize {
-        let line = &self.content[line_idx];
-        line.graphemes(true).take(char_idx).map(|g| g.width()).sum()
+        self.line_layout(line_idx).width_upto(char_idx)
     }
 
     pub fn get_line_visual_width(&self, line_idx: usize) -> usize {
-        self.content[line_idx].width()
+        self.line_layout(line_idx).width
     }
 
     pub fn refresh_file_info(&mut self) -> Result<()> {
@@ -333,17 +679,137 @@ impl Buffer {
 
         let metadata = fs::metadata(path_str)?;
 
-        let info = FileInfo {
-            size: metadata.len(),
-            read_only: metadata.permissions().readonly(),
-            format: detect_line_ending(&self.content.join("")),
+        // the format is kept as it was read, the disk doesn't know it
+        let info = self.file_info.get_or_insert_with(FileInfo::new);
+        info.size = metadata.len();
+        info.read_only = !fileio::is_writable(Path::new(path_str));
+        Ok(())
+    }
+
+    // look at the file on the disk, and report changes made by other programs
+    pub fn check_disk(&mut self) -> Option<DiskChange> {
+        let path = self.path.as_ref()?;
+        let info = self.file_info.as_mut()?;
+        let known = info.disk?;
+
+        let meta = match fs::metadata(path) {
+            Ok(m) => m,
+            Err(_) => {
+                // report it once, saving creates the file again
+                info.disk = None;
+                return Some(DiskChange::Deleted);
+            }
         };
+        if known.same_metadata(&meta) {
+            return None;
+        }
+        if let Some(conflict) = self.disk_conflict
+            && conflict.same_metadata(&meta)
+        {
+            return None;
+        }
+        // large files aren't hashed, the metadata has to do
+        if self.large.is_some() {
+            return DiskState::from_path(path).map(DiskChange::Changed);
+        }
 
-        self.file_info = Some(info);
-        Ok(())
+        let bytes = fs::read(path).ok()?;
+        let state = DiskState::new(path, &bytes)?;
+        if state.hash == known.hash {
+            // only touched
+            info.disk = Some(state);
+            return None;
+        }
+        Some(DiskChange::Changed(state))
+    }
+
+    // whether the file was changed by others since it was read or written
+    pub fn is_disk_newer(&self) -> bool {
+        let (Some(path), Some(known)) = (&self.path, self.file_info.as_ref().and_then(|i| i.disk))
+        else {
+            return false;
+        };
+        match fs::metadata(path) {
+            Ok(meta) if !known.same_metadata(&meta) && self.is_large() => true,
+            Ok(meta) if !known.same_metadata(&meta) => fs::read(path)
+                .map(|bytes| hash_bytes(&bytes) != known.hash)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    pub fn read_disk_content(&self) -> Result<Vec<String>, BufferError> {
+        let path = self.path.as_ref().ok_or(BufferError::InvalidPath)?;
+        let enc = self.file_info.as_ref().map(|i| i.encoding);
+        Ok(Self::read_file(path, enc)?.0)
+    }
+
+    // show `lines` in a read-only buffer which isn't bound to a file, like
+    // a list or the output of a command, it can't be undone
+    pub fn set_scratch_content(&mut self, lines: Vec<String>) {
+        self.content = if lines.is_empty() {
+            vec![String::new()]
+        } else {
+            lines
+        };
+        self.line_cache.get_mut().clear();
+        self.version += 1;
+        self.op_stack.clear();
+        self.read_only = true;
+        self.saved = true;
+    }
+
+    // reload the file with the encoding it was read
+    pub fn reload(&mut self) -> Result<(), BufferError> {
+        let enc = match &self.file_info {
+            Some(info) => info.encoding,
+            None => encoding_rs::UTF_8,
+        };
+        self.reopen_with_encoding(enc)
+    }
+
+    // options of this buffer only, returns false if `expr` isn't one of them
+    pub fn set_local_option(&mut self, expr: &str) -> Result<bool, OptionError> {
+        match expr.trim() {
+            "readonly" | "ro" => {
+                self.read_only = true;
+                return Ok(true);
+            }
+            "noreadonly" | "noro" => {
+                self.read_only = false;
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        let info = self.file_info.get_or_insert_with(FileInfo::new);
+        match expr.trim() {
+            "fileformat=unix" | "ff=unix" => info.format = FileFormat::UNIX,
+            "fileformat=dos" | "ff=dos" => info.format = FileFormat::DOS,
+            "bomb" => info.bom = true,
+            "nobomb" => info.bom = false,
+            "eol" => info.final_newline = true,
+            "noeol" => info.final_newline = false,
+            s if s.starts_with("fileencoding=") || s.starts_with("fenc=") => {
+                let v = s.split_once('=').map(|(_, v)| v).unwrap_or("");
+                info.encoding =
+                    encoding_for_label(v).ok_or(OptionError::InvalidValue(v.to_string()))?;
+            }
+            s if s.starts_with("fileformat=") || s.starts_with("ff=") => {
+                let v = s.split_once('=').map(|(_, v)| v).unwrap_or("");
+                return Err(OptionError::InvalidValue(v.to_string()));
+            }
+            _ => return Ok(false),
+        }
+        // the file on the disk differs from the buffer now
+        self.saved = false;
+        Ok(true)
     }
 
     pub fn revoke(&mut self) -> Result<(), LayoutError> {
+        if self.read_only {
+            return Err(BufferError::ReadOnly.into());
+        }
         let op = if let Some(o) = self.op_stack.pop() {
             o
         } else {
@@ -354,7 +820,25 @@ impl Buffer {
     }
 
     pub fn apply_op(&mut self, op: EditOp, add: bool) -> Result<(), BufferError> {
-        let new_op = match op.clone() {
+        if self.read_only {
+            return Err(BufferError::ReadOnly);
+        }
+        let new_op = self.apply_op_inner(op)?;
+        if add {
+            self.handle_change(new_op);
+        }
+        self.swap_pending = true;
+        Ok(())
+    }
+
+    // returns the op with the text it removed, so it can be undone
+    fn apply_op_inner(&mut self, op: EditOp) -> Result<EditOp, BufferError> {
+        // the language servers don't see large files
+        let change = match self.large {
+            None => protocol::doc_change(&self.content, &op),
+            Some(_) => None,
+        };
+        let new_op = match op {
             EditOp::Insert { pos, text, len } => {
                 self.add_content_at(&text, pos)?;
                 EditOp::Insert {pos, text, len}
@@ -373,17 +857,94 @@ impl Buffer {
             }
             EditOp::DeleteBlock { start_pos, end_pos, .. } => {
                 let text = self.delete_content_block(start_pos, end_pos)?;
-                EditOp::DeleteBlock { start_pos, end_pos, text: text.into() }
+                EditOp::DeleteBlock { start_pos, end_pos, text }
             }
             EditOp::InsertBlock { start_pos, text, end_pos } => {
                 self.insert_content_block(start_pos, &text)?;
                 EditOp::InsertBlock { start_pos, end_pos, text }
             }
-
+            EditOp::Group(ops) => {
+                let mut done = Vec::with_capacity(ops.len());
+                for op in ops {
+                    match self.apply_op_inner(op) {
+                        Ok(op) => done.push(op),
+                        Err(e) => {
+                            // a group is applied whole or not at all
+                            let _ = self.apply_op_inner(EditOp::Group(done).inverse());
+                            return Err(e);
+                        }
+                    }
+                }
+                return Ok(EditOp::Group(done));
+            }
         };
-        if add {
-            self.handle_change(new_op);
+        self.line_cache.get_mut().apply(&new_op);
+        self.shift_diagnostics(&new_op);
+        self.changes.extend(change);
+        self.version += 1;
+        Ok(new_op)
+    }
+
+    pub fn swap_path(&self) -> Option<PathBuf> {
+        swap::swap_path_for(self.path.as_ref()?)
+    }
+
+    // write the current content into the swap file
+    pub fn write_swap(&mut self) -> io::Result<()> {
+        if let (Some(path), Some(swap_path)) = (&self.path, self.swap_path()) {
+            swap::write_swap(&swap_path, path, &self.content)?;
+            self.swap_owned = true;
+        }
+        self.swap_pending = false;
+        self.swap_written_at = Instant::now();
+        Ok(())
+    }
+
+    // remove the swap file, unless it's of another instance or the user
+    // hasn't decided about it yet
+    pub fn remove_swap(&mut self) {
+        if self.swap_owned {
+            self.discard_swap();
+        }
+        self.swap_pending = false;
+    }
+
+    // remove the swap file whoever wrote it
+    pub fn discard_swap(&mut self) {
+        if let Some(swap_path) = self.swap_path() {
+            let _ = swap::remove_swap(&swap_path);
+        }
+        self.swap_owned = false;
+        self.swap_pending = false;
+    }
+
+    pub fn has_newer_swap(&self) -> bool {
+        match (&self.path, self.swap_path()) {
+            (Some(path), Some(swap_path)) => swap::is_swap_newer(&swap_path, path),
+            _ => false,
         }
+    }
+
+    pub fn read_swap(&self) -> Result<Vec<String>, BufferError> {
+        let swap_path = self.swap_path().ok_or(BufferError::InvalidPath)?;
+        Ok(swap::read_swap(&swap_path)?)
+    }
+
+    // replace the content with the one in the swap file, the buffer becomes unsaved
+    pub fn recover_from_swap(&mut self) -> Result<(), BufferError> {
+        self.content = self.read_swap()?;
+        self.line_cache.get_mut().clear();
+        self.changes.push(DocChange::Full);
+        self.version += 1;
+        // the whole content is in memory now
+        self.large = None;
+        // the edits before were of another content
+        self.op_stack.clear();
+        self.history = vec![self.content.iter().map(|l| Arc::from(l.as_str())).collect()];
+        self.history_ptr = 0;
+        self.saved = false;
+        self.swap_pending = true;
+        self.swap_owned = true;
         Ok(())
     }
 }
@@ -391,6 +952,8 @@ impl Buffer {
 pub struct BufferManager {
     pub id_counter: usize,
     pub buffers: HashMap<usize, Buffer>,
+    // options for the new buffers
+    pub options: BufferOptions,
 }
 
 impl BufferManager {
@@ -398,12 +961,23 @@ impl BufferManager {
         Self {
             id_counter: 1,
             buffers: HashMap::new(),
+            options: BufferOptions::new(),
         }
     }
 
     pub fn add_new_buffer(&mut self, name: &str) -> usize {
         let old_id = self.id_counter;
-        let new_buffer = Buffer::new(name, old_id);
+        let mut new_buffer = Buffer::new(name, old_id);
+        new_buffer.options = self.options.clone();
+        self.buffers.insert(old_id, new_buffer);
+        self.id_counter += 1;
+        old_id
+    }
+
+    pub fn add_new_buffer_from_content(&mut self, content: &str, name: &str) -> usize {
+        let old_id = self.id_counter;
+        let mut new_buffer = Buffer::from_content(content, name, old_id);
+        new_buffer.options = self.options.clone();
         self.buffers.insert(old_id, new_buffer);
         self.id_counter += 1;
         old_id
@@ -414,12 +988,38 @@ impl BufferManager {
         path: P,
     ) -> Result<usize, BufferError> {
         let old_id = self.id_counter;
-        let new_buffer = Buffer::from_file(path, old_id)?;
+        let size = fs::metadata(path.as_ref()).map(|m| m.len()).unwrap_or(0);
+        let large = self.options.large_file.is_some_and(|threshold| size >= threshold);
+        let mut new_buffer = match large {
+            true if largefile::is_utf8(path.as_ref())? => Buffer::from_large_file(path, old_id)?,
+            true => {
+                let mut buf = Buffer::from_file(path, old_id)?;
+                buf.large_refused = true;
+                buf
+            }
+            false => Buffer::from_file(path, old_id)?,
+        };
+        new_buffer.options = self.options.clone();
         self.buffers.insert(old_id, new_buffer);
         self.id_counter += 1;
         Ok(old_id)
     }
 
+    // the buffer of the file at `path`, opened if there's none yet
+    pub fn find_or_open<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, BufferError> {
+        let target = fs::canonicalize(path.as_ref())?;
+        let found = self.buffers.values().find(|buf| {
+            buf.path
+                .as_ref()
+                .and_then(|p| fs::canonicalize(p).ok())
+                .is_some_and(|p| p == target)
+        });
+        match found {
+            Some(buf) => Ok(buf.id),
+            None => self.add_new_buffer_from_path(path),
+        }
+    }
+
     pub fn get_buffer(&self, id: usize) -> Result<&Buffer, BufferError> {
         self.buffers.get(&id).ok_or(BufferError::InvalidId)
     }
@@ -427,4 +1027,57 @@ impl BufferManager {
     pub fn get_buffer_mut(&mut self, id: usize) -> Result<&mut Buffer, BufferError> {
         self.buffers.get_mut(&id).ok_or(BufferError::InvalidId)
     }
+
+    // write the modified buffers into their swap files once in a while
+    pub fn write_swaps(&mut self) {
+        for buf in self.buffers.values_mut() {
+            // large files would be copied as a whole every time
+            if buf.swap_pending
+                && !buf.saved
+                && !buf.is_large()
+                && buf.swap_written_at.elapsed() >= swap::SWAP_INTERVAL
+            {
+                let _ = buf.write_swap();
+            }
+        }
+    }
+
+    // the buffers of `path` which differ from the file on the disk now
+    pub fn check_disk_changes(&mut self, path: &Path) -> Vec<(usize, DiskChange)> {
+        self.buffers
+            .values_mut()
+            .filter(|buf| buf.path.as_deref() == Some(path))
+            .filter_map(|buf| buf.check_disk().map(|c| (buf.id, c)))
+            .collect()
+    }
+
+    // the files the buffers are bound to, for the file watcher
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.buffers
+            .values()
+            .filter_map(|buf| buf.path.clone())
+            .collect()
+    }
+
+    // take the lines indexed in the background, and read the lines in `ranges`,
+    // returns whether any buffer changed
+    pub fn poll_large_files(&mut self, ranges: &[(usize, Range<usize>)]) -> bool {
+        let mut changed = false;
+        for buf in self.buffers.values_mut().filter(|b| b.is_large()) {
+            changed |= buf.poll_large().unwrap_or(false);
+        }
+        for (id, range) in ranges {
+            if let Some(buf) = self.buffers.get_mut(id) {
+                changed |= buf.load_lines(range.clone()).unwrap_or(false);
+            }
+        }
+        changed
+    }
+
+    // the swaps of the other instances and the ones not recovered stay
+    pub fn remove_swaps(&mut self) {
+        for buf in self.buffers.values_mut() {
+            buf.remove_swap();
+        }
+    }
 }
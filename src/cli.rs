@@ -3,5 +3,8 @@ use clap::Parser;
 #[derive(Parser, Debug)]
 pub struct Args {
     pub path: Option<String>,
+    /// open the file read-only
+    #[arg(short = 'R', long)]
+    pub readonly: bool,
 }
 
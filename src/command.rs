@@ -1,14 +1,29 @@
 #![allow(dead_code)]
 use crate::app::Mod;
 use crate::app::Screen;
-use crate::buffer::BufferManager;
+use crate::buffer::{BufferManager, DiskChange};
+use crate::diffview::DiffView;
+use crate::encoding::encoding_for_label;
 use crate::error::*;
+use crate::event_loop::{EventLoop, JobOutput};
+use crate::explorer::{EXPLORER_RATIO, Explorer};
 use crate::layout::layout_manager::*;
 use crate::layout::tree::*;
+use crate::git::hunk_line;
+use crate::lines::{LineEdit, SortOptions};
+use crate::lsp::LspManager;
+use crate::make::Make;
 use crate::op::EditOp;
 use crate::popup::*;
+use crate::quickfix::Quickfix;
+use crate::shell::{self, output_lines};
+#[cfg(unix)]
+use crate::terminal::TerminalManager;
 use crate::utils::*;
 use ratatui::style::Color;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use unicode_segmentation::UnicodeSegmentation;
@@ -31,6 +46,17 @@ pub enum KaoMoJi {
 pub enum ExCmd {
     AskAndSave,
     AskAndQuit,
+    // recover the buffer of the id from its swap file
+    RecoverSwap(usize),
+    // the file of the buffer was changed by another program
+    DiskChanged(usize),
+    // save the buffer over a newer file on the disk
+    Overwrite(usize),
+    // the files of the explorer, the path is its target
+    NewFile,
+    RenameFile,
+    MoveFile,
+    DeleteFile,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -41,6 +67,25 @@ pub enum CmdStatus {
     Failed,
 }
 
+// a shell command running in the background for a buffer, its output is
+// dropped if the buffer was changed meanwhile
+#[derive(Debug, Clone)]
+pub struct ShellJob {
+    pub command: String,
+    pub buffer_id: usize,
+    pub version: u64,
+    pub kind: JobKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum JobKind {
+    // the lines go through the command
+    Filter(Range<usize>),
+    // the output goes at `pos`, the cursor of pane `pane` after it
+    Insert { pane: usize, pos: (usize, usize) },
+    Format,
+}
+
 // KaoCo is the name of the command bar in smile
 pub struct KaoCo {
     // kaomoji of KaoCo
@@ -50,6 +95,11 @@ pub struct KaoCo {
     pub cursor_pos: (usize, usize),
     pub scroll_offset: (usize, usize),
     pub status: CmdStatus,
+    // the visual anchor when opened from the visual mode, the line commands
+    // work on the selected lines
+    pub selection: Option<(usize, usize)>,
+    // the commands running in the background, by their job
+    pub jobs: HashMap<usize, ShellJob>,
 }
 
 impl KaoCo {
@@ -61,17 +111,21 @@ impl KaoCo {
             cursor_pos: (0, 0),
             scroll_offset: (0, 0),
             status: CmdStatus::Normal,
+            selection: None,
+            jobs: HashMap::new(),
         }
     }
 
     pub fn clean(&mut self) {
         self.content = String::new();
+        self.selection = None;
         self.cursor_pos = (0, 0);
         self.scroll_offset = (0, 0);
     }
 
     pub fn clean_all(&mut self) {
         self.content = String::new();
+        self.selection = None;
         self.say = "".into();
         self.cursor_pos = (0, 0);
         self.scroll_offset = (0, 0);
@@ -115,7 +169,8 @@ impl KaoCo {
         }
 
         let byte_idx = char_to_byte_idx(&self.content, x);
-        Ok(self.content.insert_str(byte_idx, add_str))
+        self.content.insert_str(byte_idx, add_str);
+        Ok(())
     }
 
     pub fn mv_cursor_right(&mut self) {
@@ -163,22 +218,53 @@ impl KaoCo {
         line.graphemes(true).take(char_idx).map(|g| g.width()).sum()
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn handle_command(
         &mut self,
         buf_m: &mut BufferManager,
         lm: &mut LayoutManager,
         cur_screen: &mut Screen,
         popups: &mut Popups,
+        lsp: &mut LspManager,
+        qf: &mut Quickfix,
+        make: &mut Make,
+        events: &mut EventLoop,
+        diff: &mut Option<DiffView>,
+        explorer: &mut Explorer,
         quit: &mut bool,
     ) -> Result<bool, LayoutError> {
-        let buf = lm.get_current_buffer_mut(buf_m)?;
+        // the questions of the explorer are about its files, not a buffer
+        if let CmdStatus::Exec(
+            ask @ (ExCmd::NewFile | ExCmd::RenameFile | ExCmd::MoveFile | ExCmd::DeleteFile),
+        ) = self.status
+        {
+            return self.answer_explorer(ask, buf_m, explorer);
+        }
+        let buf = match (lm.current_content(), self.status) {
+            // the questions about a buffer by its id are answered anywhere
+            (
+                Some(_),
+                CmdStatus::Exec(
+                    ExCmd::RecoverSwap(id) | ExCmd::DiskChanged(id) | ExCmd::Overwrite(id),
+                ),
+            ) => buf_m.get_buffer_mut(id)?,
+            (Some(_), _) => {
+                return self.handle_content_command(buf_m, lm, explorer, cur_screen, quit);
+            }
+            (None, _) => lm.get_current_buffer_mut(buf_m)?,
+        };
         match self.status {
             CmdStatus::Exec(cmd) => match cmd {
                 ExCmd::AskAndSave => {
-                    buf.change_name(&self.content.trim());
-                    println!("{}", buf.name);
-                    buf.save()?;
+                    buf.change_name(self.content.trim());
+                    if let Err(e) = buf.save() {
+                        self.say = format!("Failed to save: {}", e).into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
                     self.say = "".into();
+                    let id = buf.id;
+                    self.report_format(buf_m, lm, id)?;
                 }
                 ExCmd::AskAndQuit => {
                     if self.content.trim() == "y" {
@@ -186,28 +272,468 @@ impl KaoCo {
                     }
                     self.say = "".into();
                 }
+                ExCmd::RecoverSwap(id) => match self.content.trim() {
+                    "r" => {
+                        if let Err(e) = buf_m.get_buffer_mut(id)?.recover_from_swap() {
+                            self.say = format!("Failed to recover: {}", e).into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                        self.say = "".into();
+                    }
+                    "d" => {
+                        // compare the swap content with the file beside it and
+                        // keep asking
+                        let swap_buf = buf_m.get_buffer(id)?;
+                        if swap_buf.is_large() {
+                            self.say =
+                                "Large files aren't compared, recover or discard it?(r or x)"
+                                    .into();
+                            return Ok(false);
+                        }
+                        let content = match swap_buf.read_swap() {
+                            Ok(content) => content,
+                            Err(e) => {
+                                self.say = format!("Failed to read the swap file: {}", e).into();
+                                self.status = CmdStatus::Failed;
+                                return Ok(false);
+                            }
+                        };
+                        let name = format!("[swap] {}", swap_buf.name);
+                        diff_beside(buf_m, lm, diff, id, content, &name)?;
+                        return Ok(false);
+                    }
+                    "x" => {
+                        buf_m.get_buffer_mut(id)?.discard_swap();
+                        self.say = "".into();
+                    }
+                    _ => {
+                        self.status = CmdStatus::Exec(ExCmd::RecoverSwap(id));
+                        return Ok(false);
+                    }
+                },
+                ExCmd::DiskChanged(id) => match self.content.trim() {
+                    "r" => {
+                        if let Err(e) = buf_m.get_buffer_mut(id)?.reload() {
+                            self.say = format!("Failed to reload: {}", e).into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                        self.say = "".into();
+                    }
+                    "k" => {
+                        self.say = "".into();
+                    }
+                    "d" => {
+                        // compare the file on the disk with the buffer and keep
+                        // asking
+                        let disk_buf = buf_m.get_buffer(id)?;
+                        if disk_buf.is_large() {
+                            self.say =
+                                "Large files aren't compared, reload or keep it?(r or k)".into();
+                            return Ok(false);
+                        }
+                        let content = match disk_buf.read_disk_content() {
+                            Ok(content) => content,
+                            Err(e) => {
+                                self.say = format!("Failed to read the file: {}", e).into();
+                                self.status = CmdStatus::Failed;
+                                return Ok(false);
+                            }
+                        };
+                        let name = format!("{} (disk)", disk_buf.name);
+                        diff_beside(buf_m, lm, diff, id, content, &name)?;
+                        return Ok(false);
+                    }
+                    _ => {
+                        self.status = CmdStatus::Exec(ExCmd::DiskChanged(id));
+                        return Ok(false);
+                    }
+                },
+                ExCmd::Overwrite(id) => {
+                    if self.content.trim() == "y"
+                        && let Err(e) = buf_m.get_buffer_mut(id)?.save()
+                    {
+                        self.say = format!("Failed to save: {}", e).into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                    self.say = "".into();
+                    self.report_format(buf_m, lm, id)?;
+                }
+                // answered above
+                ExCmd::NewFile | ExCmd::RenameFile | ExCmd::MoveFile | ExCmd::DeleteFile => {}
             },
             _ => match self.content.trim() {
                 "" => {
                     return Ok(false);
                 }
                 "revoke" => {
-                    buf.revoke()?;
+                    if let Err(e) = buf.revoke() {
+                        self.say = e.to_string().into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
                 }
                 "head" => {
-                    mv_cursor_head(lm)?;
+                    mv_cursor_head(buf_m, lm)?;
                 }
                 "tail" => {
                     mv_cursor_tail(buf_m, lm)?;
                 }
+                "match" => {
+                    mv_cursor_match(buf_m, lm)?;
+                }
+                "indent" => {
+                    let inst = Instruction::Indent(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "outdent" => {
+                    let inst = Instruction::Outdent(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "move up" => {
+                    let inst = Instruction::MoveLinesUp(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "move down" => {
+                    let inst = Instruction::MoveLinesDown(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "duplicate" => {
+                    let inst = Instruction::DuplicateLines(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "join" => {
+                    let inst = Instruction::JoinLines(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "reverse" => {
+                    let inst = Instruction::ReverseLines(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "comment" => {
+                    let inst = Instruction::ToggleComment(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "trim" => {
+                    let inst = Instruction::TrimTrailingWhitespace(self.selection);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                s if s == "sort" || s.starts_with("sort ") => {
+                    let Some(opts) = SortOptions::parse(&s[4..]) else {
+                        self.say = "Unknown sort flags, use n, r, u or i".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    };
+                    let inst = Instruction::SortLines(self.selection, opts);
+                    return Ok(self.run_instruction(buf_m, lm, inst));
+                }
+                "hover" => {
+                    return Ok(self.report_lsp(lsp.hover(buf_m, lm)));
+                }
+                "definition" => {
+                    return Ok(self.report_lsp(lsp.definition(buf_m, lm)));
+                }
+                "references" => {
+                    return Ok(self.report_lsp(lsp.references(buf_m, lm)));
+                }
+                "actions" => {
+                    return Ok(self.report_lsp(lsp.code_actions(buf_m, lm)));
+                }
+                s if s.starts_with("ref ") => {
+                    let res = match s[4..].trim().parse() {
+                        Ok(n) => lsp.open_reference(buf_m, lm, n),
+                        Err(_) => Err(LspError::NoLocation(0)),
+                    };
+                    return Ok(self.report_lsp(res));
+                }
+                s if s.starts_with("action ") => {
+                    let res = match s[7..].trim().parse() {
+                        Ok(n) => lsp.run_action(buf_m, n),
+                        Err(_) => Err(LspError::NoAction(0)),
+                    };
+                    return Ok(self.report_lsp(res));
+                }
+                s if s.starts_with("rename ") => {
+                    let name = s[7..].trim();
+                    return Ok(self.report_lsp(lsp.rename(buf_m, lm, name)));
+                }
+                "diagnostics" => {
+                    qf.collect_diagnostics(buf_m);
+                    if qf.entries.is_empty() {
+                        self.say = "No diagnostics".into();
+                        return Ok(false);
+                    }
+                    qf.open(buf_m, lm)?;
+                    self.say = format!("{} diagnostics", qf.entries.len()).into();
+                }
+                "copen" => {
+                    qf.open(buf_m, lm)?;
+                }
+                "cnext" => return Ok(self.report_jump(qf.step(buf_m, lm, true))),
+                "cprev" => return Ok(self.report_jump(qf.step(buf_m, lm, false))),
+                s if s.starts_with("cc ") => {
+                    let res = match s[3..].trim().parse::<usize>() {
+                        Ok(n) => qf.jump(buf_m, lm, n.wrapping_sub(1)),
+                        Err(_) => Err(LayoutError::NoEntry),
+                    };
+                    return Ok(self.report_jump(res));
+                }
+                // the whole buffer through a command
+                s if s.starts_with("%!") && !s[2..].trim().is_empty() => {
+                    let command = s[2..].to_string();
+                    let (id, lines, input) = match lm.filter_input(buf_m, None) {
+                        Ok(res) => res,
+                        Err(e) => return Ok(self.report_edit(Err(e), "")),
+                    };
+                    let kind = JobKind::Filter(lines);
+                    self.start_job(events, buf_m, id, command, Some(input), kind)?;
+                }
+                // the selected lines through a command, or its output at the cursor
+                s if s.starts_with('!') && !s[1..].trim().is_empty() => {
+                    let command = s[1..].to_string();
+                    let (id, input, kind) = match self.selection {
+                        Some(anchor) => match lm.filter_input(buf_m, Some(anchor)) {
+                            Ok((id, lines, input)) => (id, Some(input), JobKind::Filter(lines)),
+                            Err(e) => return Ok(self.report_edit(Err(e), "")),
+                        },
+                        None => {
+                            let buf = lm.get_current_buffer(buf_m)?;
+                            if buf.read_only {
+                                return Ok(self.report_edit(Err(BufferError::ReadOnly.into()), ""));
+                            }
+                            let pos = cursor_pos(lm)?;
+                            let kind = JobKind::Insert {
+                                pane: lm.current_layout,
+                                pos,
+                            };
+                            (buf.id, None, kind)
+                        }
+                    };
+                    self.start_job(events, buf_m, id, command, input, kind)?;
+                }
+                "make" => {
+                    let command = make.default_command();
+                    make.run(buf_m, lm, &command)?;
+                    self.say = format!("Running {}", command).into();
+                }
+                s if s.starts_with("run ") && !s[4..].trim().is_empty() => {
+                    make.run(buf_m, lm, s[4..].trim())?;
+                    self.say = format!("Running {}", s[4..].trim()).into();
+                }
+                "stop" => {
+                    self.say = match make.stop() {
+                        true => "Stopped".into(),
+                        false => "Nothing is running".into(),
+                    };
+                }
+                s if s == "explorer" || s.starts_with("explorer ") => {
+                    let args = s[8..].trim().to_string();
+                    return self.explorer_command(&args, buf_m, lm, explorer);
+                }
+                "errorformat" => {
+                    self.say = make.format_text().into();
+                    return Ok(false);
+                }
+                s if s.starts_with("errorformat ") => {
+                    if let Err(e) = make.set_format(&s[12..]) {
+                        self.say = e.into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                    self.say = make.format_text().into();
+                }
+                "lsp" => {
+                    self.say = lsp.status().into();
+                    return Ok(false);
+                }
+                s if s.starts_with("lsp ") => {
+                    let Some((lang, command)) = s[4..].trim().split_once(' ') else {
+                        self.say = "Use lsp <language> <command>".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    };
+                    if let Err(e) = lsp.set_server(buf_m, lang, command.trim()) {
+                        self.say = e.to_string().into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                }
                 "save" => {
-                    if buf.path.is_some() {
-                        let _ = buf.save();
+                    if buf.is_disk_newer() {
+                        let id = buf.id;
+                        self.ask_overwrite(cur_screen, id);
+                    } else if buf.path.is_some() {
+                        let id = buf.id;
+                        match buf.save() {
+                            Ok(()) => self.report_format(buf_m, lm, id)?,
+                            Err(e) => {
+                                self.say = save_error_text(&e).into();
+                                self.status = CmdStatus::Failed;
+                            }
+                        }
                     } else {
-                        let _ = self.ask_and_save();
+                        self.ask_and_save();
                     }
                     return Ok(false);
                 }
+                "format" => {
+                    let (command, input) = match buf.format_input() {
+                        Ok(res) => res,
+                        Err(e) => {
+                            self.say = e.into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                    };
+                    let id = buf.id;
+                    self.start_job(events, buf_m, id, command, Some(input), JobKind::Format)?;
+                }
+                "hunk" => {
+                    let y = cursor_pos(lm)?.1;
+                    let buf = lm.get_current_buffer_mut(buf_m)?;
+                    // the hunks are only updated on the timer, they may be
+                    // behind the last edits
+                    buf.update_git_hunks();
+                    let Some(h) = buf.hunk_at(y) else {
+                        self.say = "No change at the cursor".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    };
+                    let preview = buf.hunk_preview(h);
+                    popups.push(Popup::fit(
+                        &preview,
+                        Duration::from_secs(8),
+                        Color::Rgb(240, 186, 89),
+                    ));
+                }
+                "hunk next" => mv_cursor_hunk(self, buf_m, lm, true)?,
+                "hunk prev" => mv_cursor_hunk(self, buf_m, lm, false)?,
+                "hunk stage" | "hunk revert" => {
+                    let y = cursor_pos(lm)?.1;
+                    let buf = lm.get_current_buffer_mut(buf_m)?;
+                    buf.update_git_hunks();
+                    let Some(h) = buf.hunk_at(y).cloned() else {
+                        self.say = "No change at the cursor".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    };
+                    let res = match self.content.trim() {
+                        "hunk stage" => buf.stage_hunk(&h).map(|_| "Staged the hunk"),
+                        _ => match buf.revert_hunk_op(&h) {
+                            Some(op) => buf
+                                .apply_op(op, true)
+                                .map(|_| "Reverted the hunk")
+                                .map_err(|e| e.to_string()),
+                            None => Err("The file isn't in the HEAD".to_string()),
+                        },
+                    };
+                    buf.update_git_hunks();
+                    match res {
+                        Ok(done) => self.say = done.into(),
+                        Err(e) => {
+                            self.say = e.into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                    }
+                    let id = buf.id;
+                    lm.jump_to(buf_m, id, (0, h.new.start))?;
+                }
+                "diff saved" => {
+                    let pane = lm.current_layout;
+                    let id = buf.id;
+                    let name = format!("[saved] {}", buf.name);
+                    if buf.is_large() {
+                        self.say = "Large files aren't compared".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                    let saved = match buf.read_disk_content() {
+                        Ok(lines) => lines,
+                        Err(e) => {
+                            self.say = format!("No saved file: {}", e).into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                    };
+                    let saved_id = open_beside(buf_m, lm, &[], &name)?;
+                    buf_m.get_buffer_mut(saved_id)?.set_scratch_content(saved);
+                    *diff = Some(DiffView::new((lm.current_layout, saved_id), (pane, id)));
+                    // the edits go on in the buffer
+                    lm.focus(pane);
+                }
+                s if s.starts_with("diff ") && !matches!(s[5..].trim(), "next" | "prev") => {
+                    let path = s[5..].trim();
+                    let (pane, id) = (lm.current_layout, buf.id);
+                    if buf.is_large() {
+                        self.say = "Large files aren't compared".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                    let other = match buf_m.find_or_open(path) {
+                        Ok(other) if !buf_m.get_buffer(other)?.is_large() => other,
+                        Ok(_) => {
+                            self.say = "Large files aren't compared".into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                        Err(e) => {
+                            self.say = format!("Failed to open {}: {}", path, e).into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                    };
+                    split(buf_m, lm, SplitDirection::Vertical, Some(other))?;
+                    *diff = Some(DiffView::new((pane, id), (lm.current_layout, other)));
+                }
+                "diff next" | "diff prev" => match diff {
+                    Some(view) if view.side(lm.current_layout).is_some() => {
+                        let forward = self.content.trim() == "diff next";
+                        mv_cursor_diff_hunk(self, view, buf_m, lm, forward)?;
+                    }
+                    _ => {
+                        self.say = "The pane isn't in a diff".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                },
+                "diffget" | "diffput" => {
+                    let id = buf.id;
+                    let side = diff.as_ref().and_then(|view| view.side(lm.current_layout));
+                    let (Some(view), Some(side)) = (diff.as_ref(), side) else {
+                        self.say = "The pane isn't in a diff".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    };
+                    let y = cursor_pos(lm)?.1;
+                    if view.hunk_at(side, y).is_none() {
+                        self.say = "No difference at the cursor".into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                    let get = self.content.trim() == "diffget";
+                    match view.copy_hunk(buf_m, side, y, get) {
+                        Ok(line) if get => lm.jump_to(buf_m, id, (0, line))?,
+                        Ok(_) => {}
+                        Err(e) => {
+                            self.say = e.to_string().into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                    }
+                }
+                "diffoff" => {
+                    *diff = None;
+                }
+                "sudo save" => {
+                    if let Err(e) = buf.save_with_helper() {
+                        self.say = format!("Failed to save: {}", e).into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                }
                 "change name" => {
                     buf.change_name("omg");
                 }
@@ -215,8 +741,40 @@ impl KaoCo {
                     add_new_buffer(buf_m, lm)?;
                 }
                 s if s.starts_with("nbp:") => {
-                    let path = &s[4..];
-                    add_new_buffer_from_path(buf_m, lm, path)?;
+                    let path = s[4..].to_string();
+                    if !self.open_path(buf_m, lm, cur_screen, &path, false)? {
+                        return Ok(false);
+                    }
+                }
+                s if s.starts_with("set ") => {
+                    if let Err(e) = set_option(buf_m, lm, &s[4..]) {
+                        self.say = e.to_string().into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
+                }
+                s if s.starts_with("view ") => {
+                    let path = s[5..].to_string();
+                    if !self.open_path(buf_m, lm, cur_screen, &path, true)? {
+                        return Ok(false);
+                    }
+                }
+                s if s.starts_with("reopen ") => {
+                    let label = &s[7..];
+                    let res = match encoding_for_label(label) {
+                        Some(enc) if buf.saved => buf.reopen_with_encoding(enc),
+                        Some(_) => {
+                            self.say = "The buffer hasn't been saved".into();
+                            self.status = CmdStatus::Failed;
+                            return Ok(false);
+                        }
+                        None => Err(BufferError::InvalidEncoding(label.to_string())),
+                    };
+                    if let Err(e) = res {
+                        self.say = e.to_string().into();
+                        self.status = CmdStatus::Failed;
+                        return Ok(false);
+                    }
                 }
                 "sv" => {
                     split(buf_m, lm, SplitDirection::Vertical, None)?;
@@ -262,17 +820,232 @@ impl KaoCo {
         Ok(true)
     }
 
+    // open the file at `path` in the current pane, a newer swap file of it
+    // is asked about first; returns false when the command isn't done
+    fn open_path(
+        &mut self,
+        buf_m: &mut BufferManager,
+        lm: &mut LayoutManager,
+        cur_screen: &mut Screen,
+        path: &str,
+        read_only: bool,
+    ) -> Result<bool, LayoutError> {
+        let id = match buf_m.add_new_buffer_from_path(path) {
+            Ok(id) => id,
+            Err(e) => {
+                self.say = format!("Failed to open {}: {}", path, e).into();
+                self.status = CmdStatus::Failed;
+                return Ok(false);
+            }
+        };
+        lm.change_current_buffer_id(id)?;
+        let new_buf = buf_m.get_buffer_mut(id)?;
+        new_buf.read_only |= read_only;
+        if new_buf.has_newer_swap() {
+            let name = new_buf.name.clone();
+            self.ask_recover(cur_screen, id, &name);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    // the commands of the terminals, in any pane, none for the others and for
+    // the answers to questions
+    #[cfg(unix)]
+    pub fn terminal_command(
+        &mut self,
+        buf_m: &mut BufferManager,
+        lm: &mut LayoutManager,
+        terms: &mut TerminalManager,
+    ) -> Option<Result<bool, LayoutError>> {
+        let s = self.content.trim().to_string();
+        if !(s == "term" || s.starts_with("term ")) || matches!(self.status, CmdStatus::Exec(_)) {
+            return None;
+        }
+        Some(self.handle_terminal_command(&s, buf_m, lm, terms))
+    }
+
+    #[cfg(unix)]
+    fn handle_terminal_command(
+        &mut self,
+        s: &str,
+        buf_m: &mut BufferManager,
+        lm: &mut LayoutManager,
+        terms: &mut TerminalManager,
+    ) -> Result<bool, LayoutError> {
+        match s {
+            "term copy" => {
+                let Some(PaneContent::Terminal(id)) = lm.current_content() else {
+                    self.say = "The pane isn't a terminal".into();
+                    self.status = CmdStatus::Failed;
+                    return Ok(false);
+                };
+                let term = terms.get(id).ok_or(LayoutError::NoEntry)?;
+                let name = format!("[{}]", term.name);
+                let lines = term.grid.text_lines();
+                // the output in a buffer beside the terminal, at its end
+                let buffer_id = open_beside(buf_m, lm, &lines, &name)?;
+                lm.jump_to(buf_m, buffer_id, (0, lines.len().saturating_sub(1)))?;
+                self.say = format!("Copied {} lines", lines.len()).into();
+            }
+            _ => {
+                let command = Some(s[4..].trim()).filter(|c| !c.is_empty());
+                if let Err(e) = open_terminal(terms, lm, command) {
+                    self.say = format!("Failed to start the terminal: {}", e).into();
+                    self.status = CmdStatus::Failed;
+                    return Ok(false);
+                }
+            }
+        }
+        self.status = CmdStatus::Success;
+        Ok(true)
+    }
+
+    // the commands in a pane without a buffer, the others need one
+    fn handle_content_command(
+        &mut self,
+        buf_m: &mut BufferManager,
+        lm: &mut LayoutManager,
+        explorer: &mut Explorer,
+        cur_screen: &mut Screen,
+        quit: &mut bool,
+    ) -> Result<bool, LayoutError> {
+        match self.content.trim() {
+            "" => {
+                return Ok(false);
+            }
+            s if s == "explorer" || s.starts_with("explorer ") => {
+                let args = s[8..].trim().to_string();
+                return self.explorer_command(&args, buf_m, lm, explorer);
+            }
+            "close" => {
+                close_current_pane(self, buf_m, lm, quit, cur_screen)?;
+                move_focus_in_pane(lm, MoveDir::Right);
+            }
+            "right pane" => {
+                move_focus_in_pane(lm, MoveDir::Right);
+            }
+            "left pane" => {
+                move_focus_in_pane(lm, MoveDir::Left);
+            }
+            "up pane" => {
+                move_focus_in_pane(lm, MoveDir::Up);
+            }
+            "down pane" => {
+                move_focus_in_pane(lm, MoveDir::Down);
+            }
+            "change pane" => {
+                change_pane(lm, 1)?;
+            }
+            _ => {
+                self.say = "The pane has no buffer".into();
+                self.status = CmdStatus::Failed;
+                return Ok(false);
+            }
+        }
+        self.status = CmdStatus::Success;
+        Ok(true)
+    }
+
+    // `explorer` opens it or goes to it, or back from it, the others show
+    // or hide files in it
+    fn explorer_command(
+        &mut self,
+        args: &str,
+        buf_m: &BufferManager,
+        lm: &mut LayoutManager,
+        explorer: &mut Explorer,
+    ) -> Result<bool, LayoutError> {
+        match args {
+            "" => focus_explorer(buf_m, lm, explorer)?,
+            "hidden" => explorer.toggle_hidden(),
+            "ignored" => explorer.toggle_ignored(),
+            "refresh" => explorer.reload(),
+            _ => {
+                self.say = "Unknown command".into();
+                self.status = CmdStatus::Failed;
+                return Ok(false);
+            }
+        }
+        self.status = CmdStatus::Success;
+        Ok(true)
+    }
+
+    // do what was asked about the target of the explorer, an empty answer
+    // does nothing
+    fn answer_explorer(
+        &mut self,
+        ask: ExCmd,
+        buf_m: &mut BufferManager,
+        explorer: &mut Explorer,
+    ) -> Result<bool, LayoutError> {
+        let answer = self.content.trim().to_string();
+        let target = explorer.target.take();
+        self.say = "".into();
+        let (Some(target), false) = (target, answer.is_empty()) else {
+            self.status = CmdStatus::Success;
+            return Ok(true);
+        };
+        let rel = explorer.relative(&target);
+        let res = match ask {
+            ExCmd::NewFile => explorer
+                .create(&target, &answer)
+                .map(|path| format!("Created {}", explorer.relative(&path))),
+            ExCmd::RenameFile | ExCmd::MoveFile => {
+                let (to, done) = match ask {
+                    ExCmd::RenameFile => (target.with_file_name(&answer), "Renamed"),
+                    _ => (explorer.destination(&target, &answer), "Moved"),
+                };
+                // the buffers of the files go with them
+                let open = buffers_under(buf_m, &target);
+                explorer.rename(&target, &to).map(|_| {
+                    follow_move(buf_m, &open, &to);
+                    format!("{} {} to {}", done, rel, explorer.relative(&to))
+                })
+            }
+            ExCmd::DeleteFile if answer == "y" => {
+                explorer.delete(&target).map(|_| format!("Deleted {}", rel))
+            }
+            _ => Ok(String::new()),
+        };
+        match res {
+            Ok(text) => {
+                self.say = text.into();
+                self.status = CmdStatus::Success;
+                Ok(true)
+            }
+            Err(e) => {
+                self.say = format!("Failed on {}: {}", rel, e).into();
+                self.status = CmdStatus::Failed;
+                Ok(false)
+            }
+        }
+    }
+
     pub fn handle_instructions(
         &mut self,
         buf_m: &mut BufferManager,
         lm: &mut LayoutManager,
         inst: Instruction,
     ) -> Result<(), LayoutError> {
+        if lm.get_current_buffer(buf_m)?.read_only {
+            self.say = "The buffer is read-only".into();
+            self.status = CmdStatus::Failed;
+            return Ok(());
+        }
         let show = match inst {
             Instruction::InsertText(str) => {
                 add_content_at(buf_m, lm, &str)?;
                 "InsertText"
             }
+            Instruction::InsertChar(ch) => {
+                lm.handle_char(buf_m, ch)?;
+                "InsertChar"
+            }
+            Instruction::InsertTab => {
+                lm.handle_tab(buf_m)?;
+                "InsertTab"
+            }
             Instruction::DeleteText(_) => {
                 lm.handle_backspace(buf_m)?;
                 "DeleteText"
@@ -293,11 +1066,230 @@ impl KaoCo {
                 delete_line(buf_m, lm)?;
                 "DeleteLine"
             }
+            Instruction::Indent(anchor) => {
+                lm.indent_lines(buf_m, anchor, false)?;
+                "Indent"
+            }
+            Instruction::Outdent(anchor) => {
+                lm.indent_lines(buf_m, anchor, true)?;
+                "Outdent"
+            }
+            Instruction::MoveLinesUp(anchor) => {
+                lm.edit_lines(buf_m, anchor, LineEdit::MoveUp)?;
+                "MoveLinesUp"
+            }
+            Instruction::MoveLinesDown(anchor) => {
+                lm.edit_lines(buf_m, anchor, LineEdit::MoveDown)?;
+                "MoveLinesDown"
+            }
+            Instruction::DuplicateLines(anchor) => {
+                lm.edit_lines(buf_m, anchor, LineEdit::Duplicate)?;
+                "DuplicateLines"
+            }
+            Instruction::JoinLines(anchor) => {
+                lm.edit_lines(buf_m, anchor, LineEdit::Join)?;
+                "JoinLines"
+            }
+            Instruction::SortLines(anchor, opts) => {
+                lm.edit_lines(buf_m, anchor, LineEdit::Sort(opts))?;
+                "SortLines"
+            }
+            Instruction::ReverseLines(anchor) => {
+                lm.edit_lines(buf_m, anchor, LineEdit::Reverse)?;
+                "ReverseLines"
+            }
+            Instruction::TrimTrailingWhitespace(anchor) => {
+                lm.edit_lines(buf_m, anchor, LineEdit::TrimTrailing)?;
+                "TrimTrailingWhitespace"
+            }
+            Instruction::ToggleComment(anchor) => {
+                if let Err(e) = lm.edit_lines(buf_m, anchor, LineEdit::ToggleComment) {
+                    self.say = e.to_string().into();
+                    self.status = CmdStatus::Failed;
+                    return Ok(());
+                }
+                "ToggleComment"
+            }
         };
         self.say = show.into();
         Ok(())
     }
 
+    // an instruction given as a command, whether it was done
+    fn run_instruction(
+        &mut self,
+        buf_m: &mut BufferManager,
+        lm: &mut LayoutManager,
+        inst: Instruction,
+    ) -> bool {
+        self.status = CmdStatus::Success;
+        let res = self.handle_instructions(buf_m, lm, inst);
+        res.is_ok() && !matches!(self.status, CmdStatus::Failed)
+    }
+
+    // the format run by a save of buffer `id`: the cursors follow the lines
+    // it changed, a failed one is told, the file was written anyway
+    pub fn report_format(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        id: usize,
+    ) -> Result<(), LayoutError> {
+        match bm.get_buffer_mut(id)?.format_result.take() {
+            Some(Ok(hunks)) => lm.follow_hunks(bm, id, &hunks)?,
+            Some(Err(e)) => {
+                self.say = format!("Saved without formatting: {}", e).into();
+                self.status = CmdStatus::Failed;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    // run `command` for buffer `id` in the background, what it made of the
+    // buffer as it is now is applied once it's done
+    fn start_job(
+        &mut self,
+        events: &mut EventLoop,
+        bm: &BufferManager,
+        id: usize,
+        command: String,
+        input: Option<String>,
+        kind: JobKind,
+    ) -> Result<(), LayoutError> {
+        let version = bm.get_buffer(id)?.version;
+        let run = command.clone();
+        let job = events.spawn_job(move || shell::run(&run, input));
+        self.say = format!("Running {}", command.trim()).into();
+        self.jobs.insert(
+            job,
+            ShellJob {
+                command,
+                buffer_id: id,
+                version,
+                kind,
+            },
+        );
+        Ok(())
+    }
+
+    // the output of a job, returns whether the screen has to be drawn again
+    pub fn job_done(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        id: usize,
+        output: JobOutput,
+    ) -> bool {
+        let Some(job) = self.jobs.remove(&id) else {
+            if let Err(e) = output {
+                self.say = e.into();
+                self.status = CmdStatus::Failed;
+                return true;
+            }
+            return false;
+        };
+        let res = output
+            .map_err(LayoutError::CommandFailed)
+            .and_then(|output| self.apply_job(bm, lm, &job, &output));
+        match res {
+            Ok(say) => {
+                self.say = say.into();
+                self.status = CmdStatus::Success;
+            }
+            Err(e) => {
+                self.say = e.to_string().into();
+                self.status = CmdStatus::Failed;
+            }
+        }
+        true
+    }
+
+    fn apply_job(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        job: &ShellJob,
+        output: &str,
+    ) -> Result<String, LayoutError> {
+        let id = job.buffer_id;
+        if bm.get_buffer(id)?.version != job.version {
+            return Err(LayoutError::CommandFailed(format!(
+                "The buffer was changed while {} ran",
+                job.command.trim()
+            )));
+        }
+        match &job.kind {
+            JobKind::Filter(lines) => {
+                lm.apply_filter(bm, id, lines.clone(), output)?;
+                Ok("Filtered".to_string())
+            }
+            JobKind::Insert { pane, pos } => {
+                let text = output_lines(output).join("\n");
+                lm.insert_text(bm, *pane, id, *pos, &text)?;
+                Ok("Done".to_string())
+            }
+            JobKind::Format => {
+                let hunks = bm
+                    .get_buffer_mut(id)?
+                    .apply_format(output)
+                    .map_err(LayoutError::CommandFailed)?;
+                lm.follow_hunks(bm, id, &hunks)?;
+                Ok(match hunks.len() {
+                    0 => "Already formatted".to_string(),
+                    n => format!("Formatted, {} changes", n),
+                })
+            }
+        }
+    }
+
+    // an edit made by a command, the text is left as it was when it failed
+    pub fn report_edit(&mut self, res: Result<(), LayoutError>, done: &str) -> bool {
+        match res {
+            Ok(()) => {
+                self.say = done.into();
+                self.status = CmdStatus::Success;
+                true
+            }
+            Err(e) => {
+                self.say = e.to_string().into();
+                self.status = CmdStatus::Failed;
+                false
+            }
+        }
+    }
+
+    // the answer comes later, only a failed request is reported now
+    pub fn report_lsp(&mut self, res: Result<(), LspError>) -> bool {
+        match res {
+            Ok(()) => {
+                self.status = CmdStatus::Success;
+                true
+            }
+            Err(e) => {
+                self.say = e.to_string().into();
+                self.status = CmdStatus::Failed;
+                false
+            }
+        }
+    }
+
+    // a jump to a listed location, its message is shown
+    pub fn report_jump(&mut self, res: Result<String, LayoutError>) -> bool {
+        match res {
+            Ok(message) => {
+                self.say = message.into();
+                self.status = CmdStatus::Success;
+                true
+            }
+            Err(e) => {
+                self.say = e.to_string().into();
+                self.status = CmdStatus::Failed;
+                false
+            }
+        }
+    }
+
     pub fn ask_and_save(&mut self) {
         self.status = CmdStatus::Exec(ExCmd::AskAndSave);
         self.say = "Input the file's name".into();
@@ -308,6 +1300,276 @@ impl KaoCo {
         self.status = CmdStatus::Exec(ExCmd::AskAndQuit);
         self.say = "The file hasn't been saved. Are you sure you want to exit?(y or n)".into();
     }
+
+    pub fn ask_recover(&mut self, screen: &mut Screen, buf_id: usize, name: &str) {
+        *screen = Screen::Command;
+        self.status = CmdStatus::Exec(ExCmd::RecoverSwap(buf_id));
+        self.say = format!(
+            "Found a swap file of {}. Recover, diff or discard it?(r, d or x)",
+            name
+        )
+        .into();
+    }
+
+    pub fn ask_disk_changed(&mut self, screen: &mut Screen, buf_id: usize, name: &str) {
+        *screen = Screen::Command;
+        self.status = CmdStatus::Exec(ExCmd::DiskChanged(buf_id));
+        self.say = format!(
+            "{} was changed on the disk. Reload, keep or diff it?(r, k or d)",
+            name
+        )
+        .into();
+    }
+
+    pub fn ask_new_file(&mut self, screen: &mut Screen, dir: &str) {
+        *screen = Screen::Command;
+        self.status = CmdStatus::Exec(ExCmd::NewFile);
+        self.say = format!("New file in {} (a directory with a / at the end)", dir).into();
+    }
+
+    // the answer starts with the path as it is
+    pub fn ask_rename_file(&mut self, screen: &mut Screen, name: &str, moving: bool) {
+        *screen = Screen::Command;
+        self.content = name.to_string();
+        self.cursor_pos = (get_line_len(name), 0);
+        if moving {
+            self.status = CmdStatus::Exec(ExCmd::MoveFile);
+            self.say = format!("Move {} to", name).into();
+        } else {
+            self.status = CmdStatus::Exec(ExCmd::RenameFile);
+            self.say = format!("Rename {} to", name).into();
+        }
+    }
+
+    pub fn ask_delete_file(&mut self, screen: &mut Screen, name: &str) {
+        *screen = Screen::Command;
+        self.status = CmdStatus::Exec(ExCmd::DeleteFile);
+        self.say = format!("Are you sure you want to delete {}?(y or n)", name).into();
+    }
+
+    pub fn ask_overwrite(&mut self, screen: &mut Screen, buf_id: usize) {
+        *screen = Screen::Command;
+        self.status = CmdStatus::Exec(ExCmd::Overwrite(buf_id));
+        self.say =
+            "The file on the disk is newer. Are you sure you want to overwrite it?(y or n)".into();
+    }
+}
+
+pub fn save_error_text(e: &std::io::Error) -> String {
+    // only the system refuses it, the read-only buffers can't be written at all
+    if e.kind() == std::io::ErrorKind::PermissionDenied && e.raw_os_error().is_some() {
+        format!("Failed to save: {}, try `sudo save`", e)
+    } else {
+        format!("Failed to save: {}", e)
+    }
+}
+
+// handle a change of a file made by another program, returns true if the user is asked
+pub fn handle_disk_change(
+    cmd: &mut KaoCo,
+    bm: &mut BufferManager,
+    screen: &mut Screen,
+    popups: &mut Popups,
+    buf_id: usize,
+    change: DiskChange,
+) -> Result<bool, LayoutError> {
+    let buf = bm.get_buffer_mut(buf_id)?;
+    let name = buf.name.clone();
+    let color = Color::Rgb(240, 186, 89);
+
+    match change {
+        DiskChange::Deleted => {
+            popups.push(Popup::new(
+                format!("{} was deleted on the disk", name),
+                Duration::from_secs(3),
+                (40, 3),
+                color,
+            ));
+            Ok(false)
+        }
+        DiskChange::Changed(_) if buf.saved => {
+            // a file which can't be read any more leaves the buffer as it is
+            let popup = match buf.reload() {
+                Ok(()) => Popup::new(
+                    format!("{} was reloaded", name),
+                    Duration::from_secs(3),
+                    (40, 3),
+                    color,
+                ),
+                Err(e) => Popup::fit(
+                    &format!("{} was changed but can't be read: {}", name, e),
+                    Duration::from_secs(5),
+                    color,
+                ),
+            };
+            popups.push(popup);
+            Ok(false)
+        }
+        DiskChange::Changed(state) => {
+            buf.disk_conflict = Some(state);
+            cmd.clean();
+            cmd.ask_disk_changed(screen, buf_id, &name);
+            Ok(true)
+        }
+    }
+}
+
+// show some content in a new buffer in a vertical split
+// compare buffer `id` with `content` in a scratch buffer beside it, the
+// focus stays on the buffer; the scratch of the diff shown already is reused,
+// so asking again opens no more panes
+fn diff_beside(
+    bm: &mut BufferManager,
+    lm: &mut LayoutManager,
+    diff: &mut Option<DiffView>,
+    id: usize,
+    content: Vec<String>,
+    name: &str,
+) -> Result<(), LayoutError> {
+    let pane = lm.pane_showing(id).unwrap_or(lm.current_layout);
+    let shown = diff
+        .as_ref()
+        .filter(|view| view.old == (pane, id))
+        .map(|view| view.new)
+        .filter(|(p, other)| lm.pane_showing(*other) == Some(*p));
+    let other = match shown {
+        Some(other) => other,
+        None => {
+            lm.focus(pane);
+            let other_id = open_beside(bm, lm, &[], name)?;
+            (lm.current_layout, other_id)
+        }
+    };
+    let other_buf = bm.get_buffer_mut(other.1)?;
+    other_buf.name = Arc::from(name);
+    other_buf.set_scratch_content(content);
+    *diff = Some(DiffView::new((pane, id), other));
+    lm.focus(pane);
+    Ok(())
+}
+
+pub fn open_beside(
+    bm: &mut BufferManager,
+    lm: &mut LayoutManager,
+    content: &[String],
+    name: &str,
+) -> Result<usize, LayoutError> {
+    let id = bm.add_new_buffer_from_content(&content.join("\n"), name);
+    split(bm, lm, SplitDirection::Vertical, Some(id))?;
+    Ok(id)
+}
+
+// open the explorer on the left of the panes, or go to it, with the file of
+// the current pane selected, from the explorer it goes back to that pane
+pub fn focus_explorer(
+    bm: &BufferManager,
+    lm: &mut LayoutManager,
+    explorer: &mut Explorer,
+) -> Result<(), LayoutError> {
+    let pane = lm.pane_with(PaneContent::Explorer);
+    if pane.is_some() && pane == Some(lm.current_layout) {
+        lm.focus(explorer.last_pane);
+        return Ok(());
+    }
+    let path = lm
+        .get_current_buffer(bm)
+        .ok()
+        .and_then(|buf| buf.path.as_ref())
+        .and_then(|path| std::fs::canonicalize(path).ok());
+    if lm.current_content().is_none() {
+        explorer.last_pane = lm.current_layout;
+    }
+    explorer.load_ignored();
+    match path {
+        Some(path) => explorer.reveal(&path),
+        None => explorer.refresh(),
+    }
+    match pane {
+        Some(id) => {
+            lm.focus(id);
+        }
+        None => {
+            lm.open_sidebar(PaneContent::Explorer, EXPLORER_RATIO)?;
+        }
+    }
+    Ok(())
+}
+
+// open the file selected in the explorer in the pane it came from, or in a
+// split of it, a directory opens or closes instead
+pub fn open_from_explorer(
+    bm: &mut BufferManager,
+    lm: &mut LayoutManager,
+    explorer: &mut Explorer,
+    direc: Option<SplitDirection>,
+) -> Result<(), LayoutError> {
+    let Some(entry) = explorer.selected_entry().cloned() else {
+        return Ok(());
+    };
+    if entry.is_dir {
+        explorer.toggle();
+        return Ok(());
+    }
+    let id = bm.find_or_open(&entry.path)?;
+    let explorer_pane = lm.current_layout;
+    if !lm.focus(explorer.last_pane) || lm.current_content().is_some() {
+        // no pane to go back to, the file goes beside the explorer
+        lm.focus(explorer_pane);
+        lm.split(explorer_pane, Some(id), SplitDirection::Vertical, bm)?;
+    } else if let Some(direc) = direc {
+        split(bm, lm, direc, Some(id))?;
+    } else if lm.get_current_buffer(bm)?.id != id {
+        lm.jump_to(bm, id, (0, 0))?;
+    }
+    explorer.last_pane = lm.current_layout;
+    Ok(())
+}
+
+// the buffers of `path` and of the files under it, with their paths in it
+fn buffers_under(bm: &BufferManager, path: &Path) -> Vec<(usize, PathBuf)> {
+    bm.buffers
+        .values()
+        .filter_map(|buf| {
+            let file = std::fs::canonicalize(buf.path.as_ref()?).ok()?;
+            let rest = file.strip_prefix(path).ok()?;
+            Some((buf.id, rest.to_path_buf()))
+        })
+        .collect()
+}
+
+// the buffers of moved files take their new paths
+fn follow_move(bm: &mut BufferManager, open: &[(usize, PathBuf)], to: &Path) {
+    for (id, rest) in open {
+        let Ok(buf) = bm.get_buffer_mut(*id) else {
+            continue;
+        };
+        let path = match rest.as_os_str().is_empty() {
+            true => to.to_path_buf(),
+            false => to.join(rest),
+        };
+        // the swap file and the git state were of the old path
+        buf.remove_swap();
+        buf.git = None;
+        if let Some(name) = path.file_name() {
+            buf.name = Arc::from(name.to_string_lossy().as_ref());
+        }
+        buf.path = Some(path);
+    }
+}
+
+// start a terminal in a pane under the current one, it gets the focus
+#[cfg(unix)]
+pub fn open_terminal(
+    terms: &mut TerminalManager,
+    lm: &mut LayoutManager,
+    command: Option<&str>,
+) -> Result<usize, LayoutError> {
+    let id = terms.open(command)?;
+    lm.split_content(
+        lm.current_layout,
+        PaneContent::Terminal(id),
+        SplitDirection::Horizontal,
+    )
 }
 
 pub fn create_new_buffer(
@@ -356,8 +1618,8 @@ pub fn mv_cursor_prev_word_head(
     Ok(())
 }
 
-pub fn mv_cursor_head(lm: &mut LayoutManager) -> Result<(), LayoutError> {
-    lm.mv_cursor_head()?;
+pub fn mv_cursor_head(bm: &mut BufferManager, lm: &mut LayoutManager) -> Result<(), LayoutError> {
+    lm.mv_cursor_head(bm)?;
     Ok(())
 }
 
@@ -366,6 +1628,68 @@ pub fn mv_cursor_tail(bm: &mut BufferManager, lm: &mut LayoutManager) -> Result<
     Ok(())
 }
 
+pub fn mv_cursor_match(bm: &mut BufferManager, lm: &mut LayoutManager) -> Result<(), LayoutError> {
+    lm.mv_cursor_match(bm)?;
+    Ok(())
+}
+
+// move to the next or the previous diagnostic of the buffer and show it
+pub fn mv_cursor_diagnostic(
+    cmd: &mut KaoCo,
+    bm: &BufferManager,
+    lm: &mut LayoutManager,
+    forward: bool,
+) -> Result<(), LayoutError> {
+    let buf = lm.get_current_buffer(bm)?;
+    let Some(d) = buf.next_diagnostic(cursor_pos(lm)?, forward) else {
+        cmd.say = "No diagnostics".into();
+        return Ok(());
+    };
+    let message = d.message.lines().next().unwrap_or_default();
+    cmd.say = format!("{} {}", d.severity.sign(), message).into();
+    let (id, pos) = (buf.id, d.start);
+    lm.jump_to(bm, id, pos)
+}
+
+// go to the next or the previous hunk of the changes since the git HEAD
+pub fn mv_cursor_hunk(
+    cmd: &mut KaoCo,
+    bm: &BufferManager,
+    lm: &mut LayoutManager,
+    forward: bool,
+) -> Result<(), LayoutError> {
+    let buf = lm.get_current_buffer(bm)?;
+    let Some(y) = buf.next_hunk(cursor_pos(lm)?.1, forward) else {
+        cmd.say = "No changes".into();
+        return Ok(());
+    };
+    let hunks = buf.git_hunks();
+    let n = hunks.iter().position(|h| hunk_line(h) == y).unwrap_or(0);
+    cmd.say = format!("({}/{}) hunk", n + 1, hunks.len()).into();
+    let id = buf.id;
+    lm.jump_to(bm, id, (0, y))
+}
+
+// go to the next or the previous hunk of the diff the current pane is in
+pub fn mv_cursor_diff_hunk(
+    cmd: &mut KaoCo,
+    view: &DiffView,
+    bm: &BufferManager,
+    lm: &mut LayoutManager,
+    forward: bool,
+) -> Result<(), LayoutError> {
+    let Some(side) = view.side(lm.current_layout) else {
+        return Ok(());
+    };
+    let Some((y, n)) = view.next_hunk(side, cursor_pos(lm)?.1, forward) else {
+        cmd.say = "No differences".into();
+        return Ok(());
+    };
+    cmd.say = format!("({}/{}) hunk", n + 1, view.hunks.len()).into();
+    let id = lm.get_current_buffer(bm)?.id;
+    lm.jump_to(bm, id, (0, y))
+}
+
 pub fn add_content_at(
     bm: &mut BufferManager,
     lm: &mut LayoutManager,
@@ -385,7 +1709,7 @@ pub fn add_content_at(
         EditOp::Insert {
             pos: cursor.pos,
             text: add_str.into(),
-            len: add_str.len(),
+            len: get_line_len(add_str),
         },
         true,
     )?;
@@ -406,7 +1730,7 @@ pub fn delete_line(bm: &mut BufferManager, lm: &mut LayoutManager) -> Result<(),
     let buf = bm.get_buffer_mut(buffer_id)?;
     buf.apply_op(
         EditOp::DeleteLine {
-            y: y,
+            y,
             text: buf.content[y].clone().into(),
         },
         true,
@@ -452,6 +1776,31 @@ pub fn save(bm: &mut BufferManager, lm: &mut LayoutManager) -> Result<(), Layout
     Ok(buf.save()?)
 }
 
+// set the option of the current buffer and the default of the new buffers
+pub fn set_option(
+    bm: &mut BufferManager,
+    lm: &mut LayoutManager,
+    expr: &str,
+) -> Result<(), LayoutError> {
+    if let Some(LayoutNode::Pane { options, .. }) = lm.get_current_pane_mut()
+        && options.set(expr)?
+    {
+        return Ok(());
+    }
+    if lm.get_current_buffer_mut(bm)?.set_local_option(expr)? {
+        return Ok(());
+    }
+    bm.options.set(expr)?;
+    let buf = lm.get_current_buffer_mut(bm)?;
+    buf.options.set(expr)?;
+    Ok(())
+}
+
+pub fn is_disk_newer(bm: &mut BufferManager, lm: &mut LayoutManager) -> Result<bool, LayoutError> {
+    let buf = lm.get_current_buffer(bm)?;
+    Ok(buf.is_disk_newer())
+}
+
 pub fn is_buffer_binding(
     bm: &mut BufferManager,
     lm: &mut LayoutManager,
@@ -492,6 +1841,8 @@ pub fn split(
 
     let id = match pane {
         LayoutNode::Pane { id, .. } => id,
+        // a pane without a buffer only splits with one given
+        LayoutNode::Content { id, .. } if buf_id.is_some() => id,
         _ => return Err(LayoutError::NotPane),
     };
 
@@ -517,11 +1868,16 @@ pub fn close_current_pane(
     let pane = lm.get_current_pane().ok_or(LayoutError::PaneNotFound)?;
 
     let (id, buffer_id) = match pane {
-        LayoutNode::Pane { id, buffer_id, .. } => (id, buffer_id),
+        LayoutNode::Pane { id, buffer_id, .. } => (id, Some(buffer_id)),
+        // nothing to save in a pane without a buffer
+        LayoutNode::Content { id, .. } => (id, None),
         _ => return Err(LayoutError::NotPane),
     };
-    let buf = bm.get_buffer(buffer_id)?;
-    if !buf.saved {
+    let saved = match buffer_id {
+        Some(buffer_id) => bm.get_buffer(buffer_id)?.saved,
+        None => true,
+    };
+    if !saved {
         cmd.ask_and_quit(screen);
     } else {
         lm.remove(id)?;
@@ -538,6 +1894,15 @@ pub fn move_focus_in_pane(lm: &mut LayoutManager, direc: MoveDir) {
     lm.move_focus(direc);
 }
 
+pub fn click_in_pane(
+    bm: &BufferManager,
+    lm: &mut LayoutManager,
+    column: u16,
+    row: u16,
+) -> Result<bool, LayoutError> {
+    lm.click(bm, column, row)
+}
+
 pub fn change_pane(lm: &mut LayoutManager, id: usize) -> Result<(), LayoutError> {
     if !lm.contain_id(id) {
         return Err(LayoutError::IdNotFound);
@@ -556,10 +1921,18 @@ pub fn add_new_buffer_from_path(
     bm: &mut BufferManager,
     lm: &mut LayoutManager,
     p: &str,
-) -> Result<(), LayoutError> {
+) -> Result<usize, LayoutError> {
     let id = bm.add_new_buffer_from_path(p)?;
     lm.change_current_buffer_id(id)?;
-    Ok(())
+    Ok(id)
+}
+
+pub fn cursor_pos(lm: &LayoutManager) -> Result<(usize, usize), LayoutError> {
+    match lm.get_current_pane() {
+        Some(LayoutNode::Pane { cursor, .. }) => Ok(cursor.pos),
+        Some(_) => Err(LayoutError::NotPane),
+        None => Err(LayoutError::PaneNotFound),
+    }
 }
 
 pub fn enter_visual(lm: &mut LayoutManager, cur_mod: &mut Mod) -> Result<(), LayoutError> {
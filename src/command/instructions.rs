@@ -1,11 +1,30 @@
 use crate::command::Arc;
+use crate::lines::SortOptions;
 
 pub enum Instruction {
     InsertText(Arc<str>),
+    // a typed character, the closers may take away some indent
+    InsertChar(char),
+    // a tab character or spaces up to the next tab stop
+    InsertTab,
     DeleteText(usize),
     InsertLine,
     DeleteLine,
     DeleteBlock((usize, usize)),
     InsertBlock(Vec<Arc<str>>),
+    // the current line, or the lines up to the visual anchor
+    Indent(Option<(usize, usize)>),
+    Outdent(Option<(usize, usize)>),
+    // the line commands, on the current line or the lines up to the anchor
+    MoveLinesUp(Option<(usize, usize)>),
+    MoveLinesDown(Option<(usize, usize)>),
+    DuplicateLines(Option<(usize, usize)>),
+    JoinLines(Option<(usize, usize)>),
+    SortLines(Option<(usize, usize)>, SortOptions),
+    ReverseLines(Option<(usize, usize)>),
+    // the whole buffer without an anchor
+    TrimTrailingWhitespace(Option<(usize, usize)>),
+    // with the comment tokens of the buffer's language
+    ToggleComment(Option<(usize, usize)>),
 }
 
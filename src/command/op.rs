@@ -1,3 +1,4 @@
+use crate::utils::get_line_len;
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
@@ -30,9 +31,36 @@ pub enum EditOp {
         end_pos: (usize, usize),
         text: String,
     },
+    // applied in order, undone as one step
+    Group(Vec<EditOp>),
 }
 
 impl EditOp {
+    // replace the text between `start` and `end` with `text`, as one step
+    pub fn replace(start: (usize, usize), end: (usize, usize), text: &str) -> Self {
+        let (start, end) = if (start.1, start.0) <= (end.1, end.0) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        let mut ops = vec![];
+        if start != end {
+            ops.push(Self::DeleteBlock {
+                start_pos: start,
+                end_pos: end,
+                text: String::new(),
+            });
+        }
+        if !text.is_empty() {
+            ops.push(Self::InsertBlock {
+                start_pos: start,
+                end_pos: text_end(start, text),
+                text: text.to_string(),
+            });
+        }
+        Self::Group(ops)
+    }
+
     pub fn inverse(&self) -> Self {
         match self {
             Self::Insert { pos, text, len } => Self::Delete {
@@ -64,6 +92,15 @@ impl EditOp {
                 end_pos: *end_pos,
                 text: text.clone(),
             },
+            Self::Group(ops) => Self::Group(ops.iter().rev().map(|op| op.inverse()).collect()),
         }
     }
 }
+
+// where `text` ends when it's inserted at `start`
+pub fn text_end(start: (usize, usize), text: &str) -> (usize, usize) {
+    match text.rsplit_once('\n') {
+        Some((head, last)) => (get_line_len(last), start.1 + head.matches('\n').count() + 1),
+        None => (start.0 + get_line_len(text), start.1),
+    }
+}
@@ -0,0 +1,119 @@
+#![allow(dead_code)]
+// comment and uncomment lines with the tokens of the buffer's language, the
+// line comments go at the smallest indent of the lines so they stay aligned
+use crate::buffer::Buffer;
+use crate::error::BufferError;
+use crate::indent::leading_whitespace;
+use crate::op::EditOp;
+use crate::utils::get_line_len;
+
+// the token and the space after it, if it's there
+fn token_len(text: &str, token: &str) -> Option<usize> {
+    let rest = text.strip_prefix(token)?;
+    Some(token.len() + usize::from(rest.starts_with(' ')))
+}
+
+impl Buffer {
+    // the op which comments the lines `sy..=ey`, or uncomments them if all
+    // of them are comments already, `None` if they are blank
+    pub fn toggle_comment_op(&self, sy: usize, ey: usize) -> Result<Option<EditOp>, BufferError> {
+        let lang = self.language().ok_or(BufferError::NoCommentToken)?;
+        let lines: Vec<(usize, &str)> = (sy..=ey)
+            .map(|y| (y, self.content[y].as_str()))
+            .filter(|(_, l)| !l.trim().is_empty())
+            .collect();
+        if lines.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(token) = lang.line_comment {
+            return Ok(Some(line_comment_op(&lines, token)));
+        }
+        let (open, close) = lang.block_comment.ok_or(BufferError::NoCommentToken)?;
+        Ok(Some(block_comment_op(&lines, open, close)))
+    }
+}
+
+fn line_comment_op(lines: &[(usize, &str)], token: &str) -> EditOp {
+    let commented = lines.iter().all(|(_, l)| l.trim_start().starts_with(token));
+    let ops = if commented {
+        lines
+            .iter()
+            .map(|&(y, line)| {
+                let indent = leading_whitespace(line);
+                let len = token_len(&line[indent.len()..], token).unwrap_or(0);
+                EditOp::Delete {
+                    pos: (get_line_len(indent), y),
+                    len: get_line_len(&line[indent.len()..indent.len() + len]),
+                    text: line[indent.len()..indent.len() + len].into(),
+                }
+            })
+            .collect()
+    } else {
+        let indent = lines
+            .iter()
+            .map(|(_, l)| leading_whitespace(l))
+            .min_by_key(|i| i.len())
+            .unwrap_or_default();
+        let text = format!("{} ", token);
+        lines
+            .iter()
+            .map(|&(y, _)| EditOp::Insert {
+                pos: (get_line_len(indent), y),
+                text: text.as_str().into(),
+                len: get_line_len(&text),
+            })
+            .collect()
+    };
+    EditOp::Group(ops)
+}
+
+// the first line opens the comment and the last one closes it, the end is
+// changed first so the positions at the head stay right on a single line
+fn block_comment_op(lines: &[(usize, &str)], open: &str, close: &str) -> EditOp {
+    let (fy, first) = lines[0];
+    let (ly, last) = lines[lines.len() - 1];
+    let indent = leading_whitespace(first);
+    let head = &first[indent.len()..];
+    let tail = last.trim_end();
+
+    let opened = token_len(head, open);
+    let closed = tail
+        .strip_suffix(close)
+        .map(|rest| close.len() + usize::from(rest.ends_with(' ')));
+    let ops = match (opened, closed) {
+        // on a single line the tokens can't overlap
+        (Some(open_len), Some(close_len)) if fy != ly || head.len() >= open_len + close_len => {
+            let start = tail.len() - close_len;
+            vec![
+                EditOp::Delete {
+                    pos: (get_line_len(&last[..start]), ly),
+                    len: get_line_len(&tail[start..]),
+                    text: tail[start..].into(),
+                },
+                EditOp::Delete {
+                    pos: (get_line_len(indent), fy),
+                    len: get_line_len(&head[..open_len]),
+                    text: head[..open_len].into(),
+                },
+            ]
+        }
+        _ => {
+            let close_text = format!(" {}", close);
+            let open_text = format!("{} ", open);
+            vec![
+                EditOp::Insert {
+                    pos: (get_line_len(tail), ly),
+                    text: close_text.as_str().into(),
+                    len: get_line_len(&close_text),
+                },
+                EditOp::Insert {
+                    pos: (get_line_len(indent), fy),
+                    text: open_text.as_str().into(),
+                    len: get_line_len(&open_text),
+                },
+            ]
+        }
+    };
+    EditOp::Group(ops)
+}
@@ -0,0 +1,271 @@
+#![allow(dead_code)]
+// the completion menu: the words of the open buffers, the files of a path
+// being typed and the items of the language server, filtered fuzzily by the
+// text typed since the menu opened
+use crate::buffer::BufferManager;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use unicode_segmentation::UnicodeSegmentation;
+
+// the most words taken from the buffers
+const MAX_WORDS: usize = 20000;
+// characters which end a path being typed
+const PATH_STOPS: &[char] = &[
+    '"', '\'', '`', '(', ')', '<', '>', '[', ']', '{', '}', '=', ',', ';',
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    Lsp,
+    Path,
+    Word,
+}
+
+impl Source {
+    // shown after the label
+    pub fn tag(self) -> &'static str {
+        match self {
+            Source::Lsp => "lsp",
+            Source::Path => "file",
+            Source::Word => "word",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub label: String,
+    // what's inserted, the label when the server doesn't say
+    pub insert: String,
+    pub source: Source,
+}
+
+#[derive(Debug)]
+pub struct Completion {
+    pub buffer_id: usize,
+    // the head of the completed text, it's replaced when one is picked
+    pub start: (usize, usize),
+    pub prefix: String,
+    // the directory of the path being typed, as it was typed
+    pub dir: Option<String>,
+    pub items: Vec<Candidate>,
+    // the indices of the items which match the prefix, the best first
+    pub shown: Vec<usize>,
+    pub selected: usize,
+}
+
+fn is_word(g: &str) -> bool {
+    g.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+// where the text completed at `x` starts, the text and the directory if it
+// looks like a path
+fn context(line: &str, x: usize) -> (usize, String, Option<String>) {
+    let graphemes: Vec<&str> = line.graphemes(true).take(x).collect();
+    let x = graphemes.len();
+    let word_start = x - graphemes.iter().rev().take_while(|g| is_word(g)).count();
+    let token_start = x - graphemes
+        .iter()
+        .rev()
+        .take_while(|g| {
+            !g.chars()
+                .any(|c| c.is_whitespace() || PATH_STOPS.contains(&c))
+        })
+        .count();
+    let token = graphemes[token_start..].concat();
+    if let Some(slash) = token.rfind('/') {
+        let start = token_start + token[..=slash].graphemes(true).count();
+        let prefix = token[slash + 1..].to_string();
+        return (start, prefix, Some(token[..=slash].to_string()));
+    }
+    (word_start, graphemes[word_start..].concat(), None)
+}
+
+// the words of all the buffers, without repeating any
+fn buffer_words(bm: &BufferManager) -> Vec<Candidate> {
+    let mut seen = HashSet::new();
+    let lines = bm.buffers.values().flat_map(|buf| buf.content.iter());
+    for line in lines {
+        for word in line.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+            if word.chars().count() >= 2 && !word.starts_with(|c: char| c.is_ascii_digit()) {
+                seen.insert(word);
+            }
+        }
+        if seen.len() >= MAX_WORDS {
+            break;
+        }
+    }
+    seen.into_iter()
+        .map(|w| Candidate {
+            label: w.to_string(),
+            insert: w.to_string(),
+            source: Source::Word,
+        })
+        .collect()
+}
+
+// the entries of the directory `dir` as it was typed, the hidden ones only
+// when the name typed starts with a dot
+fn dir_entries(dir: &str, hidden: bool) -> Vec<Candidate> {
+    let path = match dir.strip_prefix("~/") {
+        Some(rest) => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(rest))
+            .unwrap_or_else(|| PathBuf::from(dir)),
+        None => PathBuf::from(dir),
+    };
+    let Ok(entries) = fs::read_dir(path) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            if name.starts_with('.') && !hidden {
+                return None;
+            }
+            // a directory goes on with a slash
+            let insert = match e.file_type().is_ok_and(|t| t.is_dir()) {
+                true => format!("{}/", name),
+                false => name,
+            };
+            Some(Candidate {
+                label: insert.clone(),
+                insert,
+                source: Source::Path,
+            })
+        })
+        .collect()
+}
+
+// how well `pattern` matches `text`, its characters in order, `None` if it
+// doesn't, higher is better: runs of characters and the head count more
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    // only an upper case letter has to match its case
+    let smart = |p: char, t: char| match p.is_uppercase() {
+        true => p == t,
+        false => p == t.to_lowercase().next().unwrap_or(t),
+    };
+    let mut score = 0;
+    let mut chars = text.chars().enumerate();
+    let mut last = None;
+    for p in pattern.chars() {
+        let (i, t) = loop {
+            let (i, t) = chars.next()?;
+            if smart(p, t) {
+                break (i, t);
+            }
+        };
+        score += match last {
+            Some(l) if l + 1 == i => 8,
+            _ if i == 0 => 10,
+            _ => 1,
+        };
+        if t == p {
+            score += 1;
+        }
+        last = Some(i);
+    }
+    // the shorter the rest, the closer the match
+    Some(score * 100 - text.chars().count() as i64)
+}
+
+impl Completion {
+    // the menu at `pos` of the buffer, with the words or the files, `None`
+    // if the position is past the buffer
+    pub fn new(bm: &BufferManager, buffer_id: usize, pos: (usize, usize)) -> Option<Self> {
+        let buf = bm.get_buffer(buffer_id).ok()?;
+        let (start, prefix, dir) = context(buf.content.get(pos.1)?, pos.0);
+        let items = match &dir {
+            Some(dir) => dir_entries(dir, prefix.starts_with('.')),
+            None => buffer_words(bm),
+        };
+        let mut menu = Self {
+            buffer_id,
+            start: (start, pos.1),
+            prefix,
+            dir,
+            items,
+            shown: vec![],
+            selected: 0,
+        };
+        menu.filter();
+        Some(menu)
+    }
+
+    // the items of the language server, they come after the menu opened
+    pub fn add_items(&mut self, items: Vec<Candidate>) {
+        // a word the server knows too is shown once
+        let labels: HashSet<String> = items.iter().map(|c| c.label.clone()).collect();
+        self.items
+            .retain(|c| c.source != Source::Word || !labels.contains(&c.label));
+        self.items.extend(items);
+        self.filter();
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.label != self.prefix)
+            .filter_map(|(i, c)| Some((fuzzy_score(&self.prefix, &c.label)?, i)))
+            .collect();
+        let items = &self.items;
+        scored.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then(items[a.1].source.cmp(&items[b.1].source))
+                .then(items[a.1].label.cmp(&items[b.1].label))
+        });
+        self.shown = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+
+    // the cursor moved to `pos` after typing, returns false when it left the
+    // completed text and the menu should close
+    pub fn update(&mut self, bm: &BufferManager, pos: (usize, usize)) -> bool {
+        let Some(line) = bm
+            .get_buffer(self.buffer_id)
+            .ok()
+            .and_then(|buf| buf.content.get(pos.1))
+        else {
+            return false;
+        };
+        if pos.1 != self.start.1 {
+            return false;
+        }
+        let (start, prefix, dir) = context(line, pos.0);
+        // a dot brings the hidden files in
+        let hidden = dir.is_some() && prefix.starts_with('.') != self.prefix.starts_with('.');
+        if start != self.start.0 || dir != self.dir || hidden {
+            // a directory was typed, its files are offered instead
+            if dir.is_none() {
+                return false;
+            }
+            match Self::new(bm, self.buffer_id, pos) {
+                Some(menu) => *self = menu,
+                None => return false,
+            }
+            return true;
+        }
+        self.prefix = prefix;
+        self.filter();
+        true
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.shown.is_empty() {
+            self.selected = (self.selected + 1) % self.shown.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.shown.is_empty() {
+            self.selected = (self.selected + self.shown.len() - 1) % self.shown.len();
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Candidate> {
+        self.shown.get(self.selected).map(|&i| &self.items[i])
+    }
+}
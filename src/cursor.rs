@@ -4,13 +4,16 @@ use ratatui::crossterm::cursor::{SetCursorStyle};
 pub struct Cursor {
     pub style: SetCursorStyle,
     pub pos: (usize, usize),
+    // the closers inserted by auto-pairing, typing one over them steps over it
+    pub auto_closers: Vec<(usize, usize)>,
 }
 
 impl Cursor {
     pub fn new() -> Self {
         Self {
             style: SetCursorStyle::DefaultUserShape,
-            pos: (0, 0)
+            pos: (0, 0),
+            auto_closers: vec![],
         }
     }
 }
@@ -0,0 +1,180 @@
+#![allow(dead_code)]
+// the problems found in a buffer by a language server, a build or a linter,
+// each of them replaces its own diagnostics and keeps the others
+use crate::buffer::Buffer;
+use crate::op::EditOp;
+use ratatui::style::Color;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    // the number the language server protocol uses, 1 is an error
+    pub fn from_lsp(n: u64) -> Self {
+        match n {
+            1 => Severity::Error,
+            2 => Severity::Warning,
+            3 => Severity::Info,
+            _ => Severity::Hint,
+        }
+    }
+
+    // shown in the gutter
+    pub fn sign(self) -> &'static str {
+        match self {
+            Severity::Error => "E",
+            Severity::Warning => "W",
+            Severity::Info => "I",
+            Severity::Hint => "H",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Severity::Error => Color::Rgb(237, 106, 94),
+            Severity::Warning => Color::Rgb(245, 200, 92),
+            Severity::Info => Color::Rgb(120, 180, 235),
+            Severity::Hint => Color::DarkGray,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    // grapheme positions (x, y), like the cursor
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub severity: Severity,
+    pub message: String,
+    // who found it, the name of the language server for example
+    pub source: String,
+    // what reported it, `lsp` or `make` for example
+    pub producer: String,
+}
+
+// the most severe diagnostic of line `y`
+pub fn worst_on_line(diagnostics: &[Diagnostic], y: usize) -> Option<&Diagnostic> {
+    diagnostics
+        .iter()
+        .filter(|d| d.start.1 == y)
+        .min_by_key(|d| d.severity)
+}
+
+// the errors and the warnings among `diagnostics`
+pub fn count(diagnostics: &[Diagnostic]) -> (usize, usize) {
+    diagnostics
+        .iter()
+        .fold((0, 0), |(e, w), d| match d.severity {
+            Severity::Error => (e + 1, w),
+            Severity::Warning => (e, w + 1),
+            _ => (e, w),
+        })
+}
+
+impl Buffer {
+    // replace the diagnostics of `producer`, they're kept in order
+    pub fn set_diagnostics(&mut self, producer: &str, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics.retain(|d| d.producer != producer);
+        self.diagnostics.extend(diagnostics);
+        self.diagnostics
+            .sort_by_key(|d| ((d.start.1, d.start.0), d.severity));
+    }
+
+    // the position of the next diagnostic after `pos`, or the one before it,
+    // going round the end of the buffer
+    pub fn next_diagnostic(&self, pos: (usize, usize), forward: bool) -> Option<&Diagnostic> {
+        let key = |d: &Diagnostic| (d.start.1, d.start.0);
+        let pos = (pos.1, pos.0);
+        if forward {
+            self.diagnostics
+                .iter()
+                .find(|d| key(d) > pos)
+                .or(self.diagnostics.first())
+        } else {
+            self.diagnostics
+                .iter()
+                .rev()
+                .find(|d| key(d) < pos)
+                .or(self.diagnostics.last())
+        }
+    }
+
+    // the diagnostics follow the lines added or removed by `op`, until their
+    // producer reports again
+    pub fn shift_diagnostics(&mut self, op: &EditOp) {
+        // from which line, by how many lines
+        let (from, delta): (usize, isize) = match op {
+            EditOp::InsertLine { y, .. } => (*y, 1),
+            EditOp::DeleteLine { y, .. } => {
+                self.diagnostics.retain(|d| d.start.1 != *y);
+                (*y + 1, -1)
+            }
+            EditOp::InsertBlock {
+                start_pos, text, ..
+            } => (start_pos.1 + 1, text.matches('\n').count() as isize),
+            // selected upwards the cursor comes before the anchor
+            EditOp::DeleteBlock {
+                start_pos, end_pos, ..
+            } => {
+                let (sy, ey) = (start_pos.1.min(end_pos.1), start_pos.1.max(end_pos.1));
+                (ey + 1, sy as isize - ey as isize)
+            }
+            _ => return,
+        };
+        if delta == 0 {
+            return;
+        }
+        for d in self.diagnostics.iter_mut() {
+            for y in [&mut d.start.1, &mut d.end.1] {
+                if *y >= from {
+                    *y = y.saturating_add_signed(delta);
+                } else if delta < 0 && *y as isize > from as isize - 1 + delta {
+                    // inside the lines removed
+                    *y = (from as isize - 1 + delta) as usize;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(y: usize) -> Diagnostic {
+        Diagnostic {
+            start: (0, y),
+            end: (3, y),
+            severity: Severity::Error,
+            message: String::new(),
+            source: String::new(),
+            producer: "test".to_string(),
+        }
+    }
+
+    fn lines_after(op: &EditOp) -> Vec<usize> {
+        let mut buf = Buffer::new("test", 1);
+        buf.diagnostics = [0, 2, 5].into_iter().map(diagnostic).collect();
+        buf.shift_diagnostics(op);
+        buf.diagnostics.iter().map(|d| d.start.1).collect()
+    }
+
+    #[test]
+    fn shift_after_reversed_block() {
+        let block = |start_pos, end_pos| EditOp::DeleteBlock {
+            start_pos,
+            end_pos,
+            text: "a\nb\nc".to_string(),
+        };
+        // the lines 1 to 3 are joined into the line 1
+        let down = lines_after(&block((1, 1), (2, 3)));
+        let up = lines_after(&block((2, 3), (1, 1)));
+        assert_eq!(down, vec![0, 1, 3]);
+        assert_eq!(up, down);
+    }
+}
@@ -0,0 +1,165 @@
+#![allow(dead_code)]
+// the lines which differ between two texts, with the O(ND) algorithm of
+// Myers; the common head and tail are taken off first, they're most of it
+// when a few lines were edited
+use std::ops::Range;
+
+// the lines `old` of the first text became the lines `new` of the second,
+// one of them is empty for an insert or a delete
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+// more edits than this and the middle is one hunk, the trace of the search
+// grows with the square of them
+const MAX_EDITS: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Equal,
+    Delete,
+    Insert,
+}
+
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let head = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let tail = old[head..]
+        .iter()
+        .rev()
+        .zip(new[head..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[head..old.len() - tail];
+    let b = &new[head..new.len() - tail];
+    if a.is_empty() && b.is_empty() {
+        return vec![];
+    }
+    let hunks = match shortest_edit(a, b) {
+        Some(steps) => hunks_of(&steps),
+        None => vec![Hunk {
+            old: 0..a.len(),
+            new: 0..b.len(),
+        }],
+    };
+    hunks
+        .into_iter()
+        .map(|h| Hunk {
+            old: h.old.start + head..h.old.end + head,
+            new: h.new.start + head..h.new.end + head,
+        })
+        .collect()
+}
+
+// the steps from `a` to `b`, none if it takes more than MAX_EDITS
+fn shortest_edit<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Step>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let limit = (a.len() + b.len()).min(MAX_EDITS) as isize;
+    let off = limit + 1;
+    // the furthest x on each diagonal k = x - y
+    let mut v = vec![0isize; 2 * limit as usize + 3];
+    // v before each round, for the diagonals -d..=d
+    let mut trace: Vec<Vec<isize>> = vec![];
+    for d in 0..=limit {
+        trace.push(v[(off - d) as usize..=(off + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let down = k == -d || (k != d && v[(off + k - 1) as usize] < v[(off + k + 1) as usize]);
+            let mut x = match down {
+                true => v[(off + k + 1) as usize],
+                false => v[(off + k - 1) as usize] + 1,
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(off + k) as usize] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Step> {
+    let (mut x, mut y) = (n, m);
+    let mut steps = vec![];
+    for (d, v) in trace.iter().enumerate().skip(1).rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = match k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            steps.push(Step::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        steps.push(match x == prev_x {
+            true => Step::Insert,
+            false => Step::Delete,
+        });
+        (x, y) = (prev_x, prev_y);
+    }
+    // the first snake
+    steps.extend((0..x).map(|_| Step::Equal));
+    steps.reverse();
+    steps
+}
+
+fn hunks_of(steps: &[Step]) -> Vec<Hunk> {
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    let mut start: Option<(usize, usize)> = None;
+    for step in steps {
+        match step {
+            Step::Equal => {
+                if let Some((si, sj)) = start.take() {
+                    hunks.push(Hunk {
+                        old: si..i,
+                        new: sj..j,
+                    });
+                }
+                i += 1;
+                j += 1;
+            }
+            Step::Delete => {
+                start.get_or_insert((i, j));
+                i += 1;
+            }
+            Step::Insert => {
+                start.get_or_insert((i, j));
+                j += 1;
+            }
+        }
+    }
+    if let Some((si, sj)) = start {
+        hunks.push(Hunk {
+            old: si..i,
+            new: sj..j,
+        });
+    }
+    hunks
+}
+
+// where line `y` of the old text is in the new one, a changed line goes to
+// the line in the place of it
+pub fn map_line(hunks: &[Hunk], y: usize) -> usize {
+    let mut shift = 0isize;
+    for h in hunks {
+        if y >= h.old.end {
+            shift += h.new.len() as isize - h.old.len() as isize;
+        } else if y >= h.old.start {
+            return h.new.start + (y - h.old.start).min(h.new.len().saturating_sub(1));
+        } else {
+            break;
+        }
+    }
+    (y as isize + shift).max(0) as usize
+}
@@ -0,0 +1,325 @@
+#![allow(dead_code)]
+// two panes side by side showing the differences of their buffers: the lines
+// are aligned with filler rows where one side has fewer, both panes scroll
+// together and the hunks can be copied from one side to the other
+use crate::buffer::BufferManager;
+use crate::diff::{Hunk, diff_lines};
+use crate::error::LayoutError;
+use crate::layout::layout_manager::LayoutManager;
+use crate::layout::tree::{LayoutNode, collect_panes};
+use crate::wrap::LINE_NUMBER_WIDTH;
+use ratatui::style::Color;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Old,
+    New,
+}
+
+impl Side {
+    pub fn other(&self) -> Side {
+        match self {
+            Side::Old => Side::New,
+            Side::New => Side::Old,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffRow {
+    Line(usize),
+    // the other side has a line here
+    Filler,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl DiffKind {
+    pub fn color(&self) -> Color {
+        match self {
+            DiffKind::Added => Color::Rgb(38, 74, 44),
+            DiffKind::Removed => Color::Rgb(84, 40, 44),
+            DiffKind::Changed => Color::Rgb(40, 58, 88),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffView {
+    // the panes and the buffers they show
+    pub old: (usize, usize),
+    pub new: (usize, usize),
+    // the lines of `old` to the lines of `new`
+    pub hunks: Vec<Hunk>,
+    // the first row shown in both panes
+    pub top: usize,
+}
+
+// the line a hunk is shown on in a side, lines deleted from it are marked on
+// the line above, the fillers are drawn under it
+fn mark(range: &Range<usize>) -> usize {
+    match range.is_empty() {
+        true => range.start.saturating_sub(1),
+        false => range.start,
+    }
+}
+
+impl DiffView {
+    pub fn new(old: (usize, usize), new: (usize, usize)) -> Self {
+        Self {
+            old,
+            new,
+            hunks: vec![],
+            top: 0,
+        }
+    }
+
+    pub fn side(&self, pane: usize) -> Option<Side> {
+        match pane {
+            p if p == self.old.0 => Some(Side::Old),
+            p if p == self.new.0 => Some(Side::New),
+            _ => None,
+        }
+    }
+
+    fn pane(&self, side: Side) -> (usize, usize) {
+        match side {
+            Side::Old => self.old,
+            Side::New => self.new,
+        }
+    }
+
+    // the lines of the hunk in this side and in the other one
+    fn ranges<'a>(&self, side: Side, h: &'a Hunk) -> (&'a Range<usize>, &'a Range<usize>) {
+        match side {
+            Side::Old => (&h.old, &h.new),
+            Side::New => (&h.new, &h.old),
+        }
+    }
+
+    // the rows of a side with `len` lines, the same number for both sides
+    pub fn rows(&self, side: Side, len: usize) -> Vec<DiffRow> {
+        let mut rows = vec![];
+        let mut y = 0;
+        for h in &self.hunks {
+            let (mine, theirs) = self.ranges(side, h);
+            rows.extend((y..mine.end).map(DiffRow::Line));
+            let fillers = theirs.len().saturating_sub(mine.len());
+            rows.extend(std::iter::repeat_n(DiffRow::Filler, fillers));
+            y = mine.end;
+        }
+        rows.extend((y..len).map(DiffRow::Line));
+        rows
+    }
+
+    // the row line `y` of a side is on
+    pub fn row_of(&self, side: Side, y: usize) -> usize {
+        let fillers: usize = self
+            .hunks
+            .iter()
+            .map(|h| self.ranges(side, h))
+            .take_while(|(mine, _)| mine.end <= y)
+            .map(|(mine, theirs)| theirs.len().saturating_sub(mine.len()))
+            .sum();
+        y + fillers
+    }
+
+    // how line `y` of a side differs from the other side, the lines of a
+    // hunk past the ones they replaced were added or removed
+    pub fn kind(&self, side: Side, y: usize) -> Option<DiffKind> {
+        let h = self
+            .hunks
+            .iter()
+            .find(|h| self.ranges(side, h).0.contains(&y))?;
+        let (mine, theirs) = self.ranges(side, h);
+        if y - mine.start < theirs.len() {
+            Some(DiffKind::Changed)
+        } else {
+            match side {
+                Side::Old => Some(DiffKind::Removed),
+                Side::New => Some(DiffKind::Added),
+            }
+        }
+    }
+
+    // the hunk line `y` of a side is in or marked by
+    pub fn hunk_at(&self, side: Side, y: usize) -> Option<&Hunk> {
+        self.hunks.iter().find(|h| {
+            let mine = self.ranges(side, h).0;
+            mine.contains(&y) || mark(mine) == y
+        })
+    }
+
+    // the line of the next or the previous hunk in a side, around the end,
+    // with its number
+    pub fn next_hunk(&self, side: Side, y: usize, forward: bool) -> Option<(usize, usize)> {
+        let lines: Vec<usize> = self
+            .hunks
+            .iter()
+            .map(|h| mark(self.ranges(side, h).0))
+            .collect();
+        let n = match forward {
+            true => lines.iter().position(|l| *l > y).unwrap_or(0),
+            false => lines
+                .iter()
+                .rposition(|l| *l < y)
+                .unwrap_or(lines.len().checked_sub(1)?),
+        };
+        Some((lines.get(n).copied()?, n))
+    }
+
+    // compare the buffers again and scroll both panes with the focused one,
+    // returns false when a pane was closed or shows another buffer
+    pub fn update(&mut self, bm: &BufferManager, lm: &mut LayoutManager) -> bool {
+        let Some(root) = lm.panes.as_mut() else {
+            return false;
+        };
+        let mut panes = vec![];
+        collect_panes(root, &mut panes);
+        let shown = |(pane, buffer): (usize, usize)| {
+            panes.iter().any(|p| match p {
+                LayoutNode::Pane { id, buffer_id, .. } => *id == pane && *buffer_id == buffer,
+                _ => false,
+            })
+        };
+        if !shown(self.old) || !shown(self.new) {
+            return false;
+        }
+        let (Ok(old), Ok(new)) = (bm.get_buffer(self.old.1), bm.get_buffer(self.new.1)) else {
+            return false;
+        };
+        self.hunks = diff_lines(&old.content, &new.content);
+
+        // the focused pane keeps its cursor in view, the other one follows
+        let current = lm.current_layout;
+        let side = self.side(current);
+        let rect = lm.pane_rects.get(&current).copied();
+        let mut offset_x = None;
+        if let (Some(side), Some(rect)) = (side, rect)
+            && let Some(LayoutNode::Pane {
+                cursor,
+                scroll_offset,
+                scroll_thres,
+                buffer_id,
+                ..
+            }) = root.get_pane_mut(current)
+            && let Ok(buf) = bm.get_buffer(*buffer_id)
+        {
+            // the sizes of the text in `render_buffer`
+            let height = rect.height.saturating_sub(4) as usize;
+            let width = rect.width.saturating_sub(LINE_NUMBER_WIDTH + 2) as usize;
+            let (x, y) = cursor.pos;
+            let y = y.min(buf.content.len() - 1);
+            let row = self.row_of(side, y);
+            let x = buf.get_visual_width_upto(y, x);
+            if row >= (self.top + height).saturating_sub(scroll_thres.1) {
+                self.top = (row + scroll_thres.1 + 1).saturating_sub(height);
+            }
+            if row < self.top {
+                self.top = row;
+            }
+            if x >= (scroll_offset.0 + width).saturating_sub(scroll_thres.0) {
+                scroll_offset.0 = (x + scroll_thres.0 + 1).saturating_sub(width);
+            }
+            if x < scroll_offset.0 {
+                scroll_offset.0 = x;
+            }
+            offset_x = Some(scroll_offset.0);
+        }
+
+        // the first line shown, for what reads the lines around the view
+        for side in [Side::Old, Side::New] {
+            let (pane, buffer) = self.pane(side);
+            let len = bm.get_buffer(buffer).map_or(1, |b| b.content.len());
+            let first = self
+                .rows(side, len)
+                .into_iter()
+                .skip(self.top)
+                .find_map(|r| match r {
+                    DiffRow::Line(y) => Some(y),
+                    DiffRow::Filler => None,
+                })
+                .unwrap_or(len - 1);
+            if let Some(LayoutNode::Pane { scroll_offset, .. }) = root.get_pane_mut(pane) {
+                scroll_offset.1 = first;
+                if let Some(x) = offset_x {
+                    scroll_offset.0 = x;
+                }
+            }
+        }
+        true
+    }
+
+    // put the cursor of the focused pane on the line at screen row `row`,
+    // after a click which didn't know about the fillers
+    pub fn click(&self, bm: &BufferManager, lm: &mut LayoutManager, column: u16, row: u16) {
+        let current = lm.current_layout;
+        let (Some(side), Some(rect)) = (self.side(current), lm.pane_rects.get(&current).copied())
+        else {
+            return;
+        };
+        let Some(LayoutNode::Pane {
+            cursor,
+            buffer_id,
+            scroll_offset,
+            ..
+        }) = lm
+            .panes
+            .as_mut()
+            .and_then(|root| root.get_pane_mut(current))
+        else {
+            return;
+        };
+        let Ok(buf) = bm.get_buffer(*buffer_id) else {
+            return;
+        };
+        let rows = self.rows(side, buf.content.len());
+        let at = self.top + (row.saturating_sub(rect.y + 1)) as usize;
+        // a filler goes to the line above it
+        let y = rows[..(at + 1).min(rows.len())]
+            .iter()
+            .rev()
+            .find_map(|r| match r {
+                DiffRow::Line(y) => Some(*y),
+                DiffRow::Filler => None,
+            })
+            .unwrap_or(0);
+        let col = column.saturating_sub(rect.x + LINE_NUMBER_WIDTH) as usize;
+        cursor.pos = (buf.line_layout(y).x_at_column(col + scroll_offset.0), y);
+    }
+
+    // copy the hunk at line `y` of the focused side into the other side, or
+    // the other way around with `get`, as one step of undo
+    pub fn copy_hunk(
+        &self,
+        bm: &mut BufferManager,
+        side: Side,
+        y: usize,
+        get: bool,
+    ) -> Result<usize, LayoutError> {
+        let h = self.hunk_at(side, y).ok_or(LayoutError::NoEntry)?.clone();
+        let (from, to) = match get {
+            true => (side.other(), side),
+            false => (side, side.other()),
+        };
+        let lines = bm.get_buffer(self.pane(from).1)?.content.clone();
+        let target = bm.get_buffer_mut(self.pane(to).1)?;
+        let (mine, theirs) = self.ranges(to, &h);
+        let op = target.hunk_op(
+            &Hunk {
+                old: mine.clone(),
+                new: theirs.clone(),
+            },
+            &lines,
+        );
+        target.apply_op(op, true)?;
+        Ok(mine.start)
+    }
+}
+
@@ -0,0 +1,86 @@
+#![allow(dead_code)]
+// detecting, decoding and encoding the text of files
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+use std::io;
+
+// guess the encoding from the BOM, then from the content itself
+pub fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((enc, _)) = Encoding::for_bom(bytes) {
+        return enc;
+    }
+    // zero bytes are valid UTF-8, so UTF-16 is checked first
+    if let Some(enc) = detect_utf16(bytes) {
+        return enc;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    if looks_like_shift_jis(bytes) {
+        return SHIFT_JIS;
+    }
+    // every byte is valid in it, as latin-1 it is the last choice
+    WINDOWS_1252
+}
+
+// ASCII text in UTF-16 has a zero byte in every code unit
+fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+    if odd_zeros * 10 >= units * 3 && even_zeros * 10 < units {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 >= units * 3 && odd_zeros * 10 < units {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+// japanese text almost always has kana, which rarely shows up when
+// latin-1 text is read as shift-jis
+fn looks_like_shift_jis(bytes: &[u8]) -> bool {
+    match SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes) {
+        Some(text) => text
+            .chars()
+            .any(|c| matches!(c, '\u{3040}'..='\u{30ff}' | '\u{ff61}'..='\u{ff9f}')),
+        None => false,
+    }
+}
+
+// the BOM is kept as U+FEFF, so the caller can remember it
+pub fn decode(bytes: &[u8], enc: &'static Encoding) -> String {
+    let (text, _) = enc.decode_without_bom_handling(bytes);
+    text.into_owned()
+}
+
+pub fn encode(text: &str, enc: &'static Encoding) -> io::Result<Vec<u8>> {
+    // encoding_rs only decodes UTF-16, so it is written by hand
+    if enc == UTF_16LE {
+        return Ok(text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect());
+    }
+    if enc == UTF_16BE {
+        return Ok(text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect());
+    }
+
+    let (bytes, _, had_errors) = enc.encode(text);
+    if had_errors {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("The text can't be encoded in {}", enc.name()),
+        ));
+    }
+    Ok(bytes.into_owned())
+}
+
+pub fn encoding_for_label(label: &str) -> Option<&'static Encoding> {
+    match label.trim().to_ascii_lowercase().as_str() {
+        "latin1" | "latin-1" => Some(WINDOWS_1252),
+        "sjis" | "shift-jis" => Some(SHIFT_JIS),
+        "utf16" | "utf-16" => Some(UTF_16LE),
+        l => Encoding::for_label(l.as_bytes()),
+    }
+}
@@ -10,6 +10,12 @@ pub enum BufferError {
     NotAFile,
     #[error("Invalid path.")]
     InvalidPath,
+    #[error("Invalid encoding: {0}")]
+    InvalidEncoding(String),
+    #[error("The buffer is read-only.")]
+    ReadOnly,
+    #[error("No comment token for this file type.")]
+    NoCommentToken,
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
 }
@@ -25,13 +31,52 @@ pub enum LayoutError {
     NoNode,
     #[error("Not pane.")]
     NotPane,
+    #[error("No entry in the list.")]
+    NoEntry,
+    // a command run for an edit failed, the text is left as it was
+    #[error("{0}")]
+    CommandFailed(String),
     #[error("Buffer error: {0}")]
     BufferErr(#[from] BufferError),
+    #[error("Option error: {0}")]
+    OptionErr(#[from] OptionError),
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
 }
 
 
+#[derive(Error, Debug)]
+pub enum OptionError {
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+    #[error("Missing value of {0}")]
+    MissingValue(String),
+}
+
+
+#[derive(Error, Debug)]
+pub enum LspError {
+    #[error("No language server for this buffer.")]
+    NoServer,
+    #[error("Unknown language: {0}")]
+    UnknownLanguage(String),
+    #[error("The language server isn't ready yet.")]
+    NotReady,
+    #[error("No code action {0}.")]
+    NoAction(usize),
+    #[error("No location {0}.")]
+    NoLocation(usize),
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Layout error: {0}")]
+    LayoutErr(#[from] LayoutError),
+    #[error("Buffer error: {0}")]
+    BufferErr(#[from] BufferError),
+}
+
+
 #[derive(Error, Debug)]
 pub enum RenderError {
     #[error("Buffer error: {0}")]
@@ -40,6 +85,7 @@ pub enum RenderError {
     LayoutErr(#[from] LayoutError),
     #[error("Render layout error")]
     RenderLayoutError,
+    #[allow(dead_code)]
     #[error("Rect not found")]
     RectNotFound,
     #[error("IO error: {0}")]
@@ -0,0 +1,275 @@
+#![allow(dead_code)]
+// the locations in the output of a build: a pattern matches one whole line,
+// `%f` is the file, `%l` the line, `%c` the column, `%m` the message, `%t`
+// a word like `error` or `warning` and `%*` anything; a line with only the
+// message waits for the line with the location, or the other way round
+use crate::diagnostic::Severity;
+
+// the formats known by name
+pub const BUILTIN: &[(&str, &[&str])] = &[
+    (
+        "gcc",
+        &["%f:%l:%c: %t: %m", "%f:%l: %t: %m", "%f:%l:%c: %m"],
+    ),
+    // the message comes first, the location on a line after it
+    ("rustc", &["%t%*: %m", "%*--> %f:%l:%c"]),
+    // the last `File` line of a traceback, then the exception
+    (
+        "python",
+        &["%*File \"%f\", line %l%*", "%*Error: %*", "%*Exception: %*"],
+    ),
+];
+
+// longer lines aren't matched, they'd take too long
+const MAX_LINE_LEN: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    File,
+    Line,
+    Col,
+    Message,
+    Type,
+    Any,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub source: String,
+    tokens: Vec<Token>,
+}
+
+// what a pattern took from a line
+#[derive(Debug, Default)]
+struct Captures {
+    file: Option<String>,
+    line: Option<usize>,
+    col: Option<usize>,
+    message: Option<String>,
+    severity: Option<Severity>,
+}
+
+// a location found in the output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: String,
+    // (x, y) from 0
+    pub pos: (usize, usize),
+    pub severity: Severity,
+    pub message: String,
+}
+
+fn severity_of(word: &str) -> Option<Severity> {
+    match word.to_lowercase().as_str() {
+        "error" | "fatal" | "e" => Some(Severity::Error),
+        "warning" | "warn" | "w" => Some(Severity::Warning),
+        "note" | "info" | "i" => Some(Severity::Info),
+        "help" | "hint" | "h" => Some(Severity::Hint),
+        _ => None,
+    }
+}
+
+impl Pattern {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut tokens = vec![];
+        let mut chars = source.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                tokens.push(Token::Char(c));
+                continue;
+            }
+            tokens.push(match chars.next() {
+                Some('f') => Token::File,
+                Some('l') => Token::Line,
+                Some('c') => Token::Col,
+                Some('m') => Token::Message,
+                Some('t') => Token::Type,
+                Some('*') => Token::Any,
+                Some('%') => Token::Char('%'),
+                Some(c) => return Err(format!("Unknown item %{} in {}", c, source)),
+                None => return Err(format!("A lone % at the end of {}", source)),
+            });
+        }
+        Ok(Self {
+            source: source.to_string(),
+            tokens,
+        })
+    }
+
+    // the parts of `line`, if the pattern matches all of it
+    fn captures(&self, line: &str) -> Option<Captures> {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.len() > MAX_LINE_LEN {
+            return None;
+        }
+        let mut spans = vec![];
+        if !match_at(&self.tokens, &chars, 0, &mut spans) {
+            return None;
+        }
+        let mut caps = Captures::default();
+        for (token, start, end) in spans {
+            let text: String = chars[start..end].iter().collect();
+            match token {
+                Token::File => caps.file = Some(text.trim().to_string()),
+                Token::Line => caps.line = text.parse().ok(),
+                Token::Col => caps.col = text.parse().ok(),
+                Token::Message => caps.message = Some(text.trim().to_string()),
+                Token::Type => caps.severity = severity_of(&text),
+                _ => {}
+            }
+        }
+        Some(caps)
+    }
+
+    fn has(&self, token: Token) -> bool {
+        self.tokens.contains(&token)
+    }
+}
+
+// whether `tokens` match `chars` from `i` to the end, the spans of the
+// captures are pushed into `spans`
+fn match_at(
+    tokens: &[Token],
+    chars: &[char],
+    i: usize,
+    spans: &mut Vec<(Token, usize, usize)>,
+) -> bool {
+    let Some((&token, rest)) = tokens.split_first() else {
+        return i == chars.len();
+    };
+    let try_end = |end: usize, spans: &mut Vec<(Token, usize, usize)>| {
+        spans.push((token, i, end));
+        if match_at(rest, chars, end, spans) {
+            return true;
+        }
+        spans.pop();
+        false
+    };
+    match token {
+        Token::Char(c) => chars.get(i) == Some(&c) && match_at(rest, chars, i + 1, spans),
+        // numbers and words as long as they go, then shorter
+        Token::Line | Token::Col | Token::Type => {
+            let run = chars[i..]
+                .iter()
+                .take_while(|c| match token {
+                    Token::Type => c.is_ascii_alphabetic(),
+                    _ => c.is_ascii_digit(),
+                })
+                .count();
+            (1..=run).rev().any(|n| try_end(i + n, spans))
+        }
+        // the rest as short as they can be, a file or a message isn't empty
+        _ => {
+            let min = if token == Token::Any { 0 } else { 1 };
+            (i + min..=chars.len()).any(|end| try_end(end, spans))
+        }
+    }
+}
+
+// the patterns of the builtin formats named in `spec`, like `rustc,gcc`
+pub fn parse_formats(spec: &str) -> Result<Vec<Pattern>, String> {
+    let mut patterns = vec![];
+    for name in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (_, sources) = BUILTIN
+            .iter()
+            .find(|(n, _)| *n == name)
+            .ok_or_else(|| format!("Unknown error format: {}", name))?;
+        for source in sources.iter() {
+            patterns.push(Pattern::parse(source)?);
+        }
+    }
+    Ok(patterns)
+}
+
+// the formats for the output of `command`, all of them if it's unknown
+pub fn formats_for(command: &str) -> Vec<Pattern> {
+    let program = command
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let spec = match program {
+        "cargo" | "rustc" => "rustc",
+        "make" | "gcc" | "g++" | "cc" | "c++" | "clang" | "clang++" | "ninja" => "gcc",
+        p if p.starts_with("python") || p == "pytest" => "python",
+        _ => "gcc,rustc,python",
+    };
+    parse_formats(spec).unwrap_or_default()
+}
+
+// reads the output line by line, a location is complete once its file, its
+// line and its message were seen
+pub struct Parser {
+    pub patterns: Vec<Pattern>,
+    message: Option<(String, Severity)>,
+    location: Option<(String, (usize, usize))>,
+}
+
+impl Parser {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        Self {
+            patterns,
+            message: None,
+            location: None,
+        }
+    }
+
+    pub fn feed(&mut self, line: &str) -> Option<Location> {
+        let line = line.trim_end();
+        let (pattern, caps) = self.patterns.iter().find_map(|p| {
+            let caps = p.captures(line)?;
+            // a word which isn't a severity means it's some other line
+            (!p.has(Token::Type) || caps.severity.is_some()).then_some((p, caps))
+        })?;
+        let severity = caps.severity.unwrap_or(Severity::Error);
+        let location = match (caps.file, caps.line) {
+            (Some(file), Some(y)) => {
+                let x = caps.col.unwrap_or(1).saturating_sub(1);
+                Some((file, (x, y.saturating_sub(1))))
+            }
+            _ => None,
+        };
+        // a pattern without a file or a message is the message as a whole
+        let message = match caps.message {
+            Some(m) => Some(m),
+            None if !pattern.has(Token::File) => Some(line.trim().to_string()),
+            None => None,
+        };
+        match (location, message) {
+            (Some((path, pos)), Some(message)) => Some(Location {
+                path,
+                pos,
+                severity,
+                message,
+            }),
+            (Some((path, pos)), None) => match self.message.take() {
+                Some((message, severity)) => Some(Location {
+                    path,
+                    pos,
+                    severity,
+                    message,
+                }),
+                None => {
+                    self.location = Some((path, pos));
+                    None
+                }
+            },
+            (None, Some(message)) => match self.location.take() {
+                Some((path, pos)) => Some(Location {
+                    path,
+                    pos,
+                    severity,
+                    message,
+                }),
+                None => {
+                    self.message = Some((message, severity));
+                    None
+                }
+            },
+            (None, None) => None,
+        }
+    }
+}
@@ -0,0 +1,175 @@
+#![allow(dead_code)]
+// everything the editor reacts to comes through one channel: terminal input,
+// timers, changed files and the results of background jobs
+use ratatui::crossterm::event::{self, Event};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub enum AppEvent {
+    Input(Event),
+    Timer(TimerKind),
+    // a watched file was changed, replaced or deleted
+    FileChanged(PathBuf),
+    JobDone {
+        id: usize,
+        output: JobOutput,
+    },
+    // the output of a command run by `make`, a few lines at a time, then its
+    // exit code once the pipe is closed, none when it was killed
+    JobLines {
+        id: usize,
+        lines: Vec<String>,
+    },
+    JobExited {
+        id: usize,
+        code: Option<i32>,
+    },
+    // a message of a language server, by the id of its process
+    Lsp {
+        server: usize,
+        message: Value,
+    },
+    LspExited(usize),
+    // the output of the program in a terminal pane, then its end
+    #[cfg(unix)]
+    TerminalOutput {
+        id: usize,
+        bytes: Vec<u8>,
+    },
+    #[cfg(unix)]
+    TerminalExited(usize),
+    // the input reader is gone, nothing more can happen
+    InputClosed,
+}
+
+pub type JobOutput = Result<String, String>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimerKind {
+    // drop the expired popups
+    Popups,
+    // write the swap files of modified buffers
+    Autosave,
+    CursorBlink,
+    // take the lines of large files indexed in the background
+    LargeFiles,
+    // compare the buffers with the HEAD of their repository
+    Git,
+}
+
+struct Timer {
+    kind: TimerKind,
+    interval: Duration,
+    next: Instant,
+}
+
+pub struct EventLoop {
+    tx: Sender<AppEvent>,
+    rx: Receiver<AppEvent>,
+    timers: Vec<Timer>,
+    job_counter: usize,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            tx,
+            rx,
+            timers: vec![],
+            job_counter: 1,
+        }
+    }
+
+    // for the subsystems which post events from their own threads
+    pub fn sender(&self) -> Sender<AppEvent> {
+        self.tx.clone()
+    }
+
+    // read the terminal input in a thread, so waiting for it doesn't block timers
+    pub fn spawn_input_reader(&self) {
+        let tx = self.sender();
+        thread::spawn(move || {
+            loop {
+                match event::read() {
+                    Ok(ev) => {
+                        if tx.send(AppEvent::Input(ev)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => {
+                        let _ = tx.send(AppEvent::InputClosed);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    // fire `kind` every `interval`, a timer of the same kind is replaced
+    pub fn add_timer(&mut self, kind: TimerKind, interval: Duration) {
+        self.remove_timer(kind);
+        self.timers.push(Timer {
+            kind,
+            interval,
+            next: Instant::now() + interval,
+        });
+    }
+
+    pub fn remove_timer(&mut self, kind: TimerKind) {
+        self.timers.retain(|t| t.kind != kind);
+    }
+
+    // start the interval of `kind` over
+    pub fn reset_timer(&mut self, kind: TimerKind) {
+        if let Some(timer) = self.timers.iter_mut().find(|t| t.kind == kind) {
+            timer.next = Instant::now() + timer.interval;
+        }
+    }
+
+    // run `job` in a thread, its output comes back as `AppEvent::JobDone`
+    pub fn spawn_job<F>(&mut self, job: F) -> usize
+    where
+        F: FnOnce() -> JobOutput + Send + 'static,
+    {
+        let id = self.job_counter;
+        self.job_counter += 1;
+        let tx = self.sender();
+        thread::spawn(move || {
+            let _ = tx.send(AppEvent::JobDone { id, output: job() });
+        });
+        id
+    }
+
+    // wait for the next event, the timers due are fired first
+    pub fn next(&mut self) -> AppEvent {
+        loop {
+            let now = Instant::now();
+            if let Some(timer) = self.timers.iter_mut().min_by_key(|t| t.next) {
+                if timer.next <= now {
+                    // skip the ticks missed while busy
+                    while timer.next <= now {
+                        timer.next += timer.interval;
+                    }
+                    return AppEvent::Timer(timer.kind);
+                }
+                match self.rx.recv_timeout(timer.next - now) {
+                    Ok(ev) => return ev,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    // we keep a sender, so this can't happen
+                    Err(RecvTimeoutError::Disconnected) => return AppEvent::InputClosed,
+                }
+            }
+            return self.rx.recv().unwrap_or(AppEvent::InputClosed);
+        }
+    }
+
+    // the events already waiting, without blocking
+    pub fn try_next(&mut self) -> Option<AppEvent> {
+        self.rx.try_recv().ok()
+    }
+}
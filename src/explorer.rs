@@ -0,0 +1,335 @@
+// the sidebar with the tree of the working directory: the directories open
+// and close in place, the files open in the pane focused before it, and they
+// can be created, renamed, moved and deleted after a question in KaoCo
+use crate::git::{ignored_paths, repo_root};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+// the width of the sidebar
+pub const EXPLORER_RATIO: f32 = 0.25;
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    // by the git repository, or in an ignored directory
+    pub ignored: bool,
+}
+
+pub struct Explorer {
+    pub root: PathBuf,
+    // the directories shown open
+    expanded: HashSet<PathBuf>,
+    pub show_hidden: bool,
+    pub show_ignored: bool,
+    // the paths ignored in the repository of `root`, a directory stands for
+    // everything in it; git is asked on the git timer, not for every refresh
+    ignored: HashSet<PathBuf>,
+    // the rows of the tree, read again after every change
+    pub entries: Vec<Entry>,
+    pub selected: usize,
+    // the first row shown
+    pub scroll: usize,
+    // the pane the files open in, the last one focused with a buffer
+    pub last_pane: usize,
+    // the path the question in KaoCo is about
+    pub target: Option<PathBuf>,
+    // the file of `last_pane`, highlighted, and the path of its buffer it
+    // was found from
+    pub shown: Option<PathBuf>,
+    shown_from: Option<PathBuf>,
+}
+
+// the repository `dir` is in, or which it is the root of
+fn repo_of(dir: &Path) -> Option<PathBuf> {
+    match dir.join(".git").exists() {
+        true => Some(dir.to_path_buf()),
+        false => repo_root(dir),
+    }
+}
+
+impl Explorer {
+    pub fn new() -> Self {
+        let root = env::current_dir()
+            .and_then(fs::canonicalize)
+            .unwrap_or_else(|_| PathBuf::from("."));
+        Self {
+            root,
+            expanded: HashSet::new(),
+            show_hidden: false,
+            show_ignored: false,
+            ignored: HashSet::new(),
+            entries: vec![],
+            selected: 0,
+            scroll: 0,
+            last_pane: 1,
+            target: None,
+            shown: None,
+            shown_from: None,
+        }
+    }
+
+    // read the tree again, the selection stays on its path
+    pub fn refresh(&mut self) {
+        let selected = self.selected_path();
+        self.entries.clear();
+        let root = self.root.clone();
+        self.read_dir(&root, 0, false);
+        if let Some(i) = selected.and_then(|path| self.position(&path)) {
+            self.selected = i;
+        }
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+    }
+
+    // the ignored paths and the tree again
+    pub fn reload(&mut self) {
+        self.load_ignored();
+        self.refresh();
+    }
+
+    // ask git for the ignored paths again, returns whether they changed
+    pub fn load_ignored(&mut self) -> bool {
+        let ignored: HashSet<PathBuf> = repo_of(&self.root)
+            .map(|repo| ignored_paths(&repo).into_iter().collect())
+            .unwrap_or_default();
+        let changed = ignored != self.ignored;
+        self.ignored = ignored;
+        changed
+    }
+
+    // highlight the file at `path`, it's only looked up again when the path
+    // changed or wasn't found
+    pub fn show(&mut self, path: Option<&Path>) {
+        if self.shown.is_some() && self.shown_from.as_deref() == path {
+            return;
+        }
+        self.shown_from = path.map(Path::to_path_buf);
+        self.shown = path.and_then(|path| fs::canonicalize(path).ok());
+    }
+
+    // the entries of `dir` and of its open directories, the directories
+    // first
+    fn read_dir(&mut self, dir: &Path, depth: usize, ignored: bool) {
+        let Ok(read) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<Entry> = read
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let path = e.path();
+                let name = e.file_name().to_string_lossy().into_owned();
+                let ignored = ignored || self.ignored.contains(&path);
+                let shown =
+                    (self.show_hidden || !name.starts_with('.')) && (self.show_ignored || !ignored);
+                shown.then(|| Entry {
+                    is_dir: path.is_dir(),
+                    path,
+                    name,
+                    depth,
+                    ignored,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        for entry in entries {
+            let open = entry.is_dir && self.expanded.contains(&entry.path);
+            let (path, ignored) = (entry.path.clone(), entry.ignored);
+            self.entries.push(entry);
+            if open {
+                self.read_dir(&path, depth + 1, ignored);
+            }
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn selected_path(&self) -> Option<PathBuf> {
+        self.selected_entry().map(|e| e.path.clone())
+    }
+
+    pub fn position(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|e| e.path == path)
+    }
+
+    pub fn is_open(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    // open or close the selected directory
+    pub fn toggle(&mut self) {
+        let Some(entry) = self.selected_entry().filter(|e| e.is_dir) else {
+            return;
+        };
+        let path = entry.path.clone();
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path);
+        }
+        self.refresh();
+    }
+
+    // open the selected directory, or go into it when it's open already
+    pub fn expand(&mut self) {
+        match self.selected_entry() {
+            Some(e) if e.is_dir && self.is_open(&e.path) => self.move_selection(1),
+            Some(e) if e.is_dir => self.toggle(),
+            _ => {}
+        }
+    }
+
+    // close the selected directory, or go to the one it's in
+    pub fn collapse(&mut self) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        if entry.is_dir && self.is_open(&entry.path) {
+            self.toggle();
+        } else if let Some(i) = entry.path.parent().and_then(|p| self.position(p)) {
+            self.selected = i;
+        }
+    }
+
+    // open the directories down to `path` and select it
+    pub fn reveal(&mut self, path: &Path) {
+        if let Ok(rel) = path.strip_prefix(&self.root) {
+            let mut dir = self.root.clone();
+            for part in rel.parent().into_iter().flat_map(|p| p.components()) {
+                dir.push(part);
+                self.expanded.insert(dir.clone());
+            }
+        }
+        self.refresh();
+        if let Some(i) = self.position(path) {
+            self.selected = i;
+        }
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        self.refresh();
+    }
+
+    pub fn toggle_ignored(&mut self) {
+        self.show_ignored = !self.show_ignored;
+        self.refresh();
+    }
+
+    // the directory new files go into: the selected one, or the one the
+    // selected file is in
+    pub fn base_dir(&self) -> PathBuf {
+        match self.selected_entry() {
+            Some(e) if e.is_dir => e.path.clone(),
+            Some(e) => e.path.parent().unwrap_or(&self.root).to_path_buf(),
+            None => self.root.clone(),
+        }
+    }
+
+    // the path from the root, for the questions and the messages
+    pub fn relative(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.root) {
+            Ok(rel) if rel.as_os_str().is_empty() => ".".to_string(),
+            Ok(rel) => rel.to_string_lossy().into_owned(),
+            Err(_) => path.to_string_lossy().into_owned(),
+        }
+    }
+
+    // keep the selected row among the `height` rows shown
+    pub fn update_scroll(&mut self, height: usize) {
+        let height = height.max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        }
+        if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        self.scroll = self.scroll.min(self.entries.len().saturating_sub(height));
+    }
+
+    // create `name` in `dir`, a directory if it ends with `/`, the
+    // directories in between are created too
+    pub fn create(&mut self, dir: &Path, name: &str) -> io::Result<PathBuf> {
+        let path = dir.join(name.trim_end_matches('/'));
+        if path.exists() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                "it exists already",
+            ));
+        }
+        if name.ends_with('/') {
+            fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::File::create_new(&path)?;
+        }
+        self.reveal(&path);
+        Ok(path)
+    }
+
+    // rename or move `from` to `to`, which mustn't exist
+    pub fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        if to.exists() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                "it exists already",
+            ));
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(from, to)?;
+        // the open directories under it stay open
+        let moved: Vec<PathBuf> = self
+            .expanded
+            .iter()
+            .filter(|p| p.starts_with(from))
+            .cloned()
+            .collect();
+        for path in moved {
+            self.expanded.remove(&path);
+            if let Ok(rest) = path.strip_prefix(from) {
+                self.expanded.insert(to.join(rest));
+            }
+        }
+        self.reveal(to);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, path: &Path) -> io::Result<()> {
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        self.expanded.retain(|p| !p.starts_with(path));
+        self.refresh();
+        Ok(())
+    }
+
+    // where `from` goes for the answer of a move: a path from the root, into
+    // it when it's a directory
+    pub fn destination(&self, from: &Path, answer: &str) -> PathBuf {
+        let to = self.root.join(answer);
+        match (to.is_dir(), from.file_name()) {
+            (true, Some(name)) => to.join(name),
+            _ => to,
+        }
+    }
+}
@@ -0,0 +1,255 @@
+#![allow(dead_code)]
+// writing files to the disk without leaving them half written
+use crate::options::BackupMode;
+use crate::utils::format_timestamp;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+// follow the symlinks, so the link itself is kept and its target is replaced
+pub fn resolve_target(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// write into a temp file in the same directory, then rename it over the target
+pub fn atomic_write(path: &Path, data: &[u8]) -> io::Result<()> {
+    atomic_write_with(path, |w| w.write_all(data))
+}
+
+// like `atomic_write`, but the content is written by `write` piece by piece
+pub fn atomic_write_with<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    write_replacing(path, None, write)
+}
+
+// like `atomic_write_with`, for copies only the owner may read whatever the
+// file they're of allows
+pub fn atomic_write_private<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    write_replacing(path, Some(0o600), write)
+}
+
+// the temp file gets `mode`, or the mode and the owner of the file it
+// replaces, before anything is written into it
+fn write_replacing<F>(path: &Path, mode: Option<u32>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let target = resolve_target(path);
+    let dir = match target.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("untitled");
+    let tmp_path = dir.join(format!(".{}.smile-{}.tmp", name, std::process::id()));
+
+    let meta = fs::metadata(&target).ok();
+    let tmp = match create_tmp(&tmp_path, mode.or(meta.as_ref().map(file_mode))) {
+        Ok(tmp) => tmp,
+        // the file may be written but not its directory, it is written in
+        // place then
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && meta.is_some() => {
+            return write_in_place(&target, write);
+        }
+        Err(e) => return Err(e),
+    };
+    let keep = match mode {
+        Some(_) => None,
+        None => meta.as_ref(),
+    };
+    let res = write_and_rename(tmp, &target, &tmp_path, keep, mode, write);
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    res?;
+
+    // make the rename itself durable
+    if let Ok(d) = File::open(&dir) {
+        let _ = d.sync_all();
+    }
+    Ok(())
+}
+
+fn write_and_rename<F>(
+    tmp: File,
+    target: &Path,
+    tmp_path: &Path,
+    keep: Option<&Metadata>,
+    mode: Option<u32>,
+    write: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let mut writer = BufWriter::new(tmp);
+    write(&mut writer)?;
+    let tmp = writer.into_inner().map_err(|e| e.into_error())?;
+    tmp.sync_all()?;
+
+    // the owner first, changing it clears the setuid bits
+    if let Some(meta) = keep {
+        keep_owner(&tmp, meta);
+        tmp.set_permissions(meta.permissions())?;
+    } else if let Some(mode) = mode {
+        set_mode(&tmp, mode)?;
+    }
+    fs::rename(tmp_path, target)
+}
+
+// over the old content, for a file in a directory new files can't go in
+fn write_in_place<F>(target: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let file = OpenOptions::new().write(true).truncate(true).open(target)?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+#[cfg(unix)]
+fn create_tmp(path: &Path, mode: Option<u32>) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+    options.open(path)
+}
+
+#[cfg(not(unix))]
+fn create_tmp(path: &Path, _mode: Option<u32>) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+// the permission bits only, the setuid ones come back after the owner
+#[cfg(unix)]
+fn file_mode(meta: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o777
+}
+
+#[cfg(not(unix))]
+fn file_mode(_meta: &Metadata) -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn set_mode(file: &File, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+// the owner and the group of the replaced file, or only the group, as far
+// as the user may give them
+#[cfg(unix)]
+fn keep_owner(file: &File, meta: &Metadata) {
+    use std::os::unix::fs::{MetadataExt, fchown};
+    if fchown(file, Some(meta.uid()), Some(meta.gid())).is_err() {
+        let _ = fchown(file, None, Some(meta.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_file: &File, _meta: &Metadata) {}
+
+// copy the current file on the disk before it gets replaced
+pub fn backup(path: &Path, mode: BackupMode, backup_dir: Option<&Path>) -> io::Result<()> {
+    let target = resolve_target(path);
+    if mode == BackupMode::Off || !target.is_file() {
+        return Ok(());
+    }
+    let backup_path = backup_path_for(&target, mode, backup_dir)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid backup path"))?;
+    if let Some(dir) = backup_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(&target, &backup_path)?;
+    Ok(())
+}
+
+// `file~` or `file.20260101-120000~`, in the backup directory the full path
+// is kept in the name with `%` as separator, so files with the same name
+// don't overwrite each other
+pub fn backup_path_for(
+    target: &Path,
+    mode: BackupMode,
+    backup_dir: Option<&Path>,
+) -> Option<PathBuf> {
+    let name = match backup_dir {
+        Some(_) => target.to_str()?.replace(std::path::MAIN_SEPARATOR, "%"),
+        None => target.file_name()?.to_str()?.to_string(),
+    };
+    let name = match mode {
+        BackupMode::Off => return None,
+        BackupMode::Simple => format!("{}~", name),
+        BackupMode::Timestamp => format!("{}.{}~", name, format_timestamp(SystemTime::now())),
+    };
+    match backup_dir {
+        Some(dir) => Some(dir.join(name)),
+        None => Some(target.with_file_name(name)),
+    }
+}
+
+// asked to the system for the user running smile, so the supplementary
+// groups, the ACLs and the read-only mounts count too
+#[cfg(unix)]
+pub fn is_writable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the path is a valid C string which lives through the call
+    unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), libc::W_OK, libc::AT_EACCESS) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn is_writable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|meta| !meta.permissions().readonly())
+}
+
+// pipe the content into a helper command with the path as its last argument,
+// `sudo -n tee` writes files we have no permission to
+pub fn write_with_helper(path: &Path, data: &[u8], helper: &str) -> io::Result<()> {
+    let mut parts = helper.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No write helper"))?;
+    let mut child = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(if err.is_empty() {
+            format!("{} exited with {}", program, output.status)
+        } else {
+            err
+        }));
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+#![allow(dead_code)]
+// format a buffer with the formatter of its language: the code goes through
+// the command and only the lines it changed are replaced, so the cursors and
+// the undo history stay where they were
+use crate::buffer::Buffer;
+use crate::diff::{Hunk, diff_lines};
+use crate::op::EditOp;
+use crate::shell::{self, output_lines};
+use crate::utils::get_line_len;
+
+impl Buffer {
+    // the formatter of the buffer's language, a `set formatter.<lang>` first
+    pub fn formatter(&self) -> Option<String> {
+        let lang = self.language()?;
+        match self.options.formatters.get(lang.name) {
+            Some(command) if command.trim().is_empty() => None,
+            Some(command) => Some(command.clone()),
+            None => lang.formatter.map(str::to_string),
+        }
+    }
+
+    // run the formatter over the whole buffer, returns the lines it changed,
+    // the buffer is left as it was if it fails
+    pub fn format(&mut self) -> Result<Vec<Hunk>, String> {
+        let (command, input) = self.format_input()?;
+        let output = shell::run(&command, Some(input))?;
+        self.apply_format(&output)
+    }
+
+    // the formatter and the text it gets, for a format run in the background
+    pub fn format_input(&self) -> Result<(String, String), String> {
+        let Some(command) = self.formatter() else {
+            return Err(match self.language() {
+                Some(lang) => format!("No formatter for {}", lang.name),
+                None => "No formatter for this file type".to_string(),
+            });
+        };
+        if self.is_large() {
+            return Err("Large files aren't formatted".to_string());
+        }
+        Ok((command, format!("{}\n", self.content.join("\n"))))
+    }
+
+    // only the lines the `output` of the formatter changed are replaced
+    pub fn apply_format(&mut self, output: &str) -> Result<Vec<Hunk>, String> {
+        let lines = output_lines(output);
+        let hunks = diff_lines(&self.content, &lines);
+        if hunks.is_empty() {
+            return Ok(hunks);
+        }
+        // from the bottom, the ops don't move the lines of the ones before
+        let ops = hunks
+            .iter()
+            .rev()
+            .map(|h| self.hunk_op(h, &lines))
+            .collect();
+        self.apply_op(EditOp::Group(ops), true)
+            .map_err(|e| e.to_string())?;
+        Ok(hunks)
+    }
+
+    // the op putting the lines `h.new` of `lines` in the place of `h.old`
+    pub fn hunk_op(&self, h: &Hunk, lines: &[String]) -> EditOp {
+        let content = &self.content;
+        let text = lines[h.new.clone()].join("\n");
+        let last = content.len() - 1;
+        let line_end = |y: usize| (get_line_len(&content[y]), y);
+        match (h.old.is_empty(), h.new.is_empty()) {
+            (false, false) => EditOp::replace((0, h.old.start), line_end(h.old.end - 1), &text),
+            // the new lines go before line `old.start`, or after the last one
+            (true, _) if h.old.start <= last => {
+                let p = (0, h.old.start);
+                EditOp::replace(p, p, &format!("{}\n", text))
+            }
+            (true, _) => EditOp::replace(line_end(last), line_end(last), &format!("\n{}", text)),
+            // the newline after them goes too, or the one before the last ones
+            (false, true) if h.old.end <= last => {
+                EditOp::replace((0, h.old.start), (0, h.old.end), "")
+            }
+            (false, true) => EditOp::replace(
+                line_end(h.old.start.saturating_sub(1)),
+                line_end(h.old.end - 1),
+                "",
+            ),
+        }
+    }
+}
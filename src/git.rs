@@ -0,0 +1,368 @@
+#![allow(dead_code)]
+// the changes of the buffers against the HEAD of their git repository, for
+// the signs in the gutter: the commit and the branch are read from the files
+// in `.git`, the file at HEAD is taken with `git show` once per commit
+use crate::buffer::{Buffer, BufferManager};
+use crate::diff::{Hunk, diff_lines};
+use crate::op::EditOp;
+use crate::shell::output_lines;
+use ratatui::style::Color;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    // lines were deleted under this one, or above the first line
+    Deleted,
+}
+
+impl LineChange {
+    pub fn sign(&self) -> &'static str {
+        match self {
+            LineChange::Added | LineChange::Modified => "▎",
+            LineChange::Deleted => "▁",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            LineChange::Added => Color::Rgb(150, 240, 105),
+            LineChange::Modified => Color::Rgb(240, 186, 89),
+            LineChange::Deleted => Color::Rgb(240, 105, 105),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GitInfo {
+    // the path of the buffer, the repository isn't looked for again while
+    // it stays the same
+    pub path: PathBuf,
+    pub root: PathBuf,
+    // the path of the file in the repository, with `/`
+    pub rel: String,
+    // none when the HEAD is detached
+    pub branch: Option<String>,
+    // none before the first commit
+    pub commit: Option<String>,
+    // the lines at HEAD, none if the file isn't in it
+    pub head: Option<Vec<String>>,
+    // `head` to the content of the buffer
+    pub hunks: Vec<Hunk>,
+    // the version of the content `hunks` are of
+    pub diffed: Option<u64>,
+}
+
+// the repository `path` is in, the directory holding `.git`
+pub fn repo_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+// the repository of the file `path` and the path of the file in it
+fn find_repo(path: &Path) -> Option<(PathBuf, String)> {
+    let path = fs::canonicalize(path).ok()?;
+    let root = repo_root(&path)?;
+    let rel = path
+        .strip_prefix(&root)
+        .unwrap_or(&path)
+        .to_string_lossy()
+        .replace('\\', "/");
+    Some((root, rel))
+}
+
+// `.git`, or the directory a `.git` file of a worktree points to
+fn git_dir(root: &Path) -> Option<PathBuf> {
+    let dot = root.join(".git");
+    if dot.is_dir() {
+        return Some(dot);
+    }
+    let text = fs::read_to_string(&dot).ok()?;
+    let dir = text.trim().strip_prefix("gitdir:")?.trim();
+    Some(root.join(dir))
+}
+
+// the branch and the commit of the HEAD
+pub fn read_head(root: &Path) -> (Option<String>, Option<String>) {
+    let Some(dir) = git_dir(root) else {
+        return (None, None);
+    };
+    let Ok(head) = fs::read_to_string(dir.join("HEAD")) else {
+        return (None, None);
+    };
+    let head = head.trim();
+    let Some(name) = head.strip_prefix("ref:").map(str::trim) else {
+        return (None, Some(head.to_string()));
+    };
+    let branch = name.strip_prefix("refs/heads/").unwrap_or(name).to_string();
+    // the refs of a worktree are in the main repository
+    let common = match fs::read_to_string(dir.join("commondir")) {
+        Ok(common) => dir.join(common.trim()),
+        Err(_) => dir,
+    };
+    let commit = fs::read_to_string(common.join(name))
+        .ok()
+        .map(|c| c.trim().to_string())
+        .or_else(|| {
+            let packed = fs::read_to_string(common.join("packed-refs")).ok()?;
+            packed.lines().find_map(|line| {
+                let (hash, r) = line.split_once(' ')?;
+                (r == name).then(|| hash.to_string())
+            })
+        });
+    (Some(branch), commit)
+}
+
+// the lines of `rel` at HEAD
+fn show_head(root: &Path, rel: &str) -> Option<Vec<String>> {
+    show(root, &format!("HEAD:{}", rel))
+}
+
+// the lines of `rel` in the index
+fn show_index(root: &Path, rel: &str) -> Option<Vec<String>> {
+    show(root, &format!(":{}", rel))
+}
+
+fn show(root: &Path, object: &str) -> Option<Vec<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .arg("show")
+        .arg(object)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(output_lines(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// the files and the directories ignored in the repository at `root`, an
+// ignored directory is listed alone
+pub fn ignored_paths(root: &Path) -> Vec<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["ls-files", "--others", "--ignored", "--exclude-standard"])
+        .args(["--directory", "-z"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        _ => return vec![],
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|p| !p.is_empty())
+        .map(|p| root.join(p.trim_end_matches('/')))
+        .collect()
+}
+
+// the line a hunk is shown on, a deletion is marked on the line above it
+pub fn hunk_line(h: &Hunk) -> usize {
+    match h.new.is_empty() {
+        true => h.new.start.saturating_sub(1),
+        false => h.new.start,
+    }
+}
+
+// the sign of line `y`, the lines of a hunk longer than the lines it
+// replaced are added
+pub fn line_change(hunks: &[Hunk], y: usize) -> Option<LineChange> {
+    let i = hunks.partition_point(|h| h.new.end.max(hunk_line(h) + 1) <= y);
+    let h = hunks.get(i)?;
+    if h.new.contains(&y) {
+        match y - h.new.start < h.old.len() {
+            true => Some(LineChange::Modified),
+            false => Some(LineChange::Added),
+        }
+    } else if h.new.is_empty() && hunk_line(h) == y {
+        Some(LineChange::Deleted)
+    } else {
+        None
+    }
+}
+
+impl Buffer {
+    pub fn git_hunks(&self) -> &[Hunk] {
+        self.git.as_ref().map_or(&[], |git| &git.hunks)
+    }
+
+    // the hunk line `y` is in or marked by
+    pub fn hunk_at(&self, y: usize) -> Option<&Hunk> {
+        self.git_hunks()
+            .iter()
+            .find(|h| h.new.contains(&y) || hunk_line(h) == y)
+    }
+
+    // the line of the next or the previous hunk, around the end
+    pub fn next_hunk(&self, y: usize, forward: bool) -> Option<usize> {
+        let lines: Vec<usize> = self.git_hunks().iter().map(hunk_line).collect();
+        match forward {
+            true => lines.iter().find(|l| **l > y).or(lines.first()),
+            false => lines.iter().rev().find(|l| **l < y).or(lines.last()),
+        }
+        .copied()
+    }
+
+    // the hunk as lines of `-` and `+`
+    pub fn hunk_preview(&self, h: &Hunk) -> String {
+        let head = self.git.as_ref().and_then(|g| g.head.as_ref());
+        let mut text = format!(
+            "@@ -{},{} +{},{} @@",
+            h.old.start + 1,
+            h.old.len(),
+            h.new.start + 1,
+            h.new.len()
+        );
+        for line in head.map_or(&[][..], |lines| &lines[h.old.clone()]) {
+            text.push_str(&format!("\n-{}", line));
+        }
+        for line in &self.content[h.new.clone()] {
+            text.push_str(&format!("\n+{}", line));
+        }
+        text
+    }
+
+    // the op putting the lines of HEAD back in the place of the hunk
+    pub fn revert_hunk_op(&self, h: &Hunk) -> Option<EditOp> {
+        let head = self.git.as_ref()?.head.as_ref()?;
+        let back = Hunk {
+            old: h.new.clone(),
+            new: h.old.clone(),
+        };
+        Some(self.hunk_op(&back, head))
+    }
+
+    // add the hunk to the index, as a patch without context applied by git;
+    // the index may differ from HEAD, so the patch is the change of the index
+    // covering the hunk
+    pub fn stage_hunk(&self, h: &Hunk) -> Result<(), String> {
+        let git = self.git.as_ref().ok_or("Not in a git repository")?;
+        let index = show_index(&git.root, &git.rel).ok_or("The file isn't in the index")?;
+        let line = hunk_line(h);
+        let h = diff_lines(&index, &self.content)
+            .into_iter()
+            .find(|i| (i.new.start < h.new.end && h.new.start < i.new.end) || hunk_line(i) == line)
+            .ok_or("The hunk is already staged")?;
+        // a side without lines is numbered by the line before it
+        let start = |r: &std::ops::Range<usize>| match r.is_empty() {
+            true => r.start,
+            false => r.start + 1,
+        };
+        let mut patch = format!(
+            "--- a/{0}\n+++ b/{0}\n@@ -{1},{2} +{3},{4} @@\n",
+            git.rel,
+            start(&h.old),
+            h.old.len(),
+            start(&h.new),
+            h.new.len()
+        );
+        for line in &index[h.old.clone()] {
+            patch.push_str(&format!("-{}\n", line));
+        }
+        for line in &self.content[h.new.clone()] {
+            patch.push_str(&format!("+{}\n", line));
+        }
+
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&git.root)
+            .args(["apply", "--cached", "--unidiff-zero", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run git: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(patch.as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr);
+            return Err(err.lines().next().unwrap_or("git apply failed").to_string());
+        }
+        Ok(())
+    }
+
+    // the hunks against the lines of HEAD, returns whether they changed
+    pub fn update_git_hunks(&mut self) -> bool {
+        let Some(git) = self.git.as_mut() else {
+            return false;
+        };
+        // the content is the one they were made of
+        if git.diffed == Some(self.version) {
+            return false;
+        }
+        git.diffed = Some(self.version);
+        let hunks = match &git.head {
+            Some(head) => diff_lines(head, &self.content),
+            None => vec![],
+        };
+        let changed = hunks != git.hunks;
+        git.hunks = hunks;
+        changed
+    }
+
+    // look at the HEAD of the repository again, the file is read from it if
+    // the commit changed, returns whether anything shown changed
+    pub fn refresh_git(&mut self) -> bool {
+        let path = match (&self.path, &self.large) {
+            (Some(path), None) => path,
+            _ => return self.git.take().is_some(),
+        };
+        let found = match &self.git {
+            Some(git) if git.path == *path => Some((git.root.clone(), git.rel.clone())),
+            _ => find_repo(path),
+        };
+        let Some((root, rel)) = found else {
+            return self.git.take().is_some();
+        };
+        let (branch, commit) = read_head(&root);
+        let same = self
+            .git
+            .as_ref()
+            .is_some_and(|g| g.root == root && g.commit == commit);
+        if same {
+            let git = self.git.as_mut().unwrap();
+            let renamed = git.branch != branch;
+            git.branch = branch;
+            return self.update_git_hunks() || renamed;
+        }
+        let head = commit.as_ref().and_then(|_| show_head(&root, &rel));
+        self.git = Some(GitInfo {
+            path: path.clone(),
+            root,
+            rel,
+            branch,
+            commit,
+            head,
+            hunks: vec![],
+            diffed: None,
+        });
+        self.update_git_hunks();
+        true
+    }
+}
+
+impl BufferManager {
+    // the git state of all the buffers, returns whether any changed
+    pub fn refresh_git(&mut self) -> bool {
+        let mut changed = false;
+        for buf in self.buffers.values_mut() {
+            changed |= buf.refresh_git();
+        }
+        changed
+    }
+}
@@ -0,0 +1,40 @@
+#![allow(dead_code)]
+// the indent of the lines, for Enter, the closers and the indent commands
+use crate::language::Language;
+use crate::options::BufferOptions;
+use crate::pairs::closer_of;
+
+// the blank head of `line`
+pub fn leading_whitespace(line: &str) -> &str {
+    let end = line.len() - line.trim_start_matches([' ', '\t']).len();
+    &line[..end]
+}
+
+// one level of indent
+pub fn indent_unit(opts: &BufferOptions) -> String {
+    if opts.expand_tab {
+        " ".repeat(opts.tab_width)
+    } else {
+        "\t".to_string()
+    }
+}
+
+// the bracket `text` ends with if the next line should be indented deeper
+pub fn opens_block(text: &str, lang: Option<&Language>) -> Option<char> {
+    let last = text.trim_end().chars().last()?;
+    if closer_of(last).is_some() || (last == ':' && lang.is_some_and(|l| l.colon_opens_block)) {
+        Some(last)
+    } else {
+        None
+    }
+}
+
+// bytes to remove from the head of `line` to take one level of indent away
+pub fn outdent_len(line: &str, opts: &BufferOptions) -> usize {
+    let indent = leading_whitespace(line);
+    if indent.starts_with('\t') {
+        return 1;
+    }
+    let spaces = indent.len() - indent.trim_start_matches(' ').len();
+    spaces.min(opts.tab_width.max(1))
+}
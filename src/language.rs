@@ -0,0 +1,161 @@
+#![allow(dead_code)]
+// what the editor knows about the languages, picked by the file extension
+use crate::buffer::Buffer;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct Language {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    // a line ending with `:` opens a block, like in python
+    pub colon_opens_block: bool,
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    // the command of the language server, `lsp <lang> <command>` sets another
+    pub language_server: Option<&'static str>,
+    // reads the code on stdin and writes it formatted on stdout,
+    // `set formatter.<lang>=<command>` sets another
+    pub formatter: Option<&'static str>,
+}
+
+pub static LANGUAGES: &[Language] = &[
+    Language {
+        name: "rust",
+        extensions: &["rs"],
+        colon_opens_block: false,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        language_server: Some("rust-analyzer"),
+        formatter: Some("rustfmt --edition 2024"),
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h", "cc", "cpp", "hpp", "cxx"],
+        colon_opens_block: false,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        language_server: Some("clangd"),
+        formatter: Some("clang-format"),
+    },
+    Language {
+        name: "go",
+        extensions: &["go"],
+        colon_opens_block: false,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        language_server: Some("gopls"),
+        formatter: Some("gofmt"),
+    },
+    Language {
+        name: "javascript",
+        extensions: &["js", "jsx", "mjs", "ts", "tsx"],
+        colon_opens_block: false,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        language_server: Some("typescript-language-server --stdio"),
+        formatter: None,
+    },
+    Language {
+        name: "java",
+        extensions: &["java", "kt"],
+        colon_opens_block: false,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        language_server: None,
+        formatter: None,
+    },
+    Language {
+        name: "python",
+        extensions: &["py", "pyw"],
+        colon_opens_block: true,
+        line_comment: Some("#"),
+        block_comment: None,
+        language_server: Some("pylsp"),
+        formatter: Some("black -q -"),
+    },
+    Language {
+        name: "nim",
+        extensions: &["nim"],
+        colon_opens_block: true,
+        line_comment: Some("#"),
+        block_comment: Some(("#[", "]#")),
+        language_server: None,
+        formatter: None,
+    },
+    Language {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh"],
+        colon_opens_block: false,
+        line_comment: Some("#"),
+        block_comment: None,
+        language_server: None,
+        formatter: Some("shfmt"),
+    },
+    Language {
+        name: "toml",
+        extensions: &["toml"],
+        colon_opens_block: false,
+        line_comment: Some("#"),
+        block_comment: None,
+        language_server: None,
+        formatter: None,
+    },
+    Language {
+        name: "json",
+        extensions: &["json"],
+        colon_opens_block: false,
+        line_comment: None,
+        block_comment: None,
+        language_server: None,
+        formatter: Some("jq ."),
+    },
+    Language {
+        name: "lua",
+        extensions: &["lua"],
+        colon_opens_block: false,
+        line_comment: Some("--"),
+        block_comment: Some(("--[[", "]]")),
+        language_server: None,
+        formatter: Some("stylua -"),
+    },
+    Language {
+        name: "sql",
+        extensions: &["sql"],
+        colon_opens_block: false,
+        line_comment: Some("--"),
+        block_comment: Some(("/*", "*/")),
+        language_server: None,
+        formatter: None,
+    },
+    Language {
+        name: "css",
+        extensions: &["css"],
+        colon_opens_block: false,
+        line_comment: None,
+        block_comment: Some(("/*", "*/")),
+        language_server: None,
+        formatter: None,
+    },
+    Language {
+        name: "html",
+        extensions: &["html", "htm", "xml", "svg", "md"],
+        colon_opens_block: false,
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        language_server: None,
+        formatter: None,
+    },
+];
+
+pub fn language_for_path(path: &Path) -> Option<&'static Language> {
+    let ext = path.extension()?.to_str()?;
+    LANGUAGES
+        .iter()
+        .find(|l| l.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+impl Buffer {
+    pub fn language(&self) -> Option<&'static Language> {
+        language_for_path(self.path.as_deref()?)
+    }
+}
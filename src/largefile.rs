@@ -0,0 +1,179 @@
+#![allow(dead_code)]
+// large files are not read at once, a thread indexes the offsets of the lines
+// and only the lines around the panes are read from the disk
+use crate::encoding::detect_encoding;
+use encoding_rs::UTF_8;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const READ_CHUNK: usize = 1 << 20;
+// offsets are sent to the buffer in batches of this size
+const BATCH_LINES: usize = 1 << 16;
+
+pub enum IndexMsg {
+    // start offsets of the next lines, and the number of bytes indexed so far
+    Lines(Vec<u64>, u64),
+    Done,
+    Failed(String),
+}
+
+pub struct LargeFile {
+    // kept open, so the lines can still be read after the file is replaced on save
+    file: File,
+    pub offsets: Vec<u64>,
+    pub loaded: Vec<bool>,
+    pub total: u64,
+    pub indexed: u64,
+    pub crlf: bool,
+    rx: Option<Receiver<IndexMsg>>,
+}
+
+impl LargeFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let total = file.metadata()?.len();
+
+        // look at the head of the file for the BOM and the line ending
+        let mut head = vec![0; 64 * 1024];
+        let n = file.read(&mut head)?;
+        head.truncate(n);
+        let start = if head.starts_with(&[0xef, 0xbb, 0xbf]) {
+            3
+        } else {
+            0
+        };
+        let crlf = head.windows(2).any(|w| w == b"\r\n");
+
+        let rx = spawn_indexer(path, start)?;
+        Ok(Self {
+            file,
+            offsets: vec![start],
+            loaded: vec![false],
+            total,
+            indexed: start,
+            crlf,
+            rx: Some(rx),
+        })
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.offsets.first() == Some(&3)
+    }
+
+    pub fn is_indexing(&self) -> bool {
+        self.rx.is_some()
+    }
+
+    pub fn progress(&self) -> u64 {
+        (self.indexed * 100).checked_div(self.total).unwrap_or(100)
+    }
+
+    // take the offsets found by the indexer, returns the start offsets of the new
+    // lines and whether the indexing is finished
+    pub fn poll(&mut self) -> Result<(Vec<u64>, bool), String> {
+        let rx = match &self.rx {
+            Some(rx) => rx,
+            None => return Ok((vec![], false)),
+        };
+
+        let mut lines = vec![];
+        let mut done = false;
+        while let Ok(msg) = rx.try_recv() {
+            match msg {
+                IndexMsg::Lines(offsets, indexed) => {
+                    lines.extend(offsets);
+                    self.indexed = indexed;
+                }
+                IndexMsg::Done => {
+                    done = true;
+                    break;
+                }
+                IndexMsg::Failed(e) => {
+                    self.rx = None;
+                    return Err(e);
+                }
+            }
+        }
+        if done {
+            self.rx = None;
+            self.indexed = self.total;
+        }
+        Ok((lines, done))
+    }
+
+    // read the line which starts at `offset` from the original file
+    pub fn read_line_at(&mut self, offset: u64) -> io::Result<String> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(&mut self.file);
+        let mut bytes = vec![];
+        reader.read_until(b'\n', &mut bytes)?;
+        if bytes.last() == Some(&b'\n') {
+            bytes.pop();
+            if self.crlf && bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+// the lines are split at the `\n` bytes and read as UTF-8, which only works
+// for UTF-8 files, this looks at the head of the file for the encoding
+pub fn is_utf8(path: &Path) -> io::Result<bool> {
+    let mut head = vec![0; 64 * 1024];
+    let n = File::open(path)?.read(&mut head)?;
+    head.truncate(n);
+    // a character cut at the end of the head
+    if let Err(e) = std::str::from_utf8(&head)
+        && e.error_len().is_none()
+    {
+        head.truncate(e.valid_up_to());
+    }
+    Ok(detect_encoding(&head) == UTF_8)
+}
+
+fn spawn_indexer(path: &Path, start: u64) -> io::Result<Receiver<IndexMsg>> {
+    let mut file = File::open(path)?;
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        if let Err(e) = file.seek(SeekFrom::Start(start)) {
+            let _ = tx.send(IndexMsg::Failed(e.to_string()));
+            return;
+        }
+        let mut buf = vec![0; READ_CHUNK];
+        let mut pos = start;
+        let mut batch = Vec::with_capacity(BATCH_LINES);
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let _ = tx.send(IndexMsg::Failed(e.to_string()));
+                    return;
+                }
+            };
+            for (i, b) in buf[..n].iter().enumerate() {
+                if *b == b'\n' {
+                    batch.push(pos + i as u64 + 1);
+                }
+            }
+            pos += n as u64;
+            if batch.len() >= BATCH_LINES {
+                let lines = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_LINES));
+                if tx.send(IndexMsg::Lines(lines, pos)).is_err() {
+                    // the buffer is gone
+                    return;
+                }
+            }
+        }
+        let _ = tx.send(IndexMsg::Lines(batch, pos));
+        let _ = tx.send(IndexMsg::Done);
+    });
+
+    Ok(rx)
+}
@@ -1,11 +1,20 @@
 use super::tree::*;
 use crate::buffer::*;
+use crate::diff::{Hunk, map_line};
 use crate::error::*;
-use crate::op::EditOp;
+use crate::indent::*;
+use crate::lines::LineEdit;
+use crate::op::{EditOp, text_end};
+use crate::options::PaneOptions;
+use crate::pairs::*;
+use crate::shell::output_lines;
 use crate::utils::{char_to_byte_idx, get_line_len, overlap};
+use crate::wrap::{LINE_NUMBER_WIDTH, text_width, wrap_width, x_at_column};
 use ratatui::layout::Rect;
 use std::collections::HashMap;
+use std::ops::Range;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 pub struct LayoutManager {
     pub pane_rects: HashMap<usize, Rect>,
@@ -51,12 +60,70 @@ impl LayoutManager {
         } else {
             return Err(LayoutError::NoNode);
         };
+        // a pane without a buffer has none to show in the new one
+        if new_buf_id.is_none()
+            && matches!(node.get_pane(target_id), Some(LayoutNode::Content { .. }))
+        {
+            return Err(LayoutError::NotPane);
+        }
         split_current(node, target_id, self.id_counter, new_buf_id, direc, buf_m);
         self.current_layout = self.id_counter;
         self.id_counter += 1;
         Ok(self.id_counter)
     }
 
+    // put a pane showing `content` beside pane `target_id`, it becomes the
+    // current one
+    pub fn split_content(
+        &mut self,
+        target_id: usize,
+        content: PaneContent,
+        direc: SplitDirection,
+    ) -> Result<usize, LayoutError> {
+        let node = self.panes.as_mut().ok_or(LayoutError::NoNode)?;
+        let id = self.id_counter;
+        if !split_node(node, target_id, LayoutNode::Content { id, content }, direc) {
+            return Err(LayoutError::PaneNotFound);
+        }
+        self.current_layout = id;
+        self.id_counter += 1;
+        Ok(id)
+    }
+
+    // put a pane showing `content` on the left of all the others, it becomes
+    // the current one
+    pub fn open_sidebar(&mut self, content: PaneContent, ratio: f32) -> Result<usize, LayoutError> {
+        let root = self.panes.take().ok_or(LayoutError::NoNode)?;
+        let id = self.id_counter;
+        self.panes = Some(LayoutNode::Split {
+            direc: SplitDirection::Vertical,
+            ratio,
+            first: Box::new(LayoutNode::Content { id, content }),
+            second: Box::new(root),
+        });
+        self.current_layout = id;
+        self.id_counter += 1;
+        Ok(id)
+    }
+
+    // the first pane showing `content`
+    pub fn pane_with(&self, content: PaneContent) -> Option<usize> {
+        let mut panes = vec![];
+        collect_panes(self.panes.as_ref()?, &mut panes);
+        panes.into_iter().find_map(|pane| match pane {
+            LayoutNode::Content { id, content: c } if *c == content => Some(*id),
+            _ => None,
+        })
+    }
+
+    // what the current pane shows if it isn't a buffer
+    pub fn current_content(&self) -> Option<PaneContent> {
+        match self.panes.as_ref()?.get_pane(self.current_layout)? {
+            LayoutNode::Content { content, .. } => Some(*content),
+            _ => None,
+        }
+    }
+
     pub fn remove(&mut self, target_id: usize) -> Result<Option<usize>, LayoutError> {
         let node = if let Some(n) = &self.panes {
             n
@@ -166,9 +233,7 @@ impl LayoutManager {
             _ => return Err(LayoutError::NotPane),
         };
 
-        buf_m
-            .get_buffer(buffer_id)
-            .map_err(|e| LayoutError::BufferErr(e))
+        buf_m.get_buffer(buffer_id).map_err(LayoutError::BufferErr)
     }
 
     pub fn get_current_buffer_mut<'a>(
@@ -184,7 +249,7 @@ impl LayoutManager {
 
         buf_m
             .get_buffer_mut(buffer_id)
-            .map_err(|e| LayoutError::BufferErr(e))
+            .map_err(LayoutError::BufferErr)
     }
 
     pub fn change_current_buffer_id(&mut self, id: usize) -> Result<(), LayoutError> {
@@ -200,6 +265,189 @@ impl LayoutManager {
         Ok(())
     }
 
+    // show the buffer in the current pane with the cursor at `pos`
+    pub fn jump_to(
+        &mut self,
+        buf_m: &BufferManager,
+        id: usize,
+        pos: (usize, usize),
+    ) -> Result<(), LayoutError> {
+        let pane = self
+            .get_current_pane_mut()
+            .ok_or(LayoutError::PaneNotFound)?;
+        match pane {
+            LayoutNode::Pane {
+                buffer_id, cursor, ..
+            } => {
+                *buffer_id = id;
+                cursor.pos = pos;
+                cursor.auto_closers.clear();
+                check_cursor_pos(buf_m, &mut cursor.pos, id)?;
+            }
+            _ => return Err(LayoutError::NotPane),
+        };
+        Ok(())
+    }
+
+    // the lines each pane shows, with `margin` lines around them and the cursor
+    pub fn visible_lines(&self, margin: usize) -> Vec<(usize, Range<usize>)> {
+        let mut panes = vec![];
+        if let Some(root) = &self.panes {
+            collect_panes(root, &mut panes);
+        }
+        panes
+            .into_iter()
+            .filter_map(|pane| match pane {
+                LayoutNode::Pane {
+                    id,
+                    buffer_id,
+                    cursor,
+                    scroll_offset,
+                    ..
+                } => {
+                    let height = self.pane_rects.get(id).map_or(0, |r| r.height as usize);
+                    let top = scroll_offset.1.min(cursor.pos.1);
+                    let bottom = (scroll_offset.1 + height).max(cursor.pos.1 + 1);
+                    Some((*buffer_id, top.saturating_sub(margin)..bottom + margin))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // focus the pane under the screen cell and put the cursor on the grapheme
+    // there, returns false if it isn't on the text of a pane
+    pub fn click(
+        &mut self,
+        buf_m: &BufferManager,
+        column: u16,
+        row: u16,
+    ) -> Result<bool, LayoutError> {
+        // a pane without a buffer only takes the focus
+        let root = self.panes.as_ref().ok_or(LayoutError::NoNode)?;
+        let content = self.pane_rects.iter().find(|(id, r)| {
+            column >= r.x
+                && column < r.x + r.width
+                && row >= r.y
+                && row < r.y + r.height
+                && matches!(root.get_pane(**id), Some(LayoutNode::Content { .. }))
+        });
+        if let Some((&pane_id, _)) = content {
+            self.current_layout = pane_id;
+            return Ok(true);
+        }
+        let Some((&pane_id, &rect)) = self.pane_rects.iter().find(|(_, r)| {
+            column >= r.x + LINE_NUMBER_WIDTH
+                && column < r.x + LINE_NUMBER_WIDTH + text_width(**r) as u16
+                && row > r.y
+                // the status bar takes the last two rows
+                && row + 2 < r.y + r.height
+        }) else {
+            return Ok(false);
+        };
+        let root = self.panes.as_mut().ok_or(LayoutError::NoNode)?;
+        let Some(LayoutNode::Pane {
+            cursor,
+            buffer_id,
+            scroll_offset,
+            options,
+            ..
+        }) = root.get_pane_mut(pane_id)
+        else {
+            return Err(LayoutError::NotPane);
+        };
+        let buf = buf_m.get_buffer(*buffer_id)?;
+        let col = (column - rect.x - LINE_NUMBER_WIDTH) as usize;
+        let mut rows_left = (row - rect.y - 1) as usize;
+
+        let pos = match wrap_width(rect, options) {
+            Some(width) => {
+                // walk down the rows of the wrapped lines
+                let mut y = scroll_offset.1;
+                loop {
+                    let segments = buf.wrap_segments(y, width, options);
+                    if rows_left < segments.len() || y + 1 >= buf.content.len() {
+                        let seg = rows_left.min(segments.len() - 1);
+                        let col = if seg > 0 {
+                            col.saturating_sub(options.show_break.width())
+                        } else {
+                            col
+                        };
+                        let last = seg + 1 == segments.len();
+                        let x = x_at_column(&buf.line_layout(y), &segments[seg], last, col);
+                        break (x, y);
+                    }
+                    rows_left -= segments.len();
+                    y += 1;
+                }
+            }
+            None => {
+                let y = (scroll_offset.1 + rows_left).min(buf.content.len() - 1);
+                let x = buf.line_layout(y).x_at_column(col + scroll_offset.0);
+                (x, y)
+            }
+        };
+        cursor.pos = pos;
+        self.current_layout = pane_id;
+        Ok(true)
+    }
+
+    // the first pane which shows buffer `buffer_id`
+    pub fn pane_showing(&self, buffer_id: usize) -> Option<usize> {
+        let mut panes = vec![];
+        collect_panes(self.panes.as_ref()?, &mut panes);
+        panes.into_iter().find_map(|pane| match pane {
+            LayoutNode::Pane {
+                id, buffer_id: b, ..
+            } if *b == buffer_id => Some(*id),
+            _ => None,
+        })
+    }
+
+    // the cursors of the panes showing buffer `buffer_id` stay on their
+    // lines after the lines of `hunks` were replaced
+    pub fn follow_hunks(
+        &mut self,
+        buf_m: &BufferManager,
+        buffer_id: usize,
+        hunks: &[Hunk],
+    ) -> Result<(), LayoutError> {
+        let Some(root) = self.panes.as_mut() else {
+            return Ok(());
+        };
+        let mut panes = vec![];
+        collect_panes(root, &mut panes);
+        let ids: Vec<usize> = panes
+            .into_iter()
+            .filter_map(|pane| match pane {
+                LayoutNode::Pane {
+                id, buffer_id: b, ..
+            } if *b == buffer_id => Some(*id),
+                _ => None,
+            })
+            .collect();
+        for id in ids {
+            if let Some(LayoutNode::Pane { cursor, .. }) = root.get_pane_mut(id) {
+                cursor.pos.1 = map_line(hunks, cursor.pos.1);
+                cursor.auto_closers.clear();
+                check_cursor_pos(buf_m, &mut cursor.pos, buffer_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    // make pane `id` the current one, returns false if there's none
+    pub fn focus(&mut self, id: usize) -> bool {
+        let found = self
+            .panes
+            .as_ref()
+            .is_some_and(|root| root.get_pane(id).is_some());
+        if found {
+            self.current_layout = id;
+        }
+        found
+    }
+
     pub fn contain_id(&self, id: usize) -> bool {
         self.pane_rects.contains_key(&id)
     }
@@ -263,20 +511,29 @@ impl LayoutManager {
     }
 
     pub fn mv_cursor_up(&mut self, buf_m: &mut BufferManager) -> Result<(), LayoutError> {
+        let rect = self.get_current_rect().copied();
         let pane = self
             .get_current_pane_mut()
             .ok_or(LayoutError::PaneNotFound)?;
 
-        let (cursor, buffer_id) = match pane {
+        let (cursor, buffer_id, options) = match pane {
             LayoutNode::Pane {
-                cursor, buffer_id, ..
-            } => (cursor, *buffer_id),
+                cursor,
+                buffer_id,
+                options,
+                ..
+            } => (cursor, *buffer_id, options),
             _ => return Err(LayoutError::NotPane),
         };
 
         let buf = buf_m.get_buffer_mut(buffer_id)?;
 
-        if cursor.pos.1 > 0 {
+        // by rows of the wrapped lines
+        if let Some(width) = rect.and_then(|r| wrap_width(r, options)) {
+            if let Some(pos) = buf.wrapped_row_move(cursor.pos, width, options, true) {
+                cursor.pos = pos;
+            }
+        } else if cursor.pos.1 > 0 {
             cursor.pos.1 -= 1;
             cursor.pos.0 = cursor.pos.0.min(get_line_len(&buf.content[cursor.pos.1]));
         }
@@ -287,20 +544,29 @@ impl LayoutManager {
     pub fn mv_cursor_down(&mut self, buf_m: &mut BufferManager) -> Result<(), LayoutError> {
         // press down can add new line
         let freemod = false;
+        let rect = self.get_current_rect().copied();
         let pane = self
             .get_current_pane_mut()
             .ok_or(LayoutError::PaneNotFound)?;
 
-        let (cursor, buffer_id) = match pane {
+        let (cursor, buffer_id, options) = match pane {
             LayoutNode::Pane {
-                cursor, buffer_id, ..
-            } => (cursor, *buffer_id),
+                cursor,
+                buffer_id,
+                options,
+                ..
+            } => (cursor, *buffer_id, options),
             _ => return Err(LayoutError::NotPane),
         };
 
         let buf = buf_m.get_buffer_mut(buffer_id)?;
 
-        if cursor.pos.1 + 1 < buf.content.len() {
+        // by rows of the wrapped lines
+        let wrap = rect.and_then(|r| wrap_width(r, options));
+        let row_move = wrap.map(|width| buf.wrapped_row_move(cursor.pos, width, options, false));
+        if let Some(Some(pos)) = row_move {
+            cursor.pos = pos;
+        } else if cursor.pos.1 + 1 < buf.content.len() {
             cursor.pos.1 += 1;
             cursor.pos.0 = cursor.pos.0.min(get_line_len(&buf.content[cursor.pos.1]));
         } else if freemod {
@@ -433,6 +699,60 @@ impl LayoutManager {
     }
 
     pub fn mv_cursor_tail(&mut self, buf_m: &mut BufferManager) -> Result<(), LayoutError> {
+        let rect = self.get_current_rect().copied();
+        let pane = self
+            .get_current_pane_mut()
+            .ok_or(LayoutError::PaneNotFound)?;
+
+        let (cursor, buffer_id, options) = match pane {
+            LayoutNode::Pane {
+                cursor,
+                buffer_id,
+                options,
+                ..
+            } => (cursor, *buffer_id, options),
+            _ => return Err(LayoutError::NotPane),
+        };
+
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+
+        // the tail of the row in the wrapped line
+        if let Some(width) = rect.and_then(|r| wrap_width(r, options)) {
+            cursor.pos.0 = buf.wrapped_row_bounds(cursor.pos, width, options).1;
+        } else {
+            cursor.pos.0 = get_line_len(&buf.content[cursor.pos.1]);
+        }
+        Ok(())
+    }
+
+    pub fn mv_cursor_head(&mut self, buf_m: &mut BufferManager) -> Result<(), LayoutError> {
+        let rect = self.get_current_rect().copied();
+        let pane = self
+            .get_current_pane_mut()
+            .ok_or(LayoutError::PaneNotFound)?;
+
+        let (cursor, buffer_id, options) = match pane {
+            LayoutNode::Pane {
+                cursor,
+                buffer_id,
+                options,
+                ..
+            } => (cursor, *buffer_id, options),
+            _ => return Err(LayoutError::NotPane),
+        };
+
+        // the head of the row in the wrapped line
+        if let Some(width) = rect.and_then(|r| wrap_width(r, options)) {
+            let buf = buf_m.get_buffer(buffer_id)?;
+            cursor.pos.0 = buf.wrapped_row_bounds(cursor.pos, width, options).0;
+        } else {
+            cursor.pos.0 = 0;
+        }
+        Ok(())
+    }
+
+    // to the bracket matching the one under the cursor or just before it
+    pub fn mv_cursor_match(&mut self, buf_m: &mut BufferManager) -> Result<(), LayoutError> {
         let pane = self
             .get_current_pane_mut()
             .ok_or(LayoutError::PaneNotFound)?;
@@ -444,25 +764,45 @@ impl LayoutManager {
             _ => return Err(LayoutError::NotPane),
         };
 
-        let buf = buf_m.get_buffer_mut(buffer_id)?;
-
-        cursor.pos.0 = get_line_len(&buf.content[cursor.pos.1]);
+        let buf = buf_m.get_buffer(buffer_id)?;
+        if let Some([_, to]) = buf.bracket_pair_near(cursor.pos) {
+            cursor.pos = to;
+        }
         Ok(())
     }
 
-    pub fn mv_cursor_head(&mut self) -> Result<(), LayoutError> {
+    pub fn handle_tab(&mut self, buf_m: &mut BufferManager) -> Result<(), LayoutError> {
         let pane = self
             .get_current_pane_mut()
             .ok_or(LayoutError::PaneNotFound)?;
 
-        let (cursor, _buffer_id) = match pane {
+        let (cursor, buffer_id) = match pane {
             LayoutNode::Pane {
                 cursor, buffer_id, ..
             } => (cursor, *buffer_id),
             _ => return Err(LayoutError::NotPane),
         };
 
-        cursor.pos.0 = 0;
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        let (x, y) = cursor.pos;
+
+        let text = if buf.options.expand_tab {
+            let tab_width = buf.options.tab_width;
+            let col = buf.get_visual_width_upto(y, x);
+            " ".repeat(tab_width - col % tab_width)
+        } else {
+            "\t".to_string()
+        };
+        let len = text.len();
+        buf.apply_op(
+            EditOp::Insert {
+                pos: cursor.pos,
+                text: text.into(),
+                len,
+            },
+            true,
+        )?;
+        cursor.pos.0 += len;
         Ok(())
     }
 
@@ -482,17 +822,42 @@ impl LayoutManager {
         let (x, y) = cursor.pos;
 
         if x > 0 {
-            let del_str = &buf.content[y][x - 1..x];
+            let line = &buf.content[y];
+            // an empty pair goes as a whole
+            let pair = buf.options.auto_pairs
+                && buf
+                    .char_at((x - 1, y))
+                    .and_then(pair_of)
+                    .is_some_and(|c| buf.char_at((x, y)) == Some(c));
+            // in the indent made of spaces, back to the previous tab stop
+            let head = &line[..char_to_byte_idx(line, x)];
+            let (len, after) = if pair {
+                (1, 1)
+            } else if buf.options.expand_tab && head.bytes().all(|b| b == b' ') {
+                ((x - 1) % buf.options.tab_width + 1, 0)
+            } else {
+                (1, 0)
+            };
+            let start = char_to_byte_idx(line, x - len);
+            let end = char_to_byte_idx(line, x + after);
+            let del_str = &line[start..end];
             buf.apply_op(
                 EditOp::Delete {
-                    pos: (x - 1, y),
-                    len: 1,
+                    pos: (x - len, y),
+                    len: len + after,
                     text: del_str.into(),
                 },
                 true,
             )?;
-            cursor.pos.0 -= 1;
+            cursor.pos.0 -= len;
+            cursor
+                .auto_closers
+                .retain(|&p| p.1 == y && (after == 0 || p.0 != x));
+            for p in cursor.auto_closers.iter_mut().filter(|p| p.0 >= x) {
+                p.0 -= len + after;
+            }
         } else if y > 0 {
+            cursor.auto_closers.clear();
             let prev_line = &buf.content[y - 1];
             let prev_len = get_line_len(prev_line);
 
@@ -518,6 +883,8 @@ impl LayoutManager {
         Ok(())
     }
 
+    // split the line at the cursor, the new line takes the indent of this one,
+    // one level deeper after an opening bracket
     pub fn handle_enter(&mut self, buf_m: &mut BufferManager) -> Result<(), LayoutError> {
         let pane = self
             .get_current_pane_mut()
@@ -533,18 +900,332 @@ impl LayoutManager {
         let buf = buf_m.get_buffer_mut(buffer_id)?;
         let (x, y) = cursor.pos;
 
-        let byte_idx = char_to_byte_idx(&buf.content[y], x);
-        let next_line = buf.content[y].split_off(byte_idx);
+        let line = &buf.content[y];
+        let (head, tail) = line.split_at(char_to_byte_idx(line, x));
+        let base = leading_whitespace(head).to_string();
+        let opener = opens_block(head, buf.language());
+        let mut indent = base.clone();
+        if opener.is_some() {
+            indent.push_str(&indent_unit(&buf.options));
+        }
+        let mut text = format!("\n{}", indent);
+        // between a pair of brackets the closer goes down one more line
+        if opener
+            .and_then(closer_of)
+            .is_some_and(|c| tail.trim_start().starts_with(c))
+        {
+            text.push('\n');
+            text.push_str(&base);
+        }
+        let last = text.rsplit('\n').next().unwrap_or_default();
+        let end_pos = (get_line_len(last), y + text.matches('\n').count());
+
         buf.apply_op(
-            EditOp::InsertLine {
-                y: y + 1,
-                text: next_line.into(),
+            EditOp::InsertBlock {
+                start_pos: (x, y),
+                end_pos,
+                text,
             },
             true,
         )?;
-        cursor.pos.1 += 1;
-        cursor.pos.0 = 0;
+        cursor.pos = (get_line_len(&indent), y + 1);
+
+        Ok(())
+    }
+
+    // type `ch`, a bracket or a quote comes with its closer, and a closer
+    // typed in the indent takes one level of it away
+    pub fn handle_char(&mut self, buf_m: &mut BufferManager, ch: char) -> Result<(), LayoutError> {
+        let pane = self
+            .get_current_pane_mut()
+            .ok_or(LayoutError::PaneNotFound)?;
+
+        let (cursor, buffer_id) = match pane {
+            LayoutNode::Pane {
+                cursor, buffer_id, ..
+            } => (cursor, *buffer_id),
+            _ => return Err(LayoutError::NotPane),
+        };
+
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        let (x, y) = cursor.pos;
+        // the closers left on other lines are forgotten
+        cursor.auto_closers.retain(|p| p.1 == y);
+
+        let next = buf.char_at((x, y));
+        if next == Some(ch) && cursor.auto_closers.last() == Some(&(x, y)) {
+            cursor.auto_closers.pop();
+            cursor.pos.0 += 1;
+            return Ok(());
+        }
+        let prev = x.checked_sub(1).and_then(|px| buf.char_at((px, y)));
+        if let Some(closer) = pair_of(ch)
+            && buf.options.auto_pairs
+            && should_pair(ch, prev, next)
+        {
+            buf.apply_op(
+                EditOp::Insert {
+                    pos: (x, y),
+                    text: format!("{}{}", ch, closer).into(),
+                    len: 2,
+                },
+                true,
+            )?;
+            for p in cursor.auto_closers.iter_mut().filter(|p| p.0 >= x) {
+                p.0 += 2;
+            }
+            cursor.auto_closers.push((x + 1, y));
+            cursor.pos.0 = x + 1;
+            return Ok(());
+        }
+
+        let line = &buf.content[y];
+        let head = &line[..char_to_byte_idx(line, x)];
+        let dedent = if is_closer(ch) && head.trim().is_empty() {
+            outdent_len(head, &buf.options)
+        } else {
+            0
+        };
+        let insert = EditOp::Insert {
+            pos: (x - dedent, y),
+            text: ch.to_string().into(),
+            len: 1,
+        };
+        let op = if dedent > 0 {
+            EditOp::Group(vec![
+                EditOp::Delete {
+                    pos: (0, y),
+                    len: dedent,
+                    text: head[..dedent].into(),
+                },
+                insert,
+            ])
+        } else {
+            insert
+        };
+        buf.apply_op(op, true)?;
+        for p in cursor.auto_closers.iter_mut().filter(|p| p.0 >= x) {
+            p.0 = p.0 + 1 - dedent;
+        }
+        cursor.pos.0 = x - dedent + 1;
+        Ok(())
+    }
+
+    // add or take away one level of indent on the current line, or on the
+    // lines between it and `anchor`
+    pub fn indent_lines(
+        &mut self,
+        buf_m: &mut BufferManager,
+        anchor: Option<(usize, usize)>,
+        outdent: bool,
+    ) -> Result<(), LayoutError> {
+        let pane = self
+            .get_current_pane_mut()
+            .ok_or(LayoutError::PaneNotFound)?;
+
+        let (cursor, buffer_id) = match pane {
+            LayoutNode::Pane {
+                cursor, buffer_id, ..
+            } => (cursor, *buffer_id),
+            _ => return Err(LayoutError::NotPane),
+        };
+
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        let (x, y) = cursor.pos;
+        let ay = anchor.map_or(y, |(_, ay)| ay.min(buf.content.len().saturating_sub(1)));
+        let unit = indent_unit(&buf.options);
+
+        let mut ops = vec![];
+        let mut shift = 0;
+        for ly in y.min(ay)..=y.max(ay) {
+            let line = &buf.content[ly];
+            let len = if outdent {
+                let len = outdent_len(line, &buf.options);
+                if len > 0 {
+                    ops.push(EditOp::Delete {
+                        pos: (0, ly),
+                        len,
+                        text: line[..len].into(),
+                    });
+                }
+                len
+            } else if !line.is_empty() {
+                ops.push(EditOp::Insert {
+                    pos: (0, ly),
+                    text: unit.as_str().into(),
+                    len: get_line_len(&unit),
+                });
+                get_line_len(&unit)
+            } else {
+                0
+            };
+            if ly == y {
+                shift = len;
+            }
+        }
+        if ops.is_empty() {
+            return Ok(());
+        }
+        buf.apply_op(EditOp::Group(ops), true)?;
+        cursor.pos.0 = if outdent {
+            x.saturating_sub(shift)
+        } else {
+            x + shift
+        };
+        Ok(())
+    }
+
+    // `edit` on the current line, or on the lines between it and `anchor`,
+    // the whole buffer is trimmed when there's no selection
+    pub fn edit_lines(
+        &mut self,
+        buf_m: &mut BufferManager,
+        anchor: Option<(usize, usize)>,
+        edit: LineEdit,
+    ) -> Result<(), LayoutError> {
+        let pane = self
+            .get_current_pane_mut()
+            .ok_or(LayoutError::PaneNotFound)?;
+
+        let (cursor, buffer_id) = match pane {
+            LayoutNode::Pane {
+                cursor, buffer_id, ..
+            } => (cursor, *buffer_id),
+            _ => return Err(LayoutError::NotPane),
+        };
 
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        let last = buf.content.len().saturating_sub(1);
+        let (x, y) = (cursor.pos.0, cursor.pos.1.min(last));
+        let (sy, ey) = match anchor {
+            Some((_, ay)) => (y.min(ay.min(last)), y.max(ay.min(last))),
+            None if matches!(edit, LineEdit::TrimTrailing) => (0, last),
+            None => (y, y),
+        };
+        let Some(op) = buf.line_edit_op(sy, ey, edit)? else {
+            return Ok(());
+        };
+        // where the joined lines meet
+        let join_x = get_line_len(buf.content[sy].trim_end());
+        let indent = get_line_len(leading_whitespace(&buf.content[y]));
+        let before = get_line_len(&buf.content[y]);
+        buf.apply_op(op, true)?;
+
+        cursor.auto_closers.clear();
+        cursor.pos = match edit {
+            LineEdit::MoveUp => (x, y - 1),
+            LineEdit::MoveDown => (x, y + 1),
+            LineEdit::Duplicate => (x, y + ey - sy + 1),
+            LineEdit::Join => (join_x, sy),
+            // the cursor stays on its character
+            LineEdit::ToggleComment if x > indent => {
+                ((x + get_line_len(&buf.content[y])).saturating_sub(before), y)
+            }
+            _ => (x, y),
+        };
+        check_cursor_pos(buf_m, &mut cursor.pos, buffer_id)?;
+        Ok(())
+    }
+
+    // the lines between the cursor and `anchor`, or all of them without one,
+    // for a command to filter: the buffer, the lines and their text
+    pub fn filter_input(
+        &mut self,
+        buf_m: &mut BufferManager,
+        anchor: Option<(usize, usize)>,
+    ) -> Result<(usize, Range<usize>, String), LayoutError> {
+        let (y, buffer_id) = match self.get_current_pane() {
+            Some(LayoutNode::Pane {
+                cursor, buffer_id, ..
+            }) => (cursor.pos.1, buffer_id),
+            Some(_) => return Err(LayoutError::NotPane),
+            None => return Err(LayoutError::PaneNotFound),
+        };
+
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        if buf.read_only {
+            return Err(BufferError::ReadOnly.into());
+        }
+        let last = buf.content.len().saturating_sub(1);
+        let y = y.min(last);
+        let (sy, ey) = match anchor {
+            Some((_, ay)) => (y.min(ay.min(last)), y.max(ay.min(last))),
+            None => (0, last),
+        };
+        buf.load_lines(sy..ey + 1)?;
+        let input = format!("{}\n", buf.content[sy..=ey].join("\n"));
+        Ok((buffer_id, sy..ey + 1, input))
+    }
+
+    // the `output` of a filter in the place of the `lines` of buffer
+    // `buffer_id`, in one op, the cursors follow the lines
+    pub fn apply_filter(
+        &mut self,
+        buf_m: &mut BufferManager,
+        buffer_id: usize,
+        lines: Range<usize>,
+        output: &str,
+    ) -> Result<(), LayoutError> {
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        let new_lines = output_lines(output);
+        if let Some(op) = buf.replace_lines_op(lines.start, lines.end - 1, &new_lines) {
+            buf.apply_op(op, true)?;
+        }
+        let hunk = Hunk {
+            new: lines.start..lines.start + new_lines.len(),
+            old: lines,
+        };
+        self.follow_hunks(buf_m, buffer_id, &[hunk])
+    }
+
+    // put `text` at `pos` of buffer `buffer_id`, the cursor of pane `pane`
+    // goes after it while the pane still shows the buffer
+    pub fn insert_text(
+        &mut self,
+        buf_m: &mut BufferManager,
+        pane: usize,
+        buffer_id: usize,
+        pos: (usize, usize),
+        text: &str,
+    ) -> Result<(), LayoutError> {
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        buf.apply_op(EditOp::replace(pos, pos, text), true)?;
+        if let Some(LayoutNode::Pane {
+            cursor,
+            buffer_id: b,
+            ..
+        }) = self.panes.as_mut().and_then(|root| root.get_pane_mut(pane))
+            && *b == buffer_id
+        {
+            cursor.auto_closers.retain(|p| p.1 != pos.1);
+            cursor.pos = text_end(pos, text);
+        }
+        Ok(())
+    }
+
+    // put `text` over the text between `start` and the cursor, the cursor
+    // goes after it
+    pub fn complete(
+        &mut self,
+        buf_m: &mut BufferManager,
+        start: (usize, usize),
+        text: &str,
+    ) -> Result<(), LayoutError> {
+        let pane = self
+            .get_current_pane_mut()
+            .ok_or(LayoutError::PaneNotFound)?;
+
+        let (cursor, buffer_id) = match pane {
+            LayoutNode::Pane {
+                cursor, buffer_id, ..
+            } => (cursor, *buffer_id),
+            _ => return Err(LayoutError::NotPane),
+        };
+
+        let buf = buf_m.get_buffer_mut(buffer_id)?;
+        buf.apply_op(EditOp::replace(start, cursor.pos, text), true)?;
+        cursor.auto_closers.retain(|p| p.1 != start.1);
+        cursor.pos = text_end(start, text);
         Ok(())
     }
 }
@@ -566,6 +1247,8 @@ pub fn update_scroll(
     if y >= total_lines {
         cursor_pos.1 = total_lines.saturating_sub(1);
     }
+    // the offset is in columns, tabs and wide graphemes take more than one
+    let x = buf.get_visual_width_upto(cursor_pos.1, x);
 
     if y >= (scroll_offset.1 + viewport_height).saturating_sub(scroll_thres.1) {
         scroll_offset.1 = y + scroll_thres.1 - viewport_height + 1;
@@ -586,6 +1269,35 @@ pub fn update_scroll(
     Ok(())
 }
 
+// keep the row of the cursor in the viewport when the lines are wrapped
+pub fn update_scroll_wrapped(
+    buf: &Buffer,
+    viewport_height: usize,
+    width: usize,
+    opts: &PaneOptions,
+    cursor_pos: (usize, usize),
+    scroll_offset: &mut (usize, usize),
+) {
+    scroll_offset.0 = 0;
+    let y = cursor_pos.1;
+    if y < scroll_offset.1 {
+        scroll_offset.1 = y;
+        return;
+    }
+    // every line takes one row at least
+    if y - scroll_offset.1 >= viewport_height {
+        scroll_offset.1 = (y + 1).saturating_sub(viewport_height);
+    }
+    while scroll_offset.1 < y
+        && buf
+            .wrapped_position(cursor_pos, scroll_offset.1, width, opts)
+            .0
+            >= viewport_height
+    {
+        scroll_offset.1 += 1;
+    }
+}
+
 pub fn check_cursor_pos(
     buf_m: &BufferManager,
     cursor_pos: &mut (usize, usize),
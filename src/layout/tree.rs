@@ -1,5 +1,6 @@
 use crate::buffer::BufferManager;
 use crate::cursor::*;
+use crate::options::PaneOptions;
 
 #[derive(Debug, Copy, Clone)]
 pub enum SplitDirection {
@@ -7,6 +8,15 @@ pub enum SplitDirection {
     Vertical,
 }
 
+// what a pane shows instead of a buffer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PaneContent {
+    #[cfg(unix)]
+    Terminal(usize),
+    // the file explorer, there's one
+    Explorer,
+}
+
 #[derive(Debug, Clone)]
 pub enum LayoutNode {
     Pane {
@@ -15,6 +25,11 @@ pub enum LayoutNode {
         cursor: Cursor,
         scroll_offset: (usize, usize),
         scroll_thres: (usize, usize),
+        options: PaneOptions,
+    },
+    Content {
+        id: usize,
+        content: PaneContent,
     },
     Split {
         direc: SplitDirection,
@@ -32,9 +47,11 @@ impl LayoutNode {
             cursor: Cursor::new(),
             scroll_offset: (0, 0),
             scroll_thres: (0, 0),
+            options: PaneOptions::new(),
         }
     }
 
+    #[allow(dead_code)]
     pub fn get_buffer_id(&self, pane_id: usize) -> Option<usize> {
         match self {
             LayoutNode::Pane { id, buffer_id, .. } => {
@@ -44,6 +61,7 @@ impl LayoutNode {
                     None
                 }
             }
+            LayoutNode::Content { .. } => None,
             LayoutNode::Split { first, second, .. } => first
                 .get_buffer_id(pane_id)
                 .or_else(|| second.get_buffer_id(pane_id)),
@@ -52,7 +70,7 @@ impl LayoutNode {
 
     pub fn get_pane(&self, pane_id: usize) -> Option<&LayoutNode> {
         match self {
-            pane @ LayoutNode::Pane { id, .. } => {
+            pane @ (LayoutNode::Pane { id, .. } | LayoutNode::Content { id, .. }) => {
                 if *id == pane_id {
                     Some(pane)
                 } else {
@@ -67,7 +85,7 @@ impl LayoutNode {
 
     pub fn get_pane_mut(&mut self, pane_id: usize) -> Option<&mut LayoutNode> {
         match self {
-            LayoutNode::Pane { id, .. } => {
+            LayoutNode::Pane { id, .. } | LayoutNode::Content { id, .. } => {
                 if *id == pane_id {
                     Some(self)
                 } else {
@@ -81,6 +99,7 @@ impl LayoutNode {
     }
 }
 
+#[allow(clippy::only_used_in_recursion)]
 pub fn split_current(
     root: &mut LayoutNode,
     target: usize,
@@ -95,6 +114,7 @@ pub fn split_current(
         cursor,
         scroll_offset,
         scroll_thres,
+        options,
         ..
     } = root
     {
@@ -113,22 +133,63 @@ pub fn split_current(
                     cursor: cursor.clone(),
                     scroll_offset: *scroll_offset,
                     scroll_thres: *scroll_thres,
+                    options: options.clone(),
+                }),
+                second: Box::new(LayoutNode::Pane {
+                    id: new_id,
+                    buffer_id: new_buf_id,
+                    cursor: Cursor::new(),
+                    scroll_offset: (0, 0),
+                    scroll_thres: (0, 0),
+                    options: options.clone(),
                 }),
-                second: Box::new(LayoutNode::new_pane(new_id, new_buf_id)),
             };
         }
+    } else if let LayoutNode::Content { id, .. } = root {
+        // the new pane needs a buffer of its own
+        if *id == target
+            && let Some(new_buf_id) = new_buf_id
+        {
+            let new = LayoutNode::new_pane(new_id, new_buf_id);
+            split_node(root, target, new, direc);
+        }
     } else if let LayoutNode::Split { first, second, .. } = root {
         split_current(first, target, new_id, new_buf_id, direc, buf_m);
         split_current(second, target, new_id, new_buf_id, direc, buf_m);
     }
 }
 
-pub fn remove_pane(
-    node: LayoutNode,
+// put `new` beside pane `target`, returns false if there's no such pane
+pub fn split_node(
+    root: &mut LayoutNode,
     target: usize,
-) -> Option<LayoutNode> {
+    new: LayoutNode,
+    direc: SplitDirection,
+) -> bool {
+    match root {
+        LayoutNode::Pane { id, .. } | LayoutNode::Content { id, .. } => {
+            if *id != target {
+                return false;
+            }
+            let old = std::mem::replace(root, LayoutNode::new_pane(0, 0));
+            *root = LayoutNode::Split {
+                direc,
+                ratio: 0.5,
+                first: Box::new(old),
+                second: Box::new(new),
+            };
+            true
+        }
+        LayoutNode::Split { first, second, .. } => match first.get_pane(target) {
+            Some(_) => split_node(first, target, new, direc),
+            None => split_node(second, target, new, direc),
+        },
+    }
+}
+
+pub fn remove_pane(node: LayoutNode, target: usize) -> Option<LayoutNode> {
     match node {
-        LayoutNode::Pane { id, .. } => {
+        LayoutNode::Pane { id, .. } | LayoutNode::Content { id, .. } => {
             if id == target {
                 None
             } else {
@@ -164,9 +225,20 @@ pub fn remove_pane(
 
 pub fn get_first_pane_id(node: &LayoutNode) -> Option<usize> {
     match node {
-        LayoutNode::Pane { id, .. } => Some(*id),
+        LayoutNode::Pane { id, .. } | LayoutNode::Content { id, .. } => Some(*id),
         LayoutNode::Split { first, second, .. } => {
             get_first_pane_id(first).or_else(|| get_first_pane_id(second))
         }
     }
 }
+
+// every pane under `node`, in the order they are drawn
+pub fn collect_panes<'a>(node: &'a LayoutNode, panes: &mut Vec<&'a LayoutNode>) {
+    match node {
+        pane @ (LayoutNode::Pane { .. } | LayoutNode::Content { .. }) => panes.push(pane),
+        LayoutNode::Split { first, second, .. } => {
+            collect_panes(first, panes);
+            collect_panes(second, panes);
+        }
+    }
+}
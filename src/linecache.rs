@@ -0,0 +1,157 @@
+#![allow(dead_code)]
+// display layout of the lines, so drawing a frame doesn't segment every line
+// again, the entries are dropped by the edits which touch their lines
+use crate::command::op::EditOp;
+use std::ops::Range;
+use std::sync::Arc;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug)]
+pub struct LineLayout {
+    // byte offset and display width of each grapheme
+    pub graphemes: Vec<(usize, usize)>,
+    pub width: usize,
+    // byte length of the line, a cheap check against edits we missed
+    len: usize,
+}
+
+impl LineLayout {
+    // a tab reaches to the next multiple of `tab_width`
+    pub fn new(line: &str, tab_width: usize) -> Self {
+        let tab_width = tab_width.max(1);
+        let mut col = 0;
+        let graphemes: Vec<(usize, usize)> = line
+            .grapheme_indices(true)
+            .map(|(i, g)| {
+                let w = if g == "\t" {
+                    tab_width - col % tab_width
+                } else {
+                    g.width()
+                };
+                col += w;
+                (i, w)
+            })
+            .collect();
+        let width = graphemes.iter().map(|(_, w)| w).sum();
+        Self {
+            graphemes,
+            width,
+            len: line.len(),
+        }
+    }
+
+    // display width of the first `n` graphemes
+    pub fn width_upto(&self, n: usize) -> usize {
+        self.graphemes.iter().take(n).map(|(_, w)| w).sum()
+    }
+
+    // the grapheme under the column `col`, the end of the line if it's past it
+    pub fn x_at_column(&self, col: usize) -> usize {
+        let mut used = 0;
+        for (x, (_, w)) in self.graphemes.iter().enumerate() {
+            if col < used + w {
+                return x;
+            }
+            used += w;
+        }
+        self.graphemes.len()
+    }
+
+    // byte range of the grapheme at `idx`
+    pub fn byte_range(&self, idx: usize) -> Range<usize> {
+        let start = self.graphemes[idx].0;
+        let end = self.graphemes.get(idx + 1).map_or(self.len, |(i, _)| *i);
+        start..end
+    }
+}
+
+pub struct LineCache {
+    lines: Vec<Option<Arc<LineLayout>>>,
+    // the layouts depend on it, they are dropped when it changes
+    tab_width: usize,
+}
+
+impl LineCache {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![],
+            tab_width: 0,
+        }
+    }
+
+    pub fn get(&mut self, y: usize, line: &str, tab_width: usize) -> Arc<LineLayout> {
+        if tab_width != self.tab_width {
+            self.lines.clear();
+            self.tab_width = tab_width;
+        }
+        if y >= self.lines.len() {
+            self.lines.resize(y + 1, None);
+        }
+        match &self.lines[y] {
+            Some(layout) if layout.len == line.len() => layout.clone(),
+            _ => {
+                let layout = Arc::new(LineLayout::new(line, tab_width));
+                self.lines[y] = Some(layout.clone());
+                layout
+            }
+        }
+    }
+
+    pub fn invalidate(&mut self, y: usize) {
+        if let Some(entry) = self.lines.get_mut(y) {
+            *entry = None;
+        }
+    }
+
+    pub fn insert_lines(&mut self, y: usize, count: usize) {
+        if y <= self.lines.len() {
+            self.lines.splice(y..y, std::iter::repeat_n(None, count));
+        }
+    }
+
+    pub fn remove_lines(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.lines.len());
+        if range.start < end {
+            self.lines.drain(range.start..end);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    // drop the entries of the lines `op` changed, and shift the others
+    pub fn apply(&mut self, op: &EditOp) {
+        match op {
+            EditOp::Insert { pos, .. } | EditOp::Delete { pos, .. } => self.invalidate(pos.1),
+            EditOp::InsertLine { y, .. } => {
+                // Enter splits the line above before inserting
+                if *y > 0 {
+                    self.invalidate(y - 1);
+                }
+                self.insert_lines(*y, 1);
+            }
+            EditOp::DeleteLine { y, .. } => self.remove_lines(*y..(y + 1)),
+            EditOp::DeleteBlock {
+                start_pos, end_pos, ..
+            } => {
+                let (sy, ey) = (start_pos.1.min(end_pos.1), start_pos.1.max(end_pos.1));
+                self.remove_lines((sy + 1)..(ey + 1));
+                self.invalidate(sy);
+            }
+            EditOp::InsertBlock {
+                start_pos, text, ..
+            } => {
+                let y = start_pos.1;
+                self.invalidate(y);
+                self.insert_lines(y + 1, text.matches('\n').count());
+            }
+            EditOp::Group(ops) => {
+                for op in ops {
+                    self.apply(op);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+#![allow(dead_code)]
+// the commands which work on whole lines: move, duplicate, join, sort,
+// reverse, trim and comment, every one is a single op which can be revoked
+use crate::buffer::Buffer;
+use crate::error::BufferError;
+use crate::op::EditOp;
+use crate::utils::get_line_len;
+use std::cmp::Ordering;
+
+#[derive(Debug, Copy, Clone)]
+pub enum LineEdit {
+    MoveUp,
+    MoveDown,
+    Duplicate,
+    Join,
+    Sort(SortOptions),
+    Reverse,
+    TrimTrailing,
+    ToggleComment,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SortOptions {
+    // by the first number in the line
+    pub numeric: bool,
+    pub reverse: bool,
+    // drop the lines equal to the one before them
+    pub unique: bool,
+    pub ignore_case: bool,
+}
+
+impl SortOptions {
+    // the flags after `sort`, like `nu` or `r`
+    pub fn parse(flags: &str) -> Option<Self> {
+        let mut opts = Self::default();
+        for ch in flags.chars().filter(|c| !c.is_whitespace()) {
+            match ch {
+                'n' => opts.numeric = true,
+                'r' => opts.reverse = true,
+                'u' => opts.unique = true,
+                'i' => opts.ignore_case = true,
+                _ => return None,
+            }
+        }
+        Some(opts)
+    }
+}
+
+// the first number of `line`, with its sign and fraction
+fn first_number(line: &str) -> Option<f64> {
+    let start = line.find(|c: char| c.is_ascii_digit())?;
+    let start = if line[..start].ends_with('-') {
+        start - 1
+    } else {
+        start
+    };
+    let len = line[start + 1..]
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map_or(line.len() - start, |n| n + 1);
+    line[start..start + len].trim_end_matches('.').parse().ok()
+}
+
+// the lines without a number go first, in their order
+pub fn sort_lines(lines: &mut Vec<String>, opts: SortOptions) {
+    let key = |l: &String| {
+        if opts.ignore_case {
+            l.to_lowercase()
+        } else {
+            l.clone()
+        }
+    };
+    if opts.numeric {
+        lines.sort_by(|a, b| match (first_number(a), first_number(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+    } else {
+        lines.sort_by_cached_key(key);
+    }
+    if opts.reverse {
+        lines.reverse();
+    }
+    if opts.unique {
+        lines.dedup_by(|a, b| key(a) == key(b));
+    }
+}
+
+// one line, the blanks where the lines meet become a single space
+pub fn join_lines(lines: &[String]) -> String {
+    let mut joined = lines[0].trim_end().to_string();
+    for line in &lines[1..] {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if !joined.trim_start().is_empty() {
+            joined.push(' ');
+        }
+        joined.push_str(line);
+    }
+    joined
+}
+
+impl Buffer {
+    // an op which puts `lines` in the place of the lines `sy..=ey`, `None`
+    // if they are the same
+    pub fn replace_lines_op(&self, sy: usize, ey: usize, lines: &[String]) -> Option<EditOp> {
+        if lines.is_empty() || self.content[sy..=ey] == *lines {
+            return None;
+        }
+        let last = &lines[lines.len() - 1];
+        Some(EditOp::Group(vec![
+            EditOp::DeleteBlock {
+                start_pos: (0, sy),
+                end_pos: (get_line_len(&self.content[ey]), ey),
+                text: String::new(),
+            },
+            EditOp::InsertBlock {
+                start_pos: (0, sy),
+                end_pos: (get_line_len(last), sy + lines.len() - 1),
+                text: lines.join("\n"),
+            },
+        ]))
+    }
+
+    // the op of `edit` on the lines `sy..=ey`, `None` if nothing would change
+    pub fn line_edit_op(
+        &mut self,
+        sy: usize,
+        ey: usize,
+        edit: LineEdit,
+    ) -> Result<Option<EditOp>, BufferError> {
+        let count = self.content.len();
+        if ey >= count {
+            return Err(BufferError::InvalidPosition);
+        }
+        // the line next to them is moved too
+        self.load_lines(sy.saturating_sub(1)..(ey + 2).min(count))?;
+
+        let selected = self.content[sy..=ey].to_vec();
+        let op = match edit {
+            LineEdit::ToggleComment => self.toggle_comment_op(sy, ey)?,
+            LineEdit::MoveUp if sy > 0 => {
+                let mut lines = selected;
+                lines.push(self.content[sy - 1].clone());
+                self.replace_lines_op(sy - 1, ey, &lines)
+            }
+            LineEdit::MoveDown if ey + 1 < count => {
+                let mut lines = vec![self.content[ey + 1].clone()];
+                lines.extend(selected);
+                self.replace_lines_op(sy, ey + 1, &lines)
+            }
+            LineEdit::MoveUp | LineEdit::MoveDown => None,
+            LineEdit::Duplicate => {
+                let text = format!("\n{}", selected.join("\n"));
+                let end = (get_line_len(&self.content[ey]), ey + selected.len());
+                Some(EditOp::InsertBlock {
+                    start_pos: (get_line_len(&self.content[ey]), ey),
+                    end_pos: end,
+                    text,
+                })
+            }
+            // a single line is joined with the next one
+            LineEdit::Join if sy == ey && ey + 1 < count => {
+                let joined = join_lines(&self.content[sy..=ey + 1]);
+                self.replace_lines_op(sy, ey + 1, &[joined])
+            }
+            LineEdit::Join if sy < ey => self.replace_lines_op(sy, ey, &[join_lines(&selected)]),
+            LineEdit::Join => None,
+            LineEdit::Sort(opts) => {
+                let mut lines = selected;
+                sort_lines(&mut lines, opts);
+                self.replace_lines_op(sy, ey, &lines)
+            }
+            LineEdit::Reverse => {
+                let lines: Vec<String> = selected.into_iter().rev().collect();
+                self.replace_lines_op(sy, ey, &lines)
+            }
+            LineEdit::TrimTrailing => {
+                let ops: Vec<EditOp> = selected
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, line)| {
+                        let kept = line.trim_end();
+                        (kept.len() < line.len()).then(|| EditOp::Delete {
+                            pos: (get_line_len(kept), sy + i),
+                            len: get_line_len(&line[kept.len()..]),
+                            text: line[kept.len()..].into(),
+                        })
+                    })
+                    .collect();
+                (!ops.is_empty()).then_some(EditOp::Group(ops))
+            }
+        };
+        Ok(op)
+    }
+}
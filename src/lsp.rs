@@ -0,0 +1,965 @@
+#![allow(dead_code)]
+// the language servers of the open buffers: every edit is sent to them as it
+// happens, and they answer hovers, definitions, references, renames and code
+// actions and completions, their diagnostics go into the buffers
+pub mod client;
+pub mod protocol;
+
+use crate::buffer::{Buffer, BufferManager};
+use crate::command::{CmdStatus, KaoCo, cursor_pos};
+use crate::completion::{Candidate, Source};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::error::{BufferError, LspError};
+use crate::event_loop::AppEvent;
+use crate::language::{LANGUAGES, Language};
+use crate::layout::layout_manager::LayoutManager;
+use crate::op::EditOp;
+use crate::popup::{Popup, Popups};
+use client::{LspClient, Request, SyncKind};
+use protocol::*;
+use ratatui::style::Color;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+const POPUP_COLOR: Color = Color::Rgb(167, 199, 250);
+const POPUP_DURATION: Duration = Duration::from_secs(8);
+// the diagnostics of the servers are replaced together
+const PRODUCER: &str = "lsp";
+
+// a buffer the server knows about
+struct OpenDoc {
+    lang: &'static str,
+    path: PathBuf,
+    uri: String,
+    version: i64,
+    saved: bool,
+}
+
+pub struct LspManager {
+    clients: HashMap<&'static str, LspClient>,
+    // the servers which couldn't start or exited, they aren't started again
+    // until `lsp <lang> <command>` sets them up
+    failed: HashMap<&'static str, String>,
+    // by buffer id
+    docs: HashMap<usize, OpenDoc>,
+    // the diagnostics as the servers sent them by uri, code actions need them
+    raw_diagnostics: HashMap<String, Vec<Value>>,
+    // the code actions and references offered last, picked by their number
+    actions: Vec<(&'static str, Value)>,
+    locations: Vec<(PathBuf, (usize, usize))>,
+    // the completions of the last request by buffer id, until the menu takes them
+    completions: Option<(usize, Vec<Candidate>)>,
+    completion_buffer: usize,
+    // commands set with `lsp <lang> <command>`, over the defaults
+    servers: HashMap<&'static str, String>,
+    root: PathBuf,
+    tx: Sender<AppEvent>,
+    server_counter: usize,
+}
+
+impl LspManager {
+    pub fn new(tx: Sender<AppEvent>) -> Self {
+        let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        Self {
+            clients: HashMap::new(),
+            failed: HashMap::new(),
+            docs: HashMap::new(),
+            raw_diagnostics: HashMap::new(),
+            actions: vec![],
+            locations: vec![],
+            completions: None,
+            completion_buffer: 0,
+            servers: HashMap::new(),
+            root: root.canonicalize().unwrap_or(root),
+            tx,
+            server_counter: 1,
+        }
+    }
+
+    fn server_command(&self, lang: &Language) -> Option<String> {
+        match self.servers.get(lang.name) {
+            Some(command) => Some(command.clone()),
+            None => lang.language_server.map(str::to_string),
+        }
+    }
+
+    // run `command` for the buffers of `lang`, a running server is stopped
+    pub fn set_server(
+        &mut self,
+        bm: &mut BufferManager,
+        lang: &str,
+        command: &str,
+    ) -> Result<(), LspError> {
+        let lang = LANGUAGES
+            .iter()
+            .find(|l| l.name == lang)
+            .ok_or_else(|| LspError::UnknownLanguage(lang.to_string()))?;
+        self.servers.insert(lang.name, command.to_string());
+        if let Some(mut client) = self.clients.remove(lang.name) {
+            client.shutdown();
+        }
+        self.forget(bm, lang.name);
+        self.failed.remove(lang.name);
+        // asking for a server is what turns it on for the buffers
+        for buf in bm.buffers.values_mut() {
+            if buf.language().is_some_and(|l| l.name == lang.name) {
+                buf.options.lsp = true;
+            }
+        }
+        Ok(())
+    }
+
+    // the running and the failed servers
+    pub fn status(&self) -> String {
+        let mut parts: Vec<String> = self
+            .clients
+            .values()
+            .map(|c| {
+                let state = if c.initialized { "running" } else { "starting" };
+                format!("{}: {} ({})", c.lang, c.command, state)
+            })
+            .chain(
+                self.failed
+                    .iter()
+                    .map(|(lang, e)| format!("{}: {}", lang, e)),
+            )
+            .collect();
+        parts.sort();
+        if parts.is_empty() {
+            return "No language server is running".to_string();
+        }
+        parts.join(", ")
+    }
+
+    // the client of `lang`, started if it isn't running, the error is only
+    // returned when it fails to start
+    fn client(&mut self, lang: &'static Language) -> Result<Option<&mut LspClient>, String> {
+        if !self.clients.contains_key(lang.name) && !self.failed.contains_key(lang.name) {
+            let Some(command) = self.server_command(lang) else {
+                return Ok(None);
+            };
+            let id = self.server_counter;
+            self.server_counter += 1;
+            match LspClient::spawn(id, lang.name, &command, &self.root, self.tx.clone()) {
+                Ok(client) => {
+                    self.clients.insert(lang.name, client);
+                }
+                Err(e) => {
+                    let e = format!("failed to start {}: {}", command, e);
+                    self.failed.insert(lang.name, e.clone());
+                    return Err(e);
+                }
+            }
+        }
+        Ok(self.clients.get_mut(lang.name))
+    }
+
+    // tell the servers about the buffers opened, changed, saved and closed
+    // since the last time, returns the error of a server which failed to start
+    pub fn sync(&mut self, bm: &mut BufferManager) -> Option<String> {
+        let stale: Vec<usize> = self
+            .docs
+            .iter()
+            .filter(|(id, doc)| {
+                bm.buffers.get(id).is_none_or(|buf| {
+                    !buf.options.lsp || buf.path.as_deref() != Some(doc.path.as_path())
+                })
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in stale {
+            self.close_doc(bm, id);
+        }
+
+        let mut error = None;
+        for buf in bm.buffers.values_mut() {
+            let changes = std::mem::take(&mut buf.changes);
+            let Some(doc) = self.docs.get_mut(&buf.id) else {
+                // the whole text is sent when it's opened
+                if let Err(e) = self.open_doc(buf) {
+                    error = Some(e);
+                }
+                continue;
+            };
+            let Some(client) = self.clients.get_mut(doc.lang) else {
+                continue;
+            };
+            let full = changes.iter().any(|c| matches!(c, DocChange::Full));
+            let content_changes: Vec<Value> = match client.sync_kind() {
+                _ if changes.is_empty() => vec![],
+                SyncKind::None => vec![],
+                SyncKind::Full => vec![change_json(&DocChange::Full, &buf.content)],
+                SyncKind::Incremental if full => {
+                    vec![change_json(&DocChange::Full, &buf.content)]
+                }
+                SyncKind::Incremental => changes
+                    .iter()
+                    .map(|c| change_json(c, &buf.content))
+                    .collect(),
+            };
+            if !content_changes.is_empty() {
+                doc.version += 1;
+                let _ = client.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": doc.uri, "version": doc.version },
+                        "contentChanges": content_changes,
+                    }),
+                );
+            }
+            if buf.saved && !doc.saved {
+                let _ = client.notify(
+                    "textDocument/didSave",
+                    json!({ "textDocument": { "uri": doc.uri } }),
+                );
+            }
+            doc.saved = buf.saved;
+        }
+        error
+    }
+
+    fn open_doc(&mut self, buf: &Buffer) -> Result<(), String> {
+        if !buf.options.lsp || buf.is_large() {
+            return Ok(());
+        }
+        let (Some(path), Some(lang)) = (buf.path.clone(), buf.language()) else {
+            return Ok(());
+        };
+        let Some(client) = self.client(lang)? else {
+            return Ok(());
+        };
+        // opened once the server is ready
+        if !client.initialized {
+            return Ok(());
+        }
+        let uri = path_to_uri(&path);
+        let _ = client.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": lang.name,
+                    "version": 0,
+                    "text": buf.content.join("\n"),
+                }
+            }),
+        );
+        self.docs.insert(
+            buf.id,
+            OpenDoc {
+                lang: lang.name,
+                path,
+                uri,
+                version: 0,
+                saved: buf.saved,
+            },
+        );
+        Ok(())
+    }
+
+    fn close_doc(&mut self, bm: &mut BufferManager, id: usize) {
+        let Some(doc) = self.docs.remove(&id) else {
+            return;
+        };
+        if let Some(client) = self.clients.get_mut(doc.lang) {
+            let _ = client.notify(
+                "textDocument/didClose",
+                json!({ "textDocument": { "uri": doc.uri } }),
+            );
+        }
+        self.raw_diagnostics.remove(&doc.uri);
+        if let Ok(buf) = bm.get_buffer_mut(id) {
+            buf.set_diagnostics(PRODUCER, vec![]);
+        }
+    }
+
+    // drop what the server of `lang` told about its documents
+    fn forget(&mut self, bm: &mut BufferManager, lang: &str) {
+        let ids: Vec<usize> = self
+            .docs
+            .iter()
+            .filter(|(_, doc)| doc.lang == lang)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            if let Some(doc) = self.docs.remove(&id) {
+                self.raw_diagnostics.remove(&doc.uri);
+            }
+            if let Ok(buf) = bm.get_buffer_mut(id) {
+                buf.set_diagnostics(PRODUCER, vec![]);
+            }
+        }
+        self.actions.retain(|(l, _)| *l != lang);
+    }
+
+    // the language of the running server `id`
+    fn server_lang(&self, id: usize) -> Option<&'static str> {
+        self.clients.values().find(|c| c.id == id).map(|c| c.lang)
+    }
+
+    pub fn server_exited(&mut self, bm: &mut BufferManager, cmd: &mut KaoCo, server: usize) {
+        let Some(lang) = self.server_lang(server) else {
+            return;
+        };
+        let Some(mut client) = self.clients.remove(lang) else {
+            return;
+        };
+        client.reap();
+        self.forget(bm, lang);
+        self.failed
+            .insert(lang, format!("{} exited", client.command));
+        cmd.say = format!("The {} language server exited", lang).into();
+        cmd.status = CmdStatus::Failed;
+    }
+
+    pub fn shutdown_all(&mut self) {
+        for client in self.clients.values_mut() {
+            client.shutdown();
+        }
+        self.clients.clear();
+    }
+
+    // a message of the server `server`, returns whether the screen changed
+    pub fn handle_message(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        popups: &mut Popups,
+        cmd: &mut KaoCo,
+        server: usize,
+        message: Value,
+    ) -> bool {
+        // from a server which was stopped
+        let Some(lang) = self.server_lang(server) else {
+            return false;
+        };
+        let params = &message["params"];
+        match (message.get("id"), message["method"].as_str()) {
+            (Some(id), Some(method)) => {
+                let result = match method {
+                    "workspace/applyEdit" => {
+                        let applied = self.apply_workspace_edit(bm, &params["edit"]).is_ok();
+                        json!({ "applied": applied })
+                    }
+                    // no settings, one null for each item asked
+                    "workspace/configuration" => {
+                        let count = params["items"].as_array().map_or(0, Vec::len);
+                        Value::Array(vec![Value::Null; count])
+                    }
+                    _ => Value::Null,
+                };
+                if let Some(client) = self.clients.get_mut(lang) {
+                    let _ = client.respond(id.clone(), result);
+                }
+                true
+            }
+            (Some(id), None) => self.handle_response(bm, lm, popups, cmd, lang, id, &message),
+            (None, Some("textDocument/publishDiagnostics")) => {
+                self.publish_diagnostics(bm, lang, params);
+                true
+            }
+            (None, Some("window/showMessage")) => {
+                if let Some(text) = params["message"].as_str() {
+                    cmd.say = text.into();
+                    if params["type"].as_u64() == Some(1) {
+                        cmd.status = CmdStatus::Failed;
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn handle_response(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        popups: &mut Popups,
+        cmd: &mut KaoCo,
+        lang: &'static str,
+        id: &Value,
+        message: &Value,
+    ) -> bool {
+        let Some(client) = self.clients.get_mut(lang) else {
+            return false;
+        };
+        let Some(kind) = id.as_i64().and_then(|id| client.pending.remove(&id)) else {
+            return false;
+        };
+        if let Some(error) = message.get("error") {
+            let text = error["message"].as_str().unwrap_or("the request failed");
+            match kind {
+                Request::Shutdown => return false,
+                // the server can't be used, it's stopped like one which
+                // couldn't start
+                Request::Initialize => {
+                    let command = client.command.clone();
+                    if let Some(mut client) = self.clients.remove(lang) {
+                        client.shutdown();
+                    }
+                    self.failed
+                        .insert(lang, format!("{} failed to initialize: {}", command, text));
+                    cmd.say = format!("The {} language server failed to initialize", lang).into();
+                }
+                _ => cmd.say = format!("{}: {}", lang, text).into(),
+            }
+            cmd.status = CmdStatus::Failed;
+            return true;
+        }
+        let result = &message["result"];
+        let res = match kind {
+            Request::Initialize => {
+                client.capabilities = result["capabilities"].clone();
+                client.initialized = true;
+                let _ = client.notify("initialized", json!({}));
+                // the documents are opened by the next sync
+                return false;
+            }
+            Request::Hover => match hover_text(result) {
+                Some(text) => {
+                    popups.push(Popup::fit(&text, POPUP_DURATION, POPUP_COLOR));
+                    return true;
+                }
+                None => Err("No hover information".to_string()),
+            },
+            Request::Definition => match parse_locations(result).first() {
+                Some(location) => self
+                    .open_location(bm, lm, location)
+                    .map_err(|e| e.to_string()),
+                None => Err("No definition found".to_string()),
+            },
+            Request::References => {
+                self.locations = parse_locations(result);
+                if self.locations.is_empty() {
+                    Err("No references found".to_string())
+                } else {
+                    let list = self.numbered(self.locations.iter().map(|(path, pos)| {
+                        format!("{}:{}:{}", self.display_path(path), pos.0 + 1, pos.1 + 1)
+                    }));
+                    popups.push(Popup::fit(&list, POPUP_DURATION, POPUP_COLOR));
+                    cmd.say =
+                        format!("{} references, `ref <n>` opens one", self.locations.len()).into();
+                    return true;
+                }
+            }
+            Request::Rename => match self.apply_workspace_edit(bm, result) {
+                Ok(count) => {
+                    cmd.say = format!("Renamed in {} files", count).into();
+                    Ok(())
+                }
+                Err(e) => Err(e.to_string()),
+            },
+            Request::CodeAction => {
+                let actions = result.as_array().cloned().unwrap_or_default();
+                self.actions = actions.into_iter().map(|a| (lang, a)).collect();
+                if self.actions.is_empty() {
+                    Err("No code actions".to_string())
+                } else {
+                    let list = self.numbered(
+                        self.actions
+                            .iter()
+                            .map(|(_, a)| a["title"].as_str().unwrap_or("?").to_string()),
+                    );
+                    popups.push(Popup::fit(&list, POPUP_DURATION, POPUP_COLOR));
+                    cmd.say = "`action <n>` applies one".into();
+                    return true;
+                }
+            }
+            Request::Completion => {
+                // a list, or a `CompletionList` with its items
+                let items = result.get("items").unwrap_or(result);
+                let items = items
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|item| {
+                        let label = item["label"].as_str()?.to_string();
+                        let insert = item["textEdit"]["newText"]
+                            .as_str()
+                            .or_else(|| item["insertText"].as_str())
+                            .unwrap_or(&label)
+                            .to_string();
+                        Some(Candidate {
+                            label,
+                            insert,
+                            source: Source::Lsp,
+                        })
+                    })
+                    .collect();
+                self.completions = Some((self.completion_buffer, items));
+                return true;
+            }
+            Request::ExecuteCommand | Request::Shutdown => return false,
+        };
+        if let Err(e) = res {
+            cmd.say = e.into();
+            cmd.status = CmdStatus::Failed;
+        }
+        true
+    }
+
+    fn numbered(&self, items: impl Iterator<Item = String>) -> String {
+        items
+            .enumerate()
+            .map(|(i, item)| format!("{} {}", i + 1, item))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn display_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    fn publish_diagnostics(&mut self, bm: &mut BufferManager, lang: &str, params: &Value) {
+        let Some(uri) = params["uri"].as_str() else {
+            return;
+        };
+        let raw = params["diagnostics"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let id = self
+            .docs
+            .iter()
+            .find(|(_, doc)| doc.uri == uri)
+            .map(|(id, _)| *id);
+        if let Some(buf) = id.and_then(|id| bm.get_buffer_mut(id).ok()) {
+            let diagnostics = raw
+                .iter()
+                .filter_map(|d| {
+                    let (start, end) = parse_range(&d["range"])?;
+                    Some(Diagnostic {
+                        start: to_buffer_pos(&buf.content, start),
+                        end: to_buffer_pos(&buf.content, end),
+                        severity: Severity::from_lsp(d["severity"].as_u64().unwrap_or(1)),
+                        message: d["message"].as_str()?.to_string(),
+                        source: d["source"].as_str().unwrap_or(lang).to_string(),
+                        producer: PRODUCER.to_string(),
+                    })
+                })
+                .collect();
+            buf.set_diagnostics(PRODUCER, diagnostics);
+        }
+        self.raw_diagnostics.insert(uri.to_string(), raw);
+    }
+
+    // open the file of `location` in the current pane, at its position
+    fn open_location(
+        &self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        location: &(PathBuf, (usize, usize)),
+    ) -> Result<(), LspError> {
+        let (path, pos) = location;
+        let id = bm.find_or_open(path)?;
+        let pos = to_buffer_pos(&bm.get_buffer(id)?.content, *pos);
+        lm.jump_to(bm, id, pos)?;
+        Ok(())
+    }
+
+    // apply the edits of a `WorkspaceEdit`, one undo step in each buffer,
+    // all of them or none, returns the number of files changed
+    pub fn apply_workspace_edit(
+        &mut self,
+        bm: &mut BufferManager,
+        edit: &Value,
+    ) -> Result<usize, LspError> {
+        let files = parse_workspace_edit(edit);
+        // the buffers are checked before any is changed
+        let mut targets = Vec::with_capacity(files.len());
+        for (path, edits) in files {
+            let id = bm.find_or_open(&path)?;
+            if bm.get_buffer(id)?.read_only {
+                return Err(BufferError::ReadOnly.into());
+            }
+            targets.push((id, edits));
+        }
+        let count = targets.len();
+        // the buffers changed so far, with whether they were saved
+        let mut applied: Vec<(usize, bool)> = vec![];
+        for (id, mut edits) in targets {
+            let buf = bm.get_buffer_mut(id)?;
+            // from the end, so the positions before stay right, the edits at
+            // the same position keep their order
+            edits.reverse();
+            edits.sort_by_key(|e| std::cmp::Reverse(e.start));
+            let ops = edits
+                .iter()
+                .map(|e| {
+                    let start = to_buffer_pos(&buf.content, e.start);
+                    let end = to_buffer_pos(&buf.content, e.end);
+                    EditOp::replace(start, end, &e.text)
+                })
+                .collect();
+            let saved = buf.saved;
+            if let Err(e) = buf.apply_op(EditOp::Group(ops), true) {
+                for (id, saved) in applied.into_iter().rev() {
+                    if let Ok(buf) = bm.get_buffer_mut(id)
+                        && buf.revoke().is_ok()
+                    {
+                        buf.saved = saved;
+                    }
+                }
+                return Err(e.into());
+            }
+            applied.push((id, saved));
+        }
+        Ok(count)
+    }
+
+    // the language and the `TextDocumentPositionParams` of the cursor
+    fn cursor_params(
+        &self,
+        bm: &BufferManager,
+        lm: &LayoutManager,
+    ) -> Result<(&'static str, Value), LspError> {
+        let buf = lm.get_current_buffer(bm)?;
+        let Some(doc) = self.docs.get(&buf.id) else {
+            let lang = buf.language().map(|l| l.name);
+            return match lang {
+                Some(lang) if self.clients.contains_key(lang) => Err(LspError::NotReady),
+                _ => Err(LspError::NoServer),
+            };
+        };
+        let pos = to_lsp_pos(&buf.content, cursor_pos(lm)?);
+        let params = json!({
+            "textDocument": { "uri": doc.uri },
+            "position": position_json(pos),
+        });
+        Ok((doc.lang, params))
+    }
+
+    fn request_at_cursor(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &LayoutManager,
+        method: &str,
+        extra: Value,
+        kind: Request,
+    ) -> Result<(), LspError> {
+        // the server has to see the latest edits first
+        self.sync(bm);
+        let (lang, mut params) = self.cursor_params(bm, lm)?;
+        if let (Some(params), Value::Object(extra)) = (params.as_object_mut(), extra) {
+            params.extend(extra);
+        }
+        let client = self.clients.get_mut(lang).ok_or(LspError::NoServer)?;
+        client.request(method, params, kind)?;
+        Ok(())
+    }
+
+    pub fn hover(&mut self, bm: &mut BufferManager, lm: &LayoutManager) -> Result<(), LspError> {
+        self.request_at_cursor(bm, lm, "textDocument/hover", json!({}), Request::Hover)
+    }
+
+    pub fn definition(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &LayoutManager,
+    ) -> Result<(), LspError> {
+        let method = "textDocument/definition";
+        self.request_at_cursor(bm, lm, method, json!({}), Request::Definition)
+    }
+
+    pub fn references(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &LayoutManager,
+    ) -> Result<(), LspError> {
+        let extra = json!({ "context": { "includeDeclaration": true } });
+        self.request_at_cursor(
+            bm,
+            lm,
+            "textDocument/references",
+            extra,
+            Request::References,
+        )
+    }
+
+    pub fn rename(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &LayoutManager,
+        new_name: &str,
+    ) -> Result<(), LspError> {
+        let extra = json!({ "newName": new_name });
+        self.request_at_cursor(bm, lm, "textDocument/rename", extra, Request::Rename)
+    }
+
+    // the code actions of the cursor, with the diagnostics of its line
+    pub fn code_actions(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &LayoutManager,
+    ) -> Result<(), LspError> {
+        self.sync(bm);
+        let (lang, params) = self.cursor_params(bm, lm)?;
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let diagnostics: Vec<Value> = self
+            .raw_diagnostics
+            .get(uri)
+            .into_iter()
+            .flatten()
+            .filter(|d| parse_range(&d["range"]).is_some_and(|(s, e)| s.0 <= line && line <= e.0))
+            .cloned()
+            .collect();
+        let params = json!({
+            "textDocument": params["textDocument"],
+            "range": { "start": params["position"], "end": params["position"] },
+            "context": { "diagnostics": diagnostics },
+        });
+        let client = self.clients.get_mut(lang).ok_or(LspError::NoServer)?;
+        client.request("textDocument/codeAction", params, Request::CodeAction)?;
+        Ok(())
+    }
+
+    // apply the code action `n` of the last list, counted from 1
+    pub fn run_action(&mut self, bm: &mut BufferManager, n: usize) -> Result<(), LspError> {
+        let (lang, action) = self
+            .actions
+            .get(n.wrapping_sub(1))
+            .cloned()
+            .ok_or(LspError::NoAction(n))?;
+        if let Some(edit) = action.get("edit") {
+            self.apply_workspace_edit(bm, edit)?;
+        }
+        // a `Command` itself, or the command of a `CodeAction`
+        let command = match &action["command"] {
+            Value::String(_) => &action,
+            command @ Value::Object(_) => command,
+            _ => return Ok(()),
+        };
+        let mut params = json!({ "command": command["command"] });
+        if let Some(args) = command.get("arguments") {
+            params["arguments"] = args.clone();
+        }
+        let client = self.clients.get_mut(lang).ok_or(LspError::NoServer)?;
+        client.request("workspace/executeCommand", params, Request::ExecuteCommand)?;
+        Ok(())
+    }
+
+    // ask for the completions at the cursor, returns false when the server
+    // has none to give
+    pub fn completion(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &LayoutManager,
+    ) -> Result<bool, LspError> {
+        self.sync(bm);
+        let (lang, params) = self.cursor_params(bm, lm)?;
+        let client = self.clients.get_mut(lang).ok_or(LspError::NoServer)?;
+        if !client.supports("completionProvider") {
+            return Ok(false);
+        }
+        client.request("textDocument/completion", params, Request::Completion)?;
+        self.completion_buffer = lm.get_current_buffer(bm)?.id;
+        self.completions = None;
+        Ok(true)
+    }
+
+    // the completions which came since the last call, and their buffer
+    pub fn take_completions(&mut self) -> Option<(usize, Vec<Candidate>)> {
+        self.completions.take()
+    }
+
+    // open the reference `n` of the last list, counted from 1
+    pub fn open_reference(
+        &self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        n: usize,
+    ) -> Result<(), LspError> {
+        let location = self
+            .locations
+            .get(n.wrapping_sub(1))
+            .ok_or(LspError::NoLocation(n))?;
+        self.open_location(bm, lm, location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Diagnostic;
+    use std::fs;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Instant;
+
+    // built by `cargo test` with the other targets
+    fn mock_lsp() -> String {
+        let exe = std::env::current_exe().unwrap();
+        let dir = exe.parent().and_then(Path::parent).unwrap();
+        dir.join("examples/mock_lsp").display().to_string()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("smile_lsp_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    struct Harness {
+        lsp: LspManager,
+        rx: Receiver<AppEvent>,
+        bm: BufferManager,
+        lm: LayoutManager,
+        popups: Popups,
+        cmd: KaoCo,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let (tx, rx) = mpsc::channel();
+            Self {
+                lsp: LspManager::new(tx),
+                rx,
+                bm: BufferManager::new(),
+                lm: LayoutManager::new(),
+                popups: Popups::new(),
+                cmd: KaoCo::new(),
+            }
+        }
+
+        // handle the messages of the servers until `done`
+        fn wait(&mut self, done: impl Fn(&Self) -> bool) {
+            let start = Instant::now();
+            while !done(self) {
+                assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+                let Ok(event) = self.rx.recv_timeout(Duration::from_millis(100)) else {
+                    continue;
+                };
+                if let AppEvent::Lsp { server, message } = event {
+                    self.lsp.handle_message(
+                        &mut self.bm,
+                        &mut self.lm,
+                        &mut self.popups,
+                        &mut self.cmd,
+                        server,
+                        message,
+                    );
+                }
+            }
+        }
+
+        fn diagnostics(&self, id: usize) -> Vec<(Severity, (usize, usize))> {
+            let buf = self.bm.get_buffer(id).unwrap();
+            let mut found: Vec<_> = buf
+                .diagnostics
+                .iter()
+                .map(|d: &Diagnostic| (d.severity, d.start))
+                .collect();
+            found.sort_by_key(|(_, start)| *start);
+            found
+        }
+    }
+
+    #[test]
+    fn initialize_change_and_diagnostics() {
+        let dir = temp_dir("sync");
+        let path = dir.join("main.rs");
+        fs::write(&path, "fn main() {}\n// TODO one\n").unwrap();
+        let mut h = Harness::new();
+        let id = h.bm.add_new_buffer_from_path(&path).unwrap();
+        h.lm.init(id);
+
+        // nothing is started until a server is asked for
+        assert_eq!(h.lsp.sync(&mut h.bm), None);
+        assert!(h.lsp.clients.is_empty());
+
+        h.lsp.set_server(&mut h.bm, "rust", &mock_lsp()).unwrap();
+        assert_eq!(h.lsp.sync(&mut h.bm), None);
+        h.wait(|h| h.lsp.clients.get("rust").is_some_and(|c| c.initialized));
+        assert_eq!(h.lsp.clients["rust"].sync_kind(), SyncKind::Incremental);
+
+        // opened by the sync after `initialize`, the server answers with the
+        // diagnostics of the whole text
+        h.lsp.sync(&mut h.bm);
+        h.wait(|h| !h.bm.get_buffer(id).unwrap().diagnostics.is_empty());
+        assert_eq!(h.diagnostics(id), vec![(Severity::Warning, (3, 1))]);
+
+        // only the inserted text is sent
+        let buf = h.bm.get_buffer_mut(id).unwrap();
+        buf.apply_op(EditOp::replace((3, 1), (3, 1), "FIXME "), true)
+            .unwrap();
+        assert!(!buf.changes.is_empty());
+        assert!(!buf.changes.iter().any(|c| matches!(c, DocChange::Full)));
+        h.lsp.sync(&mut h.bm);
+        h.wait(|h| h.bm.get_buffer(id).unwrap().diagnostics.len() == 2);
+        assert_eq!(
+            h.diagnostics(id),
+            vec![(Severity::Error, (3, 1)), (Severity::Warning, (9, 1))]
+        );
+
+        h.lsp.shutdown_all();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_initialize() {
+        let dir = temp_dir("init");
+        let path = dir.join("main.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+        let mut h = Harness::new();
+        let id = h.bm.add_new_buffer_from_path(&path).unwrap();
+        h.lm.init(id);
+
+        let command = format!("{} --fail-init", mock_lsp());
+        h.lsp.set_server(&mut h.bm, "rust", &command).unwrap();
+        h.lsp.sync(&mut h.bm);
+        h.wait(|h| h.lsp.clients.is_empty());
+        assert!(
+            h.lsp
+                .status()
+                .contains("failed to initialize: no workspace")
+        );
+        assert!(matches!(h.cmd.status, CmdStatus::Failed));
+        // it isn't started again by itself
+        h.lsp.sync(&mut h.bm);
+        assert!(h.lsp.clients.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn workspace_edit_is_all_or_nothing() {
+        let dir = temp_dir("edit");
+        let (a, b) = (dir.join("a.rs"), dir.join("b.rs"));
+        fs::write(&a, "let x = 1;\n").unwrap();
+        fs::write(&b, "let x = 2;\n").unwrap();
+        let mut h = Harness::new();
+        let a_id = h.bm.add_new_buffer_from_path(&a).unwrap();
+        let b_id = h.bm.add_new_buffer_from_path(&b).unwrap();
+        h.bm.get_buffer_mut(b_id).unwrap().read_only = true;
+
+        let rename = json!([{
+            "range": {
+                "start": { "line": 0, "character": 4 },
+                "end": { "line": 0, "character": 5 },
+            },
+            "newText": "y",
+        }]);
+        let mut changes = serde_json::Map::new();
+        changes.insert(path_to_uri(&a), rename.clone());
+        changes.insert(path_to_uri(&b), rename);
+        let edit = json!({ "changes": changes });
+        assert!(h.lsp.apply_workspace_edit(&mut h.bm, &edit).is_err());
+        let buf = h.bm.get_buffer(a_id).unwrap();
+        assert_eq!(buf.content[0], "let x = 1;");
+        assert!(buf.saved);
+
+        h.bm.get_buffer_mut(b_id).unwrap().read_only = false;
+        assert_eq!(h.lsp.apply_workspace_edit(&mut h.bm, &edit).unwrap(), 2);
+        assert_eq!(h.bm.get_buffer(a_id).unwrap().content[0], "let y = 1;");
+        assert_eq!(h.bm.get_buffer(b_id).unwrap().content[0], "let y = 2;");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,227 @@
+#![allow(dead_code)]
+// one language server, running as a child process which talks over its
+// stdin and stdout, its messages come back through the event loop
+use crate::event_loop::AppEvent;
+use crate::lsp::protocol::{path_to_uri, read_message, write_message};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// what a request was for, so its response can be handled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Initialize,
+    Hover,
+    Definition,
+    References,
+    Rename,
+    CodeAction,
+    Completion,
+    ExecuteCommand,
+    Shutdown,
+}
+
+// how the server wants to hear about the changes of a document
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncKind {
+    None,
+    Full,
+    Incremental,
+}
+
+pub struct LspClient {
+    // a restarted server gets a new one, the events of the old one are ignored
+    pub id: usize,
+    pub lang: &'static str,
+    pub command: String,
+    child: Child,
+    // the messages go through a thread, a server which doesn't read them
+    // can't block the editor
+    input: Sender<Value>,
+    next_id: i64,
+    pub pending: HashMap<i64, Request>,
+    // documents are only opened after the server answered `initialize`
+    pub initialized: bool,
+    pub capabilities: Value,
+}
+
+// how long a server gets to exit by itself before it's killed
+const EXIT_TIMEOUT: Duration = Duration::from_millis(300);
+
+impl LspClient {
+    // start `command` in `root` and send `initialize`, the messages of the
+    // server are posted as `AppEvent::Lsp` until it exits
+    pub fn spawn(
+        id: usize,
+        lang: &'static str,
+        command: &str,
+        root: &Path,
+        tx: Sender<AppEvent>,
+    ) -> io::Result<Self> {
+        let mut parts = command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // it would be drawn over the editor
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(mut stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(io::Error::other("no pipes to the server"));
+        };
+
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if tx
+                    .send(AppEvent::Lsp {
+                        server: id,
+                        message,
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            let _ = tx.send(AppEvent::LspExited(id));
+        });
+
+        let (input, rx) = mpsc::channel::<Value>();
+        thread::spawn(move || {
+            for message in rx {
+                if write_message(&mut stdin, &message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = Self {
+            id,
+            lang,
+            command: command.to_string(),
+            child,
+            input,
+            next_id: 1,
+            pending: HashMap::new(),
+            initialized: false,
+            capabilities: Value::Null,
+        };
+        let root_uri = path_to_uri(root);
+        let name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        client.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "clientInfo": { "name": "smile" },
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": name }],
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-16"] },
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                        "definition": { "linkSupport": true },
+                        "references": {},
+                        "rename": {},
+                        "publishDiagnostics": {},
+                        "completion": { "completionItem": { "snippetSupport": false } },
+                        "codeAction": {
+                            "codeActionLiteralSupport": {
+                                "codeActionKind": {
+                                    "valueSet": ["", "quickfix", "refactor", "source"]
+                                }
+                            }
+                        }
+                    },
+                    "workspace": {
+                        "applyEdit": true,
+                        "configuration": true,
+                        "workspaceEdit": { "documentChanges": true }
+                    }
+                }
+            }),
+            Request::Initialize,
+        )?;
+        Ok(client)
+    }
+
+    // send a request, returns its id
+    pub fn request(&mut self, method: &str, params: Value, kind: Request) -> io::Result<i64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.send(message)?;
+        self.pending.insert(id, kind);
+        Ok(id)
+    }
+
+    pub fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.send(message)
+    }
+
+    // answer a request of the server
+    pub fn respond(&mut self, id: Value, result: Value) -> io::Result<()> {
+        let message = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+        self.send(message)
+    }
+
+    // fails once the writer stopped on an error
+    fn send(&self, message: Value) -> io::Result<()> {
+        self.input
+            .send(message)
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    pub fn sync_kind(&self) -> SyncKind {
+        let sync = &self.capabilities["textDocumentSync"];
+        let kind = sync.as_u64().or_else(|| sync["change"].as_u64());
+        match kind {
+            Some(0) => SyncKind::None,
+            Some(1) => SyncKind::Full,
+            _ => SyncKind::Incremental,
+        }
+    }
+
+    // whether the server offers `capability`, like `hoverProvider`
+    pub fn supports(&self, capability: &str) -> bool {
+        !matches!(
+            self.capabilities[capability],
+            Value::Null | Value::Bool(false)
+        )
+    }
+
+    // ask the server to exit, and kill it if it doesn't
+    pub fn shutdown(&mut self) {
+        let _ = self.request("shutdown", Value::Null, Request::Shutdown);
+        let _ = self.notify("exit", Value::Null);
+        let start = Instant::now();
+        while start.elapsed() < EXIT_TIMEOUT {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    // reap the process after it exited by itself
+    pub fn reap(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
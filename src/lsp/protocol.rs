@@ -0,0 +1,270 @@
+#![allow(dead_code)]
+// the wire format of the language server protocol: JSON-RPC messages after a
+// `Content-Length` header, positions in UTF-16 code units and file URIs
+use crate::op::EditOp;
+use crate::utils::{char_to_byte_idx, get_line_len};
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use unicode_segmentation::UnicodeSegmentation;
+
+// a change of the text the server has to hear about
+#[derive(Debug, Clone)]
+pub enum DocChange {
+    // `text` replaces the text between the (line, UTF-16 column) positions
+    Range {
+        start: (usize, usize),
+        end: (usize, usize),
+        text: String,
+    },
+    // the content was replaced as a whole
+    Full,
+}
+
+// a text edit of the server, in its positions
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub text: String,
+}
+
+// the next message, `None` when the server closed its output
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(v) = header.strip_prefix("Content-Length:") {
+            len = v.trim().parse().ok();
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Content-Length"))?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+// the UTF-16 column of the grapheme `x` of `line`
+pub fn utf16_col(line: &str, x: usize) -> usize {
+    line[..char_to_byte_idx(line, x)].encode_utf16().count()
+}
+
+// the grapheme at the UTF-16 column `col`, a column inside a grapheme is its head
+pub fn grapheme_x(line: &str, col: usize) -> usize {
+    let mut used = 0;
+    for (x, g) in line.graphemes(true).enumerate() {
+        let w = g.encode_utf16().count();
+        if used + w > col {
+            return x;
+        }
+        used += w;
+    }
+    get_line_len(line)
+}
+
+// the buffer position (x, y) of a server position (line, column)
+pub fn to_buffer_pos(content: &[String], pos: (usize, usize)) -> (usize, usize) {
+    let (line, col) = pos;
+    match content.get(line) {
+        Some(text) => (grapheme_x(text, col), line),
+        // past the end, like the end of the last line
+        None => {
+            let y = content.len().saturating_sub(1);
+            (get_line_len(&content[y]), y)
+        }
+    }
+}
+
+pub fn to_lsp_pos(content: &[String], pos: (usize, usize)) -> (usize, usize) {
+    let (x, y) = pos;
+    (y, content.get(y).map_or(0, |line| utf16_col(line, x)))
+}
+
+pub fn position_json(pos: (usize, usize)) -> Value {
+    json!({ "line": pos.0, "character": pos.1 })
+}
+
+pub fn parse_position(v: &Value) -> Option<(usize, usize)> {
+    Some((
+        v.get("line")?.as_u64()? as usize,
+        v.get("character")?.as_u64()? as usize,
+    ))
+}
+
+pub fn parse_range(v: &Value) -> Option<((usize, usize), (usize, usize))> {
+    Some((
+        parse_position(v.get("start")?)?,
+        parse_position(v.get("end")?)?,
+    ))
+}
+
+// the change `op` makes to `content`, taken before it's applied
+pub fn doc_change(content: &[String], op: &EditOp) -> Option<DocChange> {
+    let line_end = |y: usize| (y, content.get(y).map_or(0, |l| l.encode_utf16().count()));
+    let at = |pos: (usize, usize)| to_lsp_pos(content, pos);
+    let (start, end, text) = match op {
+        EditOp::Insert { pos, text, .. } => (at(*pos), at(*pos), text.to_string()),
+        EditOp::Delete { pos, len, .. } => (at(*pos), at((pos.0 + len, pos.1)), String::new()),
+        EditOp::InsertLine { y, text } if *y < content.len() => {
+            ((*y, 0), (*y, 0), format!("{}\n", text))
+        }
+        // appended after the last line
+        EditOp::InsertLine { y, text } => {
+            let end = line_end(y.saturating_sub(1));
+            (end, end, format!("\n{}", text))
+        }
+        EditOp::DeleteLine { .. } if content.len() == 1 => ((0, 0), line_end(0), String::new()),
+        EditOp::DeleteLine { y, .. } if y + 1 < content.len() => {
+            ((*y, 0), (y + 1, 0), String::new())
+        }
+        EditOp::DeleteLine { y, .. } => {
+            (line_end(y.saturating_sub(1)), line_end(*y), String::new())
+        }
+        EditOp::DeleteBlock {
+            start_pos, end_pos, ..
+        } => {
+            let (s, e) = if (start_pos.1, start_pos.0) <= (end_pos.1, end_pos.0) {
+                (start_pos, end_pos)
+            } else {
+                (end_pos, start_pos)
+            };
+            (at(*s), at(*e), String::new())
+        }
+        EditOp::InsertBlock {
+            start_pos, text, ..
+        } => (at(*start_pos), at(*start_pos), text.clone()),
+        // its members are recorded one by one
+        EditOp::Group(_) => return None,
+    };
+    Some(DocChange::Range { start, end, text })
+}
+
+pub fn change_json(change: &DocChange, content: &[String]) -> Value {
+    match change {
+        DocChange::Range { start, end, text } => json!({
+            "range": { "start": position_json(*start), "end": position_json(*end) },
+            "text": text,
+        }),
+        DocChange::Full => json!({ "text": content.join("\n") }),
+    }
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let abs = fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for b in abs.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i] == b'%' {
+            let hex = std::str::from_utf8(rest.get(i + 1..i + 3)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(rest[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+// the file and the position of a `Location` or a `LocationLink`
+pub fn parse_location(v: &Value) -> Option<(PathBuf, (usize, usize))> {
+    let (uri, range) = match v.get("targetUri") {
+        Some(uri) => (uri, v.get("targetSelectionRange")?),
+        None => (v.get("uri")?, v.get("range")?),
+    };
+    Some((uri_to_path(uri.as_str()?)?, parse_range(range)?.0))
+}
+
+// a single location or a list of them, `null` is none
+pub fn parse_locations(v: &Value) -> Vec<(PathBuf, (usize, usize))> {
+    match v {
+        Value::Array(items) => items.iter().filter_map(parse_location).collect(),
+        Value::Null => vec![],
+        v => parse_location(v).into_iter().collect(),
+    }
+}
+
+fn parse_text_edits(v: &Value) -> Vec<TextEdit> {
+    v.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|e| {
+            let (start, end) = parse_range(e.get("range")?)?;
+            let text = e.get("newText")?.as_str()?.replace("\r\n", "\n");
+            Some(TextEdit { start, end, text })
+        })
+        .collect()
+}
+
+// the edits of each file in a `WorkspaceEdit`, the file operations are skipped
+pub fn parse_workspace_edit(v: &Value) -> Vec<(PathBuf, Vec<TextEdit>)> {
+    let mut files = vec![];
+    if let Some(changes) = v.get("changes").and_then(Value::as_object) {
+        for (uri, edits) in changes {
+            if let Some(path) = uri_to_path(uri) {
+                files.push((path, parse_text_edits(edits)));
+            }
+        }
+    }
+    for change in v
+        .get("documentChanges")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let uri = change
+            .get("textDocument")
+            .and_then(|d| d.get("uri"))
+            .and_then(Value::as_str);
+        if let (Some(path), Some(edits)) = (uri.and_then(uri_to_path), change.get("edits")) {
+            files.push((path, parse_text_edits(edits)));
+        }
+    }
+    files
+}
+
+// the text of a hover, the markup is shown as it is
+pub fn hover_text(v: &Value) -> Option<String> {
+    fn marked(v: &Value) -> Option<String> {
+        match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(items) => {
+                let parts: Vec<String> = items.iter().filter_map(marked).collect();
+                (!parts.is_empty()).then(|| parts.join("\n\n"))
+            }
+            v => v.get("value")?.as_str().map(str::to_string),
+        }
+    }
+    let text = marked(v.get("contents")?)?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
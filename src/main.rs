@@ -4,17 +4,21 @@ use ratatui::Terminal;
 use ratatui::backend::{Backend, CrosstermBackend};
 use ratatui::crossterm::cursor::SetCursorStyle;
 use ratatui::crossterm::event::DisableMouseCapture;
-use ratatui::crossterm::event::{self, KeyCode, KeyEventKind};
 use ratatui::crossterm::event::{EnableMouseCapture, Event, KeyModifiers};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::crossterm::event::{MouseButton, MouseEventKind};
 use ratatui::crossterm::execute;
 use ratatui::crossterm::terminal::{EnterAlternateScreen, enable_raw_mode};
 use ratatui::crossterm::terminal::{LeaveAlternateScreen, disable_raw_mode};
 use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 
 mod app;
 use app::{App, Mod, Screen};
 
 mod buffer;
+use buffer::DiskChange;
 
 mod ui;
 use ui::ui;
@@ -33,9 +37,46 @@ use command::*;
 
 mod layout;
 use layout::layout_manager::MoveDir;
+use layout::tree::{PaneContent, SplitDirection};
 
+mod comment;
+mod completion;
+use completion::Completion;
 mod cursor;
+mod diagnostic;
+mod diff;
+mod diffview;
+mod encoding;
+mod errorformat;
+mod event_loop;
+mod explorer;
+use event_loop::{AppEvent, TimerKind};
+mod fileio;
+mod format;
+mod git;
+mod indent;
+mod language;
+mod largefile;
+mod linecache;
+mod lines;
+mod lsp;
+mod make;
+mod options;
+mod pairs;
 mod popup;
+// the terminal panes, only on unix
+#[cfg(unix)]
+mod pty;
+mod quickfix;
+mod shell;
+mod swap;
+#[cfg(unix)]
+mod terminal;
+#[cfg(unix)]
+mod vt;
+mod watcher;
+mod wrap;
+use watcher::FileWatcher;
 
 fn main() -> Result<()> {
     // setup terminal
@@ -49,14 +90,17 @@ fn main() -> Result<()> {
     let args = Args::parse();
     let mut app = App::new();
     // initialize
-    if let Some(_) = args.path {
+    if args.path.is_some() {
         // todo: error handle
-        app.init(args.path).unwrap();
+        app.init(args.path, args.readonly).unwrap();
     } else {
         app.current_screen = Screen::Welcome;
     };
 
     run_app(&mut terminal, &mut app)?;
+    app.lsp.shutdown_all();
+    // the edits are either saved or discarded now
+    app.buf_manager.remove_swaps();
 
     // restore terminal
     disable_raw_mode()?;
@@ -71,242 +115,854 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+// lines read around the panes of large files
+const LARGE_FILE_MARGIN: usize = 100;
+
 fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()>
 where
     <B as Backend>::Error: Sync + Send + 'static,
 {
+    let events = &mut app.events;
+    events.spawn_input_reader();
+    events.add_timer(TimerKind::Popups, Duration::from_millis(200));
+    events.add_timer(TimerKind::Autosave, Duration::from_secs(1));
+    events.add_timer(TimerKind::CursorBlink, CURSOR_BLINK_INTERVAL);
+    events.add_timer(TimerKind::LargeFiles, Duration::from_millis(100));
+    events.add_timer(TimerKind::Git, Duration::from_millis(500));
+    let watcher = FileWatcher::spawn(app.events.sender());
+    // files changed by other programs, handled once the editor has the focus
+    let mut changed_files: Vec<PathBuf> = vec![];
+
+    // the frame is only drawn again when something changed
+    let mut redraw = true;
     loop {
-        // todo: error handle
-        terminal.draw(|f| match ui(f, app) {
-            Ok(_) => {}
-            Err(e) => match e {
-                RenderError::LayoutErr(LayoutError::NoNode) => {
-                    app.should_exit = true;
-                }
-                error => {
-                    println!("{:?}", error)
-                }
-            },
-        })?;
+        // read the lines of large files the panes are about to show
+        let visible = app.layout_manager.visible_lines(LARGE_FILE_MARGIN);
+        redraw |= app.buf_manager.poll_large_files(&visible);
+        // the language servers hear about the edits before anything else
+        if let Some(e) = app.lsp.sync(&mut app.buf_manager) {
+            app.command.say = e.into();
+            app.command.status = CmdStatus::Failed;
+            redraw = true;
+        }
+        if matches!(app.current_screen, Screen::Editor) && !changed_files.is_empty() {
+            handle_changed_files(app, &mut changed_files)?;
+            redraw = true;
+        }
+        // the programs of closed terminal panes are killed
+        #[cfg(unix)]
+        app.terminals.prune(&app.layout_manager);
+        // the explorer opens files in the pane focused last
+        if app.layout_manager.current_content().is_none() {
+            app.explorer.last_pane = app.layout_manager.current_layout;
+        }
+        if redraw {
+            // the diff follows the edits and the scrolling of its panes
+            if let Some(view) = &mut app.diff
+                && !view.update(&app.buf_manager, &mut app.layout_manager)
+            {
+                app.diff = None;
+            }
+            // todo: error handle
+            terminal.draw(|f| match ui(f, app) {
+                Ok(_) => {}
+                Err(e) => match e {
+                    RenderError::LayoutErr(LayoutError::NoNode) => {
+                        app.should_exit = true;
+                    }
+                    error => {
+                        println!("{:?}", error)
+                    }
+                },
+            })?;
+            redraw = false;
+            // the terminals take the size of their panes, drawn again if it
+            // changed
+            #[cfg(unix)]
+            if app.terminals.fit(&app.layout_manager) {
+                redraw = true;
+                continue;
+            }
+        }
 
         if app.should_exit {
             break Ok(());
         }
-        app.popups.update();
-        let cur_cmd = &mut app.command;
-        let cur_screen = &mut app.current_screen;
-        let layout_m = &mut app.layout_manager;
-        let buffer_m = &mut app.buf_manager;
-        if let Event::Key(key) = event::read()? {
-            if key.kind != KeyEventKind::Press {
-                continue;
+        watcher.set_paths(app.buf_manager.paths());
+
+        match app.events.next() {
+            AppEvent::Input(Event::Key(key)) => {
+                // the cursor stays visible while typing
+                app.cursor_shown = true;
+                app.events.reset_timer(TimerKind::CursorBlink);
+                handle_key(app, key)?;
+                redraw = true;
             }
-            match *cur_screen {
-                Screen::Welcome => match key.code {
-                    KeyCode::Char('q') => {
-                        return Ok(());
+            AppEvent::Input(Event::Mouse(mouse)) => {
+                // a left click moves the cursor, the other mouse events are ignored
+                if mouse.kind == MouseEventKind::Down(MouseButton::Left)
+                    && matches!(app.current_screen, Screen::Editor)
+                {
+                    app.completion = None;
+                    let clicked = click_in_pane(
+                        &app.buf_manager,
+                        &mut app.layout_manager,
+                        mouse.column,
+                        mouse.row,
+                    )?;
+                    // a click in the explorer selects the row under it
+                    if clicked
+                        && app.layout_manager.current_content() == Some(PaneContent::Explorer)
+                        && let Some(rect) = app.layout_manager.get_current_rect()
+                        && mouse.row > rect.y
+                    {
+                        let row = (mouse.row - rect.y - 1) as usize;
+                        let explorer = &mut app.explorer;
+                        if explorer.scroll + row < explorer.entries.len() {
+                            explorer.selected = explorer.scroll + row;
+                        }
+                    }
+                    if clicked && let Some(view) = &app.diff {
+                        view.click(
+                            &app.buf_manager,
+                            &mut app.layout_manager,
+                            mouse.column,
+                            mouse.row,
+                        );
+                    }
+                    redraw |= clicked;
+                }
+            }
+            AppEvent::Input(_) => redraw = true,
+            AppEvent::Timer(kind) => redraw |= handle_timer(app, kind),
+            AppEvent::FileChanged(path) => {
+                if !changed_files.contains(&path) {
+                    changed_files.push(path);
+                }
+            }
+            AppEvent::JobDone { id, output } => {
+                let (bm, lm) = (&mut app.buf_manager, &mut app.layout_manager);
+                redraw |= app.command.job_done(bm, lm, id, output);
+            }
+            AppEvent::JobLines { id, lines } => {
+                redraw |= app
+                    .make
+                    .output(&mut app.buf_manager, &mut app.layout_manager, id, lines);
+            }
+            AppEvent::JobExited { id, code } => {
+                if let Some(message) =
+                    app.make
+                        .finish(&mut app.buf_manager, &mut app.quickfix, id, code)
+                {
+                    app.command.say = message.into();
+                    redraw = true;
+                }
+            }
+            AppEvent::Lsp { server, message } => {
+                redraw |= app.lsp.handle_message(
+                    &mut app.buf_manager,
+                    &mut app.layout_manager,
+                    &mut app.popups,
+                    &mut app.command,
+                    server,
+                    message,
+                );
+                // the items of the server join the menu they were asked for
+                if let Some((id, items)) = app.lsp.take_completions()
+                    && let Some(menu) = &mut app.completion
+                    && menu.buffer_id == id
+                {
+                    menu.add_items(items);
+                }
+            }
+            AppEvent::LspExited(server) => {
+                app.lsp
+                    .server_exited(&mut app.buf_manager, &mut app.command, server);
+                redraw = true;
+            }
+            #[cfg(unix)]
+            AppEvent::TerminalOutput { id, bytes } => {
+                redraw |= app.terminals.output(id, &bytes);
+            }
+            #[cfg(unix)]
+            AppEvent::TerminalExited(id) => {
+                if let Some(message) = app.terminals.exited(id) {
+                    app.command.say = message.into();
+                    redraw = true;
+                }
+            }
+            AppEvent::InputClosed => break Ok(()),
+        }
+    }
+}
+
+// how often the cursor turns on and off
+const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+// returns whether the screen has to be drawn again
+fn handle_timer(app: &mut App, kind: TimerKind) -> bool {
+    match kind {
+        TimerKind::Popups => app.popups.update(),
+        TimerKind::Autosave => {
+            app.buf_manager.write_swaps();
+            false
+        }
+        TimerKind::CursorBlink => {
+            app.cursor_shown = !app.cursor_shown;
+            !matches!(app.current_screen, Screen::Welcome)
+        }
+        // the lines are taken before drawing, the timer only wakes the loop
+        TimerKind::LargeFiles => false,
+        TimerKind::Git => {
+            let mut changed = app.buf_manager.refresh_git();
+            // the ignored files of the explorer, while it's shown
+            let shown = app.layout_manager.pane_with(PaneContent::Explorer);
+            if shown.is_some() && app.explorer.load_ignored() {
+                app.explorer.refresh();
+                changed = true;
+            }
+            changed
+        }
+    }
+}
+
+fn handle_changed_files(app: &mut App, changed_files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut asked = false;
+    while let Some(path) = changed_files.pop() {
+        for (id, change) in app.buf_manager.check_disk_changes(&path) {
+            // one question at a time, the others are asked later
+            if asked && matches!(change, DiskChange::Changed(_)) {
+                changed_files.push(path);
+                return Ok(());
+            }
+            asked |= handle_disk_change(
+                &mut app.command,
+                &mut app.buf_manager,
+                &mut app.current_screen,
+                &mut app.popups,
+                id,
+                change,
+            )?;
+        }
+        if asked {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// the keys of a focused terminal go to its program, except for the ones to
+// leave it and to scroll back through its output
+#[cfg(unix)]
+fn handle_terminal_key(app: &mut App, id: usize, key: KeyEvent) -> Result<()> {
+    let layout_m = &mut app.layout_manager;
+    let half_page = layout_m
+        .get_current_rect()
+        .map_or(1, |r| (r.height / 2).max(1)) as isize;
+    match (key.modifiers, key.code) {
+        (KeyModifiers::CONTROL, KeyCode::Char('q')) => {
+            close_current_pane(
+                &mut app.command,
+                &app.buf_manager,
+                layout_m,
+                &mut app.should_exit,
+                &mut app.current_screen,
+            )?;
+        }
+        (KeyModifiers::CONTROL, KeyCode::Char('x')) => {
+            app.current_screen = Screen::Command;
+            app.command.clean_all();
+        }
+        (KeyModifiers::CONTROL, KeyCode::Left) => move_focus_in_pane(layout_m, MoveDir::Left),
+        (KeyModifiers::CONTROL, KeyCode::Right) => move_focus_in_pane(layout_m, MoveDir::Right),
+        (KeyModifiers::CONTROL, KeyCode::Up) => move_focus_in_pane(layout_m, MoveDir::Up),
+        (KeyModifiers::CONTROL, KeyCode::Down) => move_focus_in_pane(layout_m, MoveDir::Down),
+        (KeyModifiers::SHIFT, KeyCode::PageUp | KeyCode::PageDown) => {
+            if let Some(term) = app.terminals.get_mut(id) {
+                match key.code {
+                    KeyCode::PageUp => term.scroll_by(half_page),
+                    _ => term.scroll_by(-half_page),
+                }
+            }
+        }
+        _ => {
+            if let Some(term) = app.terminals.get_mut(id)
+                && let Err(e) = term.send_key(key)
+            {
+                app.command.say = format!("Failed to write to the terminal: {}", e).into();
+                app.command.status = CmdStatus::Failed;
+            }
+        }
+    }
+    Ok(())
+}
+
+// the keys of the explorer move in its tree, open the files and change them
+// after a question, the ones to leave panes work as in the others
+fn handle_explorer_key(app: &mut App, key: KeyEvent) -> Result<()> {
+    let layout_m = &mut app.layout_manager;
+    let explorer = &mut app.explorer;
+    let page = layout_m
+        .get_current_rect()
+        .map_or(1, |r| r.height.saturating_sub(2).max(1)) as isize;
+    let mut res = Ok(());
+    match (key.modifiers, key.code) {
+        (KeyModifiers::CONTROL, KeyCode::Char('q')) => {
+            res = close_current_pane(
+                &mut app.command,
+                &app.buf_manager,
+                layout_m,
+                &mut app.should_exit,
+                &mut app.current_screen,
+            );
+        }
+        (KeyModifiers::CONTROL, KeyCode::Char('x')) => {
+            app.current_screen = Screen::Command;
+            app.command.clean_all();
+        }
+        (KeyModifiers::CONTROL, KeyCode::Left) => move_focus_in_pane(layout_m, MoveDir::Left),
+        (KeyModifiers::CONTROL, KeyCode::Right) => move_focus_in_pane(layout_m, MoveDir::Right),
+        (KeyModifiers::CONTROL, KeyCode::Up) => move_focus_in_pane(layout_m, MoveDir::Up),
+        (KeyModifiers::CONTROL, KeyCode::Down) => move_focus_in_pane(layout_m, MoveDir::Down),
+        // back to the pane the files open in
+        (KeyModifiers::NONE, KeyCode::Esc) | (KeyModifiers::ALT, KeyCode::Char('e')) => {
+            res = focus_explorer(&app.buf_manager, layout_m, explorer);
+        }
+        (_, KeyCode::Up) => explorer.move_selection(-1),
+        (_, KeyCode::Down) => explorer.move_selection(1),
+        (_, KeyCode::PageUp) => explorer.move_selection(-page),
+        (_, KeyCode::PageDown) => explorer.move_selection(page),
+        (_, KeyCode::Home) => explorer.selected = 0,
+        (_, KeyCode::End) => explorer.move_selection(isize::MAX / 2),
+        (_, KeyCode::Left) => explorer.collapse(),
+        (_, KeyCode::Right) => explorer.expand(),
+        (_, KeyCode::Enter) => {
+            res = open_from_explorer(&mut app.buf_manager, layout_m, explorer, None);
+        }
+        (_, KeyCode::Char(c @ ('v' | 's'))) => {
+            let direc = match c {
+                'v' => SplitDirection::Vertical,
+                _ => SplitDirection::Horizontal,
+            };
+            res = open_from_explorer(&mut app.buf_manager, layout_m, explorer, Some(direc));
+        }
+        (_, KeyCode::Char('a')) => {
+            let dir = explorer.base_dir();
+            app.command.clean_all();
+            app.command
+                .ask_new_file(&mut app.current_screen, &explorer.relative(&dir));
+            explorer.target = Some(dir);
+        }
+        (_, KeyCode::Char('r' | 'm' | 'd') | KeyCode::Delete) => {
+            if let Some(path) = explorer.selected_path() {
+                app.command.clean_all();
+                let screen = &mut app.current_screen;
+                match key.code {
+                    KeyCode::Char('r') => {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        app.command.ask_rename_file(screen, &name, false);
+                    }
+                    KeyCode::Char('m') => {
+                        app.command
+                            .ask_rename_file(screen, &explorer.relative(&path), true);
+                    }
+                    _ => app
+                        .command
+                        .ask_delete_file(screen, &explorer.relative(&path)),
+                }
+                explorer.target = Some(path);
+            }
+        }
+        (_, KeyCode::Char('.')) => explorer.toggle_hidden(),
+        (_, KeyCode::Char('i')) => explorer.toggle_ignored(),
+        (_, KeyCode::Char('R')) => explorer.reload(),
+        _ => {}
+    }
+    if let Err(e) = res {
+        app.command.say = e.to_string().into();
+        app.command.status = CmdStatus::Failed;
+    }
+    Ok(())
+}
+
+// the keys of the open completion menu, returns whether the key was used,
+// the others close it except for typing which filters it
+fn handle_completion_key(
+    menu: &mut Option<Completion>,
+    cmd: &mut KaoCo,
+    bm: &mut buffer::BufferManager,
+    lm: &mut layout::layout_manager::LayoutManager,
+    key: KeyEvent,
+) -> Result<bool> {
+    let Some(m) = menu else {
+        return Ok(false);
+    };
+    if lm.get_current_buffer(bm)?.id != m.buffer_id {
+        *menu = None;
+        return Ok(false);
+    }
+    let listed = !m.shown.is_empty();
+    match (key.modifiers, key.code) {
+        (_, KeyCode::Tab | KeyCode::Down) if listed => m.select_next(),
+        (_, KeyCode::BackTab | KeyCode::Up) if listed => m.select_prev(),
+        (KeyModifiers::NONE, KeyCode::Enter) if listed => {
+            let start = m.start;
+            let text = m.selected().map(|c| c.insert.clone()).unwrap_or_default();
+            *menu = None;
+            if let Err(e) = lm.complete(bm, start, &text) {
+                cmd.say = e.to_string().into();
+                cmd.status = CmdStatus::Failed;
+            }
+        }
+        (KeyModifiers::NONE, KeyCode::Esc) => *menu = None,
+        (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(_))
+        | (KeyModifiers::NONE, KeyCode::Backspace) => return Ok(false),
+        _ => {
+            *menu = None;
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn handle_key(app: &mut App, key: KeyEvent) -> Result<()> {
+    let cur_cmd = &mut app.command;
+    let cur_screen = &mut app.current_screen;
+    let layout_m = &mut app.layout_manager;
+    let buffer_m = &mut app.buf_manager;
+    if key.kind != KeyEventKind::Press {
+        return Ok(());
+    }
+    match *cur_screen {
+        Screen::Welcome => match key.code {
+            KeyCode::Char('q') => {
+                app.should_exit = true;
+            }
+            KeyCode::Char('a') => {
+                // todo: handle error
+                app.init(None, false).unwrap();
+            }
+            _ => {}
+        },
+        Screen::Editor => {
+            match layout_m.current_content() {
+                #[cfg(unix)]
+                Some(PaneContent::Terminal(id)) => return handle_terminal_key(app, id, key),
+                Some(PaneContent::Explorer) => return handle_explorer_key(app, key),
+                None => {}
+            }
+            match app.current_mod {
+                Mod::Visual(vx, vy) => match (key.modifiers, key.code) {
+                    (KeyModifiers::NONE, KeyCode::Esc) => {
+                        app.current_mod = Mod::Input;
+                    }
+                    // move cursor to the head of line
+                    (KeyModifiers::CONTROL, KeyCode::Char('a')) => {
+                        mv_cursor_head(buffer_m, layout_m)?;
                     }
-                    KeyCode::Char('a') => {
-                        // todo: handle error
-                        app.init(None).unwrap();
+                    // move cursor to the end of line
+                    (KeyModifiers::CONTROL, KeyCode::Char('e')) => {
+                        mv_cursor_tail(buffer_m, layout_m)?;
                     }
+                    // move cursor to the matching bracket
+                    (KeyModifiers::CONTROL, KeyCode::Char('b')) => {
+                        mv_cursor_match(buffer_m, layout_m)?;
+                    }
+                    // the line commands on the selected lines
+                    (KeyModifiers::CONTROL, KeyCode::Char('x')) => {
+                        app.current_screen = Screen::Command;
+                        cur_cmd.clean_all();
+                        cur_cmd.selection = Some((vx, vy));
+                        app.current_mod = Mod::Input;
+                    }
+                    // move the selected lines, the selection goes with them
+                    (KeyModifiers::ALT, KeyCode::Up | KeyCode::Down) => {
+                        let before = cursor_pos(layout_m)?.1;
+                        let inst = if key.code == KeyCode::Up {
+                            Instruction::MoveLinesUp(Some((vx, vy)))
+                        } else {
+                            Instruction::MoveLinesDown(Some((vx, vy)))
+                        };
+                        cur_cmd.handle_instructions(buffer_m, layout_m, inst)?;
+                        let after = cursor_pos(layout_m)?.1;
+                        app.current_mod = Mod::Visual(vx, vy + after - before);
+                    }
+                    (KeyModifiers::ALT, KeyCode::Char('c')) => {
+                        cur_cmd.handle_instructions(
+                            buffer_m,
+                            layout_m,
+                            Instruction::DuplicateLines(Some((vx, vy))),
+                        )?;
+                        app.current_mod = Mod::Input;
+                    }
+                    (KeyModifiers::ALT, KeyCode::Char('j')) => {
+                        cur_cmd.handle_instructions(
+                            buffer_m,
+                            layout_m,
+                            Instruction::JoinLines(Some((vx, vy))),
+                        )?;
+                        app.current_mod = Mod::Input;
+                    }
+                    (KeyModifiers::ALT, KeyCode::Char('/')) => {
+                        cur_cmd.handle_instructions(
+                            buffer_m,
+                            layout_m,
+                            Instruction::ToggleComment(Some((vx, vy))),
+                        )?;
+                        app.current_mod = Mod::Input;
+                    }
+                    (_, KeyCode::Char('d')) => {
+                        cur_cmd.handle_instructions(
+                            buffer_m,
+                            layout_m,
+                            Instruction::DeleteBlock((vx, vy)),
+                        )?;
+                        app.current_mod = Mod::Input;
+                    }
+                    // indent or outdent the selected lines
+                    (_, KeyCode::Char('>') | KeyCode::Tab) => {
+                        cur_cmd.handle_instructions(
+                            buffer_m,
+                            layout_m,
+                            Instruction::Indent(Some((vx, vy))),
+                        )?;
+                        app.current_mod = Mod::Input;
+                    }
+                    (_, KeyCode::Char('<') | KeyCode::BackTab) => {
+                        cur_cmd.handle_instructions(
+                            buffer_m,
+                            layout_m,
+                            Instruction::Outdent(Some((vx, vy))),
+                        )?;
+                        app.current_mod = Mod::Input;
+                    }
+                    (_, KeyCode::Left) => mv_cursor_left(buffer_m, layout_m)?,
+                    (_, KeyCode::Right) => mv_cursor_right(buffer_m, layout_m, 1)?,
+                    (_, KeyCode::Up) => mv_cursor_up(buffer_m, layout_m)?,
+                    (_, KeyCode::Down) => mv_cursor_down(buffer_m, layout_m)?,
                     _ => {}
                 },
-                Screen::Editor => {
-                    match app.current_mod {
-                        Mod::Visual(vx, vy) => match (key.modifiers, key.code) {
-                            (KeyModifiers::NONE, KeyCode::Esc) => {
-                                app.current_mod = Mod::Input;
-                            }
-                            // move cursor to the head of line
-                            (KeyModifiers::CONTROL, KeyCode::Char('a')) => {
-                                mv_cursor_head(layout_m)?;
-                            }
-                            // move cursor to the end of line
-                            (KeyModifiers::CONTROL, KeyCode::Char('e')) => {
-                                mv_cursor_tail(buffer_m, layout_m)?;
-                            }
-                            (_, KeyCode::Char('d')) => {
-                                cur_cmd.handle_instructions(
-                                    buffer_m,
-                                    layout_m,
-                                    Instruction::DeleteBlock((vx, vy)),
-                                )?;
-                                app.current_mod = Mod::Input;
-                            }
-                            (_, KeyCode::Left) => mv_cursor_left(buffer_m, layout_m)?,
-                            (_, KeyCode::Right) => mv_cursor_right(buffer_m, layout_m, 1)?,
-                            (_, KeyCode::Up) => mv_cursor_up(buffer_m, layout_m)?,
-                            (_, KeyCode::Down) => mv_cursor_down(buffer_m, layout_m)?,
-                            _ => {}
-                        },
-                        _ => {
-                            match (key.modifiers, key.code) {
-                                // exit
-                                (KeyModifiers::CONTROL, KeyCode::Char('q')) => {
-                                    close_current_pane(
-                                        cur_cmd,
-                                        buffer_m,
-                                        layout_m,
-                                        &mut app.should_exit,
-                                        &mut app.current_screen,
-                                    )?;
-                                }
-                                // save file
-                                (KeyModifiers::CONTROL, KeyCode::Char('s')) => {
-                                    // todo: error handle
-                                    if is_buffer_binding(buffer_m, layout_m).unwrap() {
-                                        let _ = save(buffer_m, layout_m);
-                                    } else {
-                                        app.current_screen = Screen::Command;
-                                        let _ = cur_cmd.ask_and_save();
-                                    }
-                                }
-                                // revoke
-                                (KeyModifiers::CONTROL, KeyCode::Char('z')) => {
-                                    revoke(buffer_m, layout_m)?;
-                                }
-                                // move cursor to the head of line
-                                (KeyModifiers::CONTROL, KeyCode::Char('a')) => {
-                                    mv_cursor_head(layout_m)?;
-                                }
-                                // move cursor to the end of line
-                                (KeyModifiers::CONTROL, KeyCode::Char('e')) => {
-                                    mv_cursor_tail(buffer_m, layout_m)?;
-                                }
-                                // move cursor to the head of the next word
-                                (KeyModifiers::CONTROL, KeyCode::Char('w')) => {
-                                    mv_cursor_next_word_head(buffer_m, layout_m)?;
-                                }
-                                // move cursor to the head of the prev word
-                                (KeyModifiers::CONTROL, KeyCode::Char('k')) => {
-                                    mv_cursor_prev_word_head(buffer_m, layout_m)?;
-                                }
-                                // active the command line
-                                (KeyModifiers::CONTROL, KeyCode::Char('x')) => {
-                                    app.current_screen = Screen::Command;
-                                    cur_cmd.clean_all();
-                                }
-                                // delete current line
-                                (KeyModifiers::CONTROL, KeyCode::Char('d')) => {
-                                    cur_cmd.handle_instructions(
-                                        buffer_m,
-                                        layout_m,
-                                        Instruction::DeleteLine,
-                                    )?;
-                                }
-                                // move to the left pane
-                                (KeyModifiers::CONTROL, KeyCode::Left) => {
-                                    move_focus_in_pane(layout_m, MoveDir::Left);
-                                }
-                                // move to the right pane
-                                (KeyModifiers::CONTROL, KeyCode::Right) => {
-                                    move_focus_in_pane(layout_m, MoveDir::Right);
-                                }
-                                // move to the up pane
-                                (KeyModifiers::CONTROL, KeyCode::Up) => {
-                                    move_focus_in_pane(layout_m, MoveDir::Up);
-                                }
-                                // move to the down pane
-                                (KeyModifiers::CONTROL, KeyCode::Down) => {
-                                    move_focus_in_pane(layout_m, MoveDir::Down);
-                                }
-                                // change to visual mode
-                                (KeyModifiers::CONTROL, KeyCode::Char('v')) => {
-                                    enter_visual(layout_m, &mut app.current_mod)?;
-                                }
-                                // enter Tab
-                                (_, KeyCode::Tab) => {
-                                    if let Ok(_) = cur_cmd.handle_instructions(
-                                        buffer_m,
-                                        layout_m,
-                                        Instruction::InsertText("    ".to_string().into()),
-                                    ) {
-                                        mv_cursor_right(buffer_m, layout_m, 4)?;
-                                    }
-                                }
-                                // enter Home
-                                (_, KeyCode::Home) => {
-                                    mv_cursor_head(layout_m)?;
-                                }
-                                // enter End
-                                (_, KeyCode::End) => {
-                                    mv_cursor_tail(buffer_m, layout_m)?;
-                                }
-
-                                (_, KeyCode::Left) => mv_cursor_left(buffer_m, layout_m)?,
-                                (_, KeyCode::Right) => mv_cursor_right(buffer_m, layout_m, 1)?,
-                                (_, KeyCode::Up) => mv_cursor_up(buffer_m, layout_m)?,
-                                (_, KeyCode::Down) => mv_cursor_down(buffer_m, layout_m)?,
-                                (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(ch)) => {
-                                    if let Ok(_) = cur_cmd.handle_instructions(
-                                        buffer_m,
-                                        layout_m,
-                                        Instruction::InsertText(ch.to_string().into()),
-                                    ) {
-                                        mv_cursor_right(buffer_m, layout_m, 1)?;
+                _ => {
+                    if handle_completion_key(&mut app.completion, cur_cmd, buffer_m, layout_m, key)?
+                    {
+                        return Ok(());
+                    }
+                    match (key.modifiers, key.code) {
+                        // exit
+                        (KeyModifiers::CONTROL, KeyCode::Char('q')) => {
+                            close_current_pane(
+                                cur_cmd,
+                                buffer_m,
+                                layout_m,
+                                &mut app.should_exit,
+                                &mut app.current_screen,
+                            )?;
+                        }
+                        // save file
+                        (KeyModifiers::CONTROL, KeyCode::Char('s')) => {
+                            // todo: error handle
+                            if is_disk_newer(buffer_m, layout_m)? {
+                                let id = layout_m.get_current_buffer(buffer_m)?.id;
+                                cur_cmd.ask_overwrite(&mut app.current_screen, id);
+                            } else if is_buffer_binding(buffer_m, layout_m).unwrap() {
+                                if let Err(e) = save(buffer_m, layout_m) {
+                                    cur_cmd.say = match &e {
+                                        LayoutError::IOError(io_err) => save_error_text(io_err),
+                                        e => format!("Failed to save: {}", e),
                                     }
+                                    .into();
+                                    cur_cmd.status = CmdStatus::Failed;
+                                } else {
+                                    let id = layout_m.get_current_buffer(buffer_m)?.id;
+                                    cur_cmd.report_format(buffer_m, layout_m, id)?;
                                 }
-                                (KeyModifiers::NONE, KeyCode::Enter) => cur_cmd
-                                    .handle_instructions(
-                                        buffer_m,
-                                        layout_m,
-                                        Instruction::InsertLine,
-                                    )?,
-                                (KeyModifiers::NONE, KeyCode::Backspace) => {
-                                    cur_cmd.handle_instructions(
-                                        buffer_m,
-                                        layout_m,
-                                        Instruction::DeleteText(1),
-                                    )?;
-                                }
-                                _ => {}
+                            } else {
+                                app.current_screen = Screen::Command;
+                                cur_cmd.ask_and_save();
                             }
                         }
-                    }
-                }
-                Screen::Command => {
-                    match (key.modifiers, key.code) {
-                        // exit
-                        (KeyModifiers::CONTROL, KeyCode::Char('q'))
-                        | (KeyModifiers::NONE, KeyCode::Esc) => {
+                        // revoke
+                        (KeyModifiers::CONTROL, KeyCode::Char('z')) => {
+                            if let Err(e) = revoke(buffer_m, layout_m) {
+                                cur_cmd.say = e.to_string().into();
+                                cur_cmd.status = CmdStatus::Failed;
+                            }
+                        }
+                        // move cursor to the head of line
+                        (KeyModifiers::CONTROL, KeyCode::Char('a')) => {
+                            mv_cursor_head(buffer_m, layout_m)?;
+                        }
+                        // move cursor to the end of line
+                        (KeyModifiers::CONTROL, KeyCode::Char('e')) => {
+                            mv_cursor_tail(buffer_m, layout_m)?;
+                        }
+                        // move cursor to the matching bracket
+                        (KeyModifiers::CONTROL, KeyCode::Char('b')) => {
+                            mv_cursor_match(buffer_m, layout_m)?;
+                        }
+                        // move cursor to the head of the next word
+                        (KeyModifiers::CONTROL, KeyCode::Char('w')) => {
+                            mv_cursor_next_word_head(buffer_m, layout_m)?;
+                        }
+                        // move cursor to the head of the prev word
+                        (KeyModifiers::CONTROL, KeyCode::Char('k')) => {
+                            mv_cursor_prev_word_head(buffer_m, layout_m)?;
+                        }
+                        // active the command line
+                        (KeyModifiers::CONTROL, KeyCode::Char('x')) => {
+                            app.current_screen = Screen::Command;
                             cur_cmd.clean_all();
-                            app.current_screen = Screen::Editor
                         }
-                        (KeyModifiers::NONE, KeyCode::Char(ch)) => {
-                            if !matches!(cur_cmd.status, CmdStatus::Exec(_)) {
-                                cur_cmd.status = CmdStatus::Normal;
+                        // delete current line
+                        (KeyModifiers::CONTROL, KeyCode::Char('d')) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::DeleteLine,
+                            )?;
+                        }
+                        // move to the left pane
+                        (KeyModifiers::CONTROL, KeyCode::Left) => {
+                            move_focus_in_pane(layout_m, MoveDir::Left);
+                        }
+                        // move to the right pane
+                        (KeyModifiers::CONTROL, KeyCode::Right) => {
+                            move_focus_in_pane(layout_m, MoveDir::Right);
+                        }
+                        // move to the up pane
+                        (KeyModifiers::CONTROL, KeyCode::Up) => {
+                            move_focus_in_pane(layout_m, MoveDir::Up);
+                        }
+                        // move to the down pane
+                        (KeyModifiers::CONTROL, KeyCode::Down) => {
+                            move_focus_in_pane(layout_m, MoveDir::Down);
+                        }
+                        // change to visual mode
+                        (KeyModifiers::CONTROL, KeyCode::Char('v')) => {
+                            enter_visual(layout_m, &mut app.current_mod)?;
+                        }
+                        // open the completion menu
+                        (KeyModifiers::CONTROL, KeyCode::Char('n' | ' ')) => {
+                            let id = layout_m.get_current_buffer(buffer_m)?.id;
+                            app.completion = Completion::new(buffer_m, id, cursor_pos(layout_m)?);
+                            // the words are there at once, the server answers later
+                            let asked = app.lsp.completion(buffer_m, layout_m).unwrap_or(false);
+                            if !asked && app.completion.as_ref().is_none_or(|c| c.shown.is_empty())
+                            {
+                                app.completion = None;
+                                cur_cmd.say = "No completions".into();
                             }
-
-                            if let Ok(_) = cur_cmd.add_content_at(ch.to_string().as_str()) {
-                                cur_cmd.mv_cursor_right();
+                        }
+                        // enter Tab
+                        (_, KeyCode::Tab) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::InsertTab,
+                            )?;
+                        }
+                        // move the current line up or down
+                        (KeyModifiers::ALT, KeyCode::Up) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::MoveLinesUp(None),
+                            )?;
+                        }
+                        (KeyModifiers::ALT, KeyCode::Down) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::MoveLinesDown(None),
+                            )?;
+                        }
+                        // duplicate the current line
+                        (KeyModifiers::ALT, KeyCode::Char('c')) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::DuplicateLines(None),
+                            )?;
+                        }
+                        // join the current line with the next one
+                        (KeyModifiers::ALT, KeyCode::Char('j')) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::JoinLines(None),
+                            )?;
+                        }
+                        // comment or uncomment the current line
+                        (KeyModifiers::ALT, KeyCode::Char('/')) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::ToggleComment(None),
+                            )?;
+                        }
+                        // ask the language server about the symbol under the cursor
+                        (KeyModifiers::ALT, KeyCode::Char('h')) => {
+                            cur_cmd.report_lsp(app.lsp.hover(buffer_m, layout_m));
+                        }
+                        (KeyModifiers::ALT, KeyCode::Char('g')) => {
+                            cur_cmd.report_lsp(app.lsp.definition(buffer_m, layout_m));
+                        }
+                        (KeyModifiers::ALT, KeyCode::Char('r')) => {
+                            cur_cmd.report_lsp(app.lsp.references(buffer_m, layout_m));
+                        }
+                        // open the file explorer or go to it
+                        (KeyModifiers::ALT, KeyCode::Char('e')) => {
+                            focus_explorer(buffer_m, layout_m, &mut app.explorer)?;
+                        }
+                        // go to the next or the previous diagnostic of the buffer
+                        (KeyModifiers::ALT, KeyCode::Char(c @ ('n' | 'p'))) => {
+                            mv_cursor_diagnostic(cur_cmd, buffer_m, layout_m, c == 'n')?;
+                        }
+                        // go to the next or the previous change of the diff the
+                        // pane is in, or since the git HEAD
+                        (KeyModifiers::ALT, KeyCode::Char(c @ (']' | '['))) => match &app.diff {
+                            Some(view) if view.side(layout_m.current_layout).is_some() => {
+                                mv_cursor_diff_hunk(cur_cmd, view, buffer_m, layout_m, c == ']')?;
                             }
+                            _ => mv_cursor_hunk(cur_cmd, buffer_m, layout_m, c == ']')?,
+                        },
+                        // indent the current line
+                        (KeyModifiers::CONTROL, KeyCode::Char('t')) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::Indent(None),
+                            )?;
+                        }
+                        // outdent the current line
+                        (_, KeyCode::BackTab) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::Outdent(None),
+                            )?;
+                        }
+                        // enter Home
+                        (_, KeyCode::Home) => {
+                            mv_cursor_head(buffer_m, layout_m)?;
+                        }
+                        // enter End
+                        (_, KeyCode::End) => {
+                            mv_cursor_tail(buffer_m, layout_m)?;
+                        }
+
+                        (_, KeyCode::Left) => mv_cursor_left(buffer_m, layout_m)?,
+                        (_, KeyCode::Right) => mv_cursor_right(buffer_m, layout_m, 1)?,
+                        (_, KeyCode::Up) => mv_cursor_up(buffer_m, layout_m)?,
+                        (_, KeyCode::Down) => mv_cursor_down(buffer_m, layout_m)?,
+                        (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(ch)) => {
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::InsertChar(ch),
+                            )?;
+                        }
+                        // open the location of the line in the quickfix list
+                        (KeyModifiers::NONE, KeyCode::Enter)
+                            if app
+                                .quickfix
+                                .is_list(layout_m.get_current_buffer(buffer_m)?.id) =>
+                        {
+                            let y = cursor_pos(layout_m)?.1;
+                            cur_cmd.report_jump(app.quickfix.jump(buffer_m, layout_m, y));
+                        }
+                        // open the location the line of the output belongs to
+                        (KeyModifiers::NONE, KeyCode::Enter)
+                            if app
+                                .make
+                                .is_output(layout_m.get_current_buffer(buffer_m)?.id) =>
+                        {
+                            let y = cursor_pos(layout_m)?.1;
+                            cur_cmd.report_jump(app.make.jump_from_output(
+                                buffer_m,
+                                layout_m,
+                                &mut app.quickfix,
+                                y,
+                            ));
                         }
-                        (_, KeyCode::Left) => cur_cmd.mv_cursor_left(),
-                        (_, KeyCode::Right) => cur_cmd.mv_cursor_right(),
                         (KeyModifiers::NONE, KeyCode::Enter) => {
-                            let ret = cur_cmd
-                                .handle_command(
-                                    buffer_m,
-                                    layout_m,
-                                    cur_screen,
-                                    &mut app.popups,
-                                    &mut app.should_exit,
-                                )
-                                .unwrap();
-                            if ret {
-                                app.current_screen = Screen::Editor
-                            }
-                            cur_cmd.clean();
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::InsertLine,
+                            )?;
                         }
                         (KeyModifiers::NONE, KeyCode::Backspace) => {
-                            cur_cmd.handle_backspace();
+                            cur_cmd.handle_instructions(
+                                buffer_m,
+                                layout_m,
+                                Instruction::DeleteText(1),
+                            )?;
                         }
-
                         _ => {}
                     }
+                    // the menu follows the text typed
+                    if let Some(menu) = &mut app.completion
+                        && !menu.update(buffer_m, cursor_pos(layout_m)?)
+                    {
+                        app.completion = None;
+                    }
                 }
             }
         }
+        Screen::Command => {
+            match (key.modifiers, key.code) {
+                // exit
+                (KeyModifiers::CONTROL, KeyCode::Char('q'))
+                | (KeyModifiers::NONE, KeyCode::Esc) => {
+                    cur_cmd.clean_all();
+                    app.current_screen = Screen::Editor
+                }
+                (KeyModifiers::NONE, KeyCode::Char(ch)) => {
+                    if !matches!(cur_cmd.status, CmdStatus::Exec(_)) {
+                        cur_cmd.status = CmdStatus::Normal;
+                    }
+
+                    if cur_cmd.add_content_at(ch.to_string().as_str()).is_ok() {
+                        cur_cmd.mv_cursor_right();
+                    }
+                }
+                (_, KeyCode::Left) => cur_cmd.mv_cursor_left(),
+                (_, KeyCode::Right) => cur_cmd.mv_cursor_right(),
+                (KeyModifiers::NONE, KeyCode::Enter) => {
+                    #[cfg(unix)]
+                    let ret = cur_cmd.terminal_command(buffer_m, layout_m, &mut app.terminals);
+                    #[cfg(not(unix))]
+                    let ret = None;
+                    let ret = match ret {
+                        Some(ret) => ret,
+                        None => cur_cmd.handle_command(
+                            buffer_m,
+                            layout_m,
+                            cur_screen,
+                            &mut app.popups,
+                            &mut app.lsp,
+                            &mut app.quickfix,
+                            &mut app.make,
+                            &mut app.events,
+                            &mut app.diff,
+                            &mut app.explorer,
+                            &mut app.should_exit,
+                        ),
+                    }
+                    .unwrap();
+                    if ret {
+                        app.current_screen = Screen::Editor
+                    }
+                    cur_cmd.clean();
+                }
+                (KeyModifiers::NONE, KeyCode::Backspace) => {
+                    cur_cmd.handle_backspace();
+                }
+
+                _ => {}
+            }
+        }
     }
+    Ok(())
 }
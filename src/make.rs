@@ -0,0 +1,397 @@
+#![allow(dead_code)]
+// run a build or any command in the background, its output streams into a
+// scratch buffer and the locations found in it become the quickfix list and
+// the diagnostics of the files
+use crate::buffer::BufferManager;
+use crate::diagnostic::{Diagnostic, count};
+use crate::error::LayoutError;
+use crate::errorformat::{Location, Parser, Pattern, formats_for, parse_formats};
+use crate::event_loop::AppEvent;
+use crate::layout::layout_manager::LayoutManager;
+use crate::layout::tree::{LayoutNode, SplitDirection};
+use crate::quickfix::{Quickfix, QuickfixEntry};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// the name of the buffer of the output
+pub const OUTPUT_NAME: &str = "[make]";
+// the diagnostics of a build are replaced by the next one
+pub const PRODUCER: &str = "make";
+// the lines read in this time are sent together, so a long output doesn't
+// draw the screen for every line
+const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+struct Job {
+    id: usize,
+    // the shell, which leads the process group of the command
+    pid: u32,
+    command: String,
+    started: Instant,
+}
+
+pub struct Make {
+    job: Option<Job>,
+    job_counter: usize,
+    // the command run last, `make` runs it again
+    pub last_command: Option<String>,
+    // set with `errorformat`, the format follows the command when it's none
+    pub formats: Option<(String, Vec<Pattern>)>,
+    // the patterns added with `errorformat add`, tried first
+    pub custom: Vec<Pattern>,
+    parser: Parser,
+    // the locations found, with the line of the output they end on
+    pub locations: Vec<(usize, Location)>,
+    pub buffer_id: Option<usize>,
+    // the pane the build was started from, the files open there
+    pub origin: usize,
+    tx: Sender<AppEvent>,
+}
+
+impl Make {
+    pub fn new(tx: Sender<AppEvent>) -> Self {
+        Self {
+            job: None,
+            job_counter: 1,
+            last_command: None,
+            formats: None,
+            custom: vec![],
+            parser: Parser::new(vec![]),
+            locations: vec![],
+            buffer_id: None,
+            origin: 0,
+            tx,
+        }
+    }
+
+    // the command `make` runs: the last one, or the build of the project
+    pub fn default_command(&self) -> String {
+        match &self.last_command {
+            Some(command) => command.clone(),
+            None if Path::new("Cargo.toml").exists() => "cargo build".to_string(),
+            None => "make".to_string(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.job.is_some()
+    }
+
+    pub fn is_output(&self, id: usize) -> bool {
+        self.buffer_id == Some(id)
+    }
+
+    // the format in use, by name
+    pub fn format_text(&self) -> String {
+        let name = match &self.formats {
+            Some((spec, _)) => spec.clone(),
+            None => "auto".to_string(),
+        };
+        let custom: Vec<&str> = self.custom.iter().map(|p| p.source.as_str()).collect();
+        match custom.is_empty() {
+            true => format!("errorformat={}", name),
+            false => format!("errorformat={} and {}", name, custom.join(" ")),
+        }
+    }
+
+    // `auto`, the names of builtin formats, or `add <pattern>`
+    pub fn set_format(&mut self, spec: &str) -> Result<(), String> {
+        match spec.trim() {
+            "auto" => {
+                self.formats = None;
+                self.custom.clear();
+            }
+            s if s.starts_with("add ") => self.custom.push(Pattern::parse(s[4..].trim())?),
+            s => self.formats = Some((s.to_string(), parse_formats(s)?)),
+        }
+        Ok(())
+    }
+
+    // start `command` in a shell, the output goes into the output buffer,
+    // shown under the current pane, a build which is still running is stopped
+    pub fn run(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        command: &str,
+    ) -> Result<(), LayoutError> {
+        self.stop();
+        // stderr comes in the same pipe so the lines keep their order
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("exec 2>&1\n{}", command))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        // in a group of its own, so stopping it stops what it started too
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        let mut child = cmd.spawn()?;
+        let pid = child.id();
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| std::io::Error::other("no pipe to the command"))?;
+
+        let id = self.job_counter;
+        self.job_counter += 1;
+        let (line_tx, line_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            let mut bytes = vec![];
+            while reader.read_until(b'\n', &mut bytes).is_ok_and(|n| n > 0) {
+                let line = String::from_utf8_lossy(&bytes);
+                let line = line.trim_end_matches(['\n', '\r']).to_string();
+                if line_tx.send(line).is_err() {
+                    return;
+                }
+                bytes.clear();
+            }
+        });
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let mut lines = vec![];
+            loop {
+                match line_rx.recv_timeout(BATCH_INTERVAL) {
+                    Ok(line) => {
+                        lines.push(line);
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if !lines.is_empty() {
+                    let lines = std::mem::take(&mut lines);
+                    if tx.send(AppEvent::JobLines { id, lines }).is_err() {
+                        return;
+                    }
+                }
+            }
+            if !lines.is_empty() {
+                let _ = tx.send(AppEvent::JobLines { id, lines });
+            }
+            // reaped here, the pipe is closed once it exits or is killed
+            let code = child.wait().ok().and_then(|status| status.code());
+            let _ = tx.send(AppEvent::JobExited { id, code });
+        });
+
+        let mut patterns = self.custom.clone();
+        match &self.formats {
+            Some((_, formats)) => patterns.extend(formats.iter().cloned()),
+            None => patterns.extend(formats_for(command)),
+        }
+        self.parser = Parser::new(patterns);
+        self.locations.clear();
+        self.last_command = Some(command.to_string());
+        self.job = Some(Job {
+            id,
+            pid,
+            command: command.to_string(),
+            started: Instant::now(),
+        });
+        self.show_output(bm, lm, vec![format!("$ {}", command)])
+    }
+
+    // write `lines` into the output buffer, which is shown under the current
+    // pane if no pane shows it, the focus stays where it is
+    fn show_output(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        lines: Vec<String>,
+    ) -> Result<(), LayoutError> {
+        let id = match self.buffer_id.filter(|id| bm.get_buffer(*id).is_ok()) {
+            Some(id) => id,
+            None => bm.add_new_buffer(OUTPUT_NAME),
+        };
+        bm.get_buffer_mut(id)?.set_scratch_content(lines);
+        self.buffer_id = Some(id);
+        match lm.pane_showing(id) {
+            Some(pane) => {
+                if let Some(LayoutNode::Pane { cursor, .. }) =
+                    lm.panes.as_mut().and_then(|root| root.get_pane_mut(pane))
+                {
+                    cursor.pos = (0, 0);
+                }
+                if lm.current_layout != pane {
+                    self.origin = lm.current_layout;
+                }
+            }
+            None => {
+                self.origin = lm.current_layout;
+                lm.split(self.origin, Some(id), SplitDirection::Horizontal, bm)?;
+                lm.jump_to(bm, id, (0, 0))?;
+                lm.focus(self.origin);
+            }
+        }
+        Ok(())
+    }
+
+    // the lines job `id` printed, the panes at the end of the output follow it
+    pub fn output(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        id: usize,
+        lines: Vec<String>,
+    ) -> bool {
+        if self.job.as_ref().is_none_or(|job| job.id != id) {
+            return false;
+        }
+        let Some(buf) = self.buffer_id.and_then(|id| bm.get_buffer_mut(id).ok()) else {
+            return false;
+        };
+        let before = buf.content.len();
+        for line in &lines {
+            if let Some(location) = self.parser.feed(line) {
+                self.locations.push((buf.content.len(), location));
+            }
+            buf.content.push(line.clone());
+        }
+        let after = buf.content.len();
+        if let Some(pane) = self.buffer_id.and_then(|id| lm.pane_showing(id))
+            && let Some(LayoutNode::Pane { cursor, .. }) =
+                lm.panes.as_mut().and_then(|root| root.get_pane_mut(pane))
+            && cursor.pos.1 + 1 >= before
+        {
+            cursor.pos = (0, after - 1);
+        }
+        true
+    }
+
+    // job `id` exited, its locations go into the quickfix list and the
+    // diagnostics of the open files, returns what to tell about it
+    pub fn finish(
+        &mut self,
+        bm: &mut BufferManager,
+        qf: &mut Quickfix,
+        id: usize,
+        code: Option<i32>,
+    ) -> Option<String> {
+        if self.job.as_ref().is_none_or(|job| job.id != id) {
+            return None;
+        }
+        let job = self.job.take()?;
+        let elapsed = job.started.elapsed().as_secs_f32();
+        let code = match code {
+            Some(code) => format!("exit {}", code),
+            None => "killed".to_string(),
+        };
+        if let Some(buf) = self.buffer_id.and_then(|id| bm.get_buffer_mut(id).ok()) {
+            buf.content
+                .push(format!("[{} in {:.1}s: {}]", code, elapsed, job.command));
+        }
+        self.publish(bm, qf);
+
+        let diagnostics: Vec<Diagnostic> =
+            self.locations.iter().map(|(_, l)| diagnostic(l)).collect();
+        let (errors, warnings) = count(&diagnostics);
+        Some(format!(
+            "{}: {} errors, {} warnings, {}",
+            job.command, errors, warnings, code
+        ))
+    }
+
+    // stop the running command and the processes it started, the thread
+    // reading its output reaps it
+    pub fn stop(&mut self) -> bool {
+        let Some(job) = self.job.take() else {
+            return false;
+        };
+        kill_group(job.pid);
+        true
+    }
+
+    // the locations into the quickfix list and the diagnostics of the buffers
+    // of their files, the others get theirs when they're opened from the list
+    pub fn publish(&self, bm: &mut BufferManager, qf: &mut Quickfix) {
+        qf.entries = self
+            .locations
+            .iter()
+            .map(|(_, l)| QuickfixEntry {
+                path: Some(PathBuf::from(&l.path)),
+                buffer_id: None,
+                pos: l.pos,
+                severity: l.severity,
+                message: l.message.clone(),
+            })
+            .collect();
+        qf.current = None;
+        qf.producer = Some(PRODUCER);
+        qf.refresh(bm, false);
+
+        let canonical: Vec<Option<PathBuf>> = self
+            .locations
+            .iter()
+            .map(|(_, l)| Path::new(&l.path).canonicalize().ok())
+            .collect();
+        for buf in bm.buffers.values_mut() {
+            let Some(path) = buf.path.as_ref().and_then(|p| p.canonicalize().ok()) else {
+                continue;
+            };
+            let diagnostics = self
+                .locations
+                .iter()
+                .zip(&canonical)
+                .filter(|(_, c)| c.as_ref() == Some(&path))
+                .map(|((_, l), _)| diagnostic(l))
+                .collect();
+            buf.set_diagnostics(PRODUCER, diagnostics);
+        }
+    }
+
+    // open the location line `y` of the output belongs to, in the pane the
+    // build was started from
+    pub fn jump_from_output(
+        &self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        qf: &mut Quickfix,
+        y: usize,
+    ) -> Result<String, LayoutError> {
+        let n = self
+            .locations
+            .iter()
+            .rposition(|(line, _)| *line <= y)
+            .ok_or(LayoutError::NoEntry)?;
+        self.publish(bm, qf);
+        if !lm.focus(self.origin) {
+            let id = self.buffer_id.ok_or(LayoutError::NoEntry)?;
+            lm.split(lm.current_layout, Some(id), SplitDirection::Horizontal, bm)?;
+        }
+        qf.jump(bm, lm, n)
+    }
+}
+
+#[cfg(unix)]
+fn kill_group(pid: u32) {
+    // SAFETY: only sends a signal, the group is the one of the shell
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGTERM);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(pid: u32) {
+    // the tree of the process
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
+
+pub fn diagnostic(location: &Location) -> Diagnostic {
+    Diagnostic {
+        start: location.pos,
+        end: (location.pos.0 + 1, location.pos.1),
+        severity: location.severity,
+        message: location.message.clone(),
+        source: PRODUCER.to_string(),
+        producer: PRODUCER.to_string(),
+    }
+}
@@ -0,0 +1,179 @@
+#![allow(dead_code)]
+// options of buffers, changed with the `set` command
+use crate::error::OptionError;
+use crate::language::LANGUAGES;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BackupMode {
+    Off,
+    // keep `file~`
+    Simple,
+    // keep `file.<time>~`
+    Timestamp,
+}
+
+#[derive(Debug, Clone)]
+pub struct BufferOptions {
+    pub backup: BackupMode,
+    pub backup_dir: Option<PathBuf>,
+    // command used by `sudo save`, the path is appended to it
+    pub write_helper: String,
+    // files of this size or larger are opened in the large file mode
+    pub large_file: Option<u64>,
+    // columns between the tab stops
+    pub tab_width: usize,
+    // Tab inserts spaces instead of a tab character
+    pub expand_tab: bool,
+    // typing a bracket or a quote inserts its closer too
+    pub auto_pairs: bool,
+    // the buffer is sent to the language server of its language
+    pub lsp: bool,
+    // the formatter of the language runs before the buffer is written
+    pub format_on_save: bool,
+    // the formatters set by language, an empty one turns it off
+    pub formatters: HashMap<String, String>,
+}
+
+impl BufferOptions {
+    pub fn new() -> Self {
+        Self {
+            backup: BackupMode::Off,
+            backup_dir: None,
+            write_helper: "sudo -n tee".to_string(),
+            large_file: Some(32 << 20),
+            tab_width: 4,
+            expand_tab: true,
+            auto_pairs: true,
+            // the servers are only started when asked for
+            lsp: false,
+            format_on_save: false,
+            formatters: HashMap::new(),
+        }
+    }
+
+    // `name=value`, `name` or `noname`
+    pub fn set(&mut self, expr: &str) -> Result<(), OptionError> {
+        let (name, value) = match expr.split_once('=') {
+            Some((n, v)) => (n.trim(), Some(v.trim())),
+            None => (expr.trim(), None),
+        };
+
+        match (name, value) {
+            ("backup", None) => self.backup = BackupMode::Simple,
+            ("nobackup", None) => self.backup = BackupMode::Off,
+            ("backup", Some(v)) => {
+                self.backup = match v {
+                    "off" => BackupMode::Off,
+                    "simple" => BackupMode::Simple,
+                    "timestamp" => BackupMode::Timestamp,
+                    _ => return Err(OptionError::InvalidValue(v.to_string())),
+                }
+            }
+            ("backupdir", Some("")) => self.backup_dir = None,
+            ("backupdir", Some(v)) => self.backup_dir = Some(PathBuf::from(v)),
+            ("writehelper", Some(v)) if !v.is_empty() => self.write_helper = v.to_string(),
+            ("nolargefile", None) => self.large_file = None,
+            ("expandtab" | "et", None) => self.expand_tab = true,
+            ("noexpandtab" | "noet", None) => self.expand_tab = false,
+            ("autopairs" | "ap", None) => self.auto_pairs = true,
+            ("noautopairs" | "noap", None) => self.auto_pairs = false,
+            ("lsp", None) => self.lsp = true,
+            ("nolsp", None) => self.lsp = false,
+            ("formatonsave" | "fos", None) => self.format_on_save = true,
+            ("noformatonsave" | "nofos", None) => self.format_on_save = false,
+            (n, Some(v)) if n.starts_with("formatter.") => {
+                let lang = &n["formatter.".len()..];
+                if !LANGUAGES.iter().any(|l| l.name == lang) {
+                    return Err(OptionError::InvalidValue(lang.to_string()));
+                }
+                self.formatters.insert(lang.to_string(), v.to_string());
+            }
+            ("tabwidth" | "ts", Some(v)) => {
+                self.tab_width = match v.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(OptionError::InvalidValue(v.to_string())),
+                }
+            }
+            ("largefile", Some(v)) => {
+                self.large_file =
+                    Some(parse_size(v).ok_or(OptionError::InvalidValue(v.to_string()))?)
+            }
+            (n, _) if n.starts_with("formatter.") => {
+                return Err(OptionError::MissingValue(name.to_string()));
+            }
+            ("backupdir" | "writehelper" | "largefile" | "tabwidth" | "ts", _) => {
+                return Err(OptionError::MissingValue(name.to_string()));
+            }
+            _ => return Err(OptionError::UnknownOption(name.to_string())),
+        }
+        Ok(())
+    }
+}
+
+// options of a single pane, a split copies them
+#[derive(Debug, Clone)]
+pub struct PaneOptions {
+    // soft wrap the lines instead of scrolling sideways
+    pub wrap: bool,
+    // wrap here if the pane is wider, 0 for the pane width
+    pub wrap_column: usize,
+    // wrap at word boundaries
+    pub linebreak: bool,
+    // shown at the head of the wrapped rows
+    pub show_break: String,
+}
+
+impl PaneOptions {
+    pub fn new() -> Self {
+        Self {
+            wrap: false,
+            wrap_column: 0,
+            linebreak: false,
+            show_break: "↪".to_string(),
+        }
+    }
+
+    // returns false if `expr` isn't one of the pane options
+    pub fn set(&mut self, expr: &str) -> Result<bool, OptionError> {
+        let (name, value) = match expr.split_once('=') {
+            Some((n, v)) => (n.trim(), Some(v.trim())),
+            None => (expr.trim(), None),
+        };
+
+        match (name, value) {
+            ("wrap", None) => self.wrap = true,
+            ("nowrap", None) => self.wrap = false,
+            ("linebreak" | "lbr", None) => self.linebreak = true,
+            ("nolinebreak" | "nolbr", None) => self.linebreak = false,
+            ("wrapcolumn", Some(v)) => {
+                self.wrap_column = v
+                    .parse()
+                    .map_err(|_| OptionError::InvalidValue(v.to_string()))?
+            }
+            ("showbreak" | "sbr", Some(v)) => self.show_break = v.to_string(),
+            ("wrapcolumn" | "showbreak" | "sbr", None) => {
+                return Err(OptionError::MissingValue(name.to_string()));
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+// `1024`, `512K`, `32M` or `1G`
+fn parse_size(s: &str) -> Option<u64> {
+    let (num, unit) = match s.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, 'B'),
+    };
+    let shift = match unit {
+        'B' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        _ => return None,
+    };
+    num.trim().parse::<u64>().ok()?.checked_mul(1 << shift)
+}
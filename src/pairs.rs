@@ -0,0 +1,135 @@
+#![allow(dead_code)]
+// the brackets and quotes which come in pairs, for auto-pairing and matching
+use crate::buffer::Buffer;
+use unicode_segmentation::UnicodeSegmentation;
+
+// how far a matching bracket is looked for, so a lone one in a large file
+// doesn't scan all of it on every frame
+pub const MATCH_SCAN_LINES: usize = 2000;
+
+// the closer of `ch` if it's an opening bracket
+pub fn closer_of(ch: char) -> Option<char> {
+    match ch {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        _ => None,
+    }
+}
+
+pub fn opener_of(ch: char) -> Option<char> {
+    match ch {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        _ => None,
+    }
+}
+
+pub fn is_closer(ch: char) -> bool {
+    opener_of(ch).is_some()
+}
+
+pub fn is_quote(ch: char) -> bool {
+    matches!(ch, '"' | '\'')
+}
+
+// the second half of the pair `ch` opens, quotes close themselves
+pub fn pair_of(ch: char) -> Option<char> {
+    closer_of(ch).or(is_quote(ch).then_some(ch))
+}
+
+// the character `ch` is typed between `prev` and `next`, is it worth a pair
+pub fn should_pair(ch: char, prev: Option<char>, next: Option<char>) -> bool {
+    // not in front of a word, it's more likely wrapping it
+    let next_ok = next.is_none_or(|c| c.is_whitespace() || is_closer(c) || matches!(c, ',' | ';'));
+    if !next_ok {
+        return false;
+    }
+    // an apostrophe or the closing quote after a word
+    !is_quote(ch) || prev.is_none_or(|c| !c.is_alphanumeric() && c != ch)
+}
+
+fn single_char(g: &str) -> Option<char> {
+    let mut chars = g.chars();
+    let ch = chars.next()?;
+    chars.next().is_none().then_some(ch)
+}
+
+impl Buffer {
+    // the character of the grapheme at `pos`
+    pub fn char_at(&self, pos: (usize, usize)) -> Option<char> {
+        let (x, y) = pos;
+        single_char(self.content.get(y)?.graphemes(true).nth(x)?)
+    }
+
+    // the bracket `pos` is on, or the one just before it
+    pub fn bracket_near(&self, pos: (usize, usize)) -> Option<(usize, usize)> {
+        let is_bracket = |p| {
+            self.char_at(p)
+                .is_some_and(|c| closer_of(c).is_some() || is_closer(c))
+        };
+        if is_bracket(pos) {
+            Some(pos)
+        } else if pos.0 > 0 && is_bracket((pos.0 - 1, pos.1)) {
+            Some((pos.0 - 1, pos.1))
+        } else {
+            None
+        }
+    }
+
+    // the bracket which pairs with the one at `pos`
+    pub fn matching_bracket(&self, pos: (usize, usize)) -> Option<(usize, usize)> {
+        let ch = self.char_at(pos)?;
+        let (other, forward) = match (closer_of(ch), opener_of(ch)) {
+            (Some(c), _) => (c, true),
+            (_, Some(o)) => (o, false),
+            _ => return None,
+        };
+        let (x, y) = pos;
+        let mut depth = 0usize;
+        let mut visit = |ly: usize, lx: usize, g: &str| -> Option<(usize, usize)> {
+            match single_char(g) {
+                Some(c) if c == ch => depth += 1,
+                Some(c) if c == other => {
+                    if depth == 0 {
+                        return Some((lx, ly));
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+            None
+        };
+
+        if forward {
+            let last = self.content.len().min(y + MATCH_SCAN_LINES);
+            for ly in y..last {
+                let skip = if ly == y { x + 1 } else { 0 };
+                for (lx, g) in self.content[ly].graphemes(true).enumerate().skip(skip) {
+                    if let Some(found) = visit(ly, lx, g) {
+                        return Some(found);
+                    }
+                }
+            }
+        } else {
+            let first = y.saturating_sub(MATCH_SCAN_LINES);
+            for ly in (first..=y).rev() {
+                let graphemes: Vec<&str> = self.content[ly].graphemes(true).collect();
+                let end = if ly == y { x } else { graphemes.len() };
+                for lx in (0..end).rev() {
+                    if let Some(found) = visit(ly, lx, graphemes[lx]) {
+                        return Some(found);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // the bracket near `pos` and its match
+    pub fn bracket_pair_near(&self, pos: (usize, usize)) -> Option<[(usize, usize); 2]> {
+        let at = self.bracket_near(pos)?;
+        Some([at, self.matching_bracket(at)?])
+    }
+}
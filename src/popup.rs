@@ -1,6 +1,10 @@
 use ratatui::style::Color;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
+
+// the largest popup sized by its content, the rest is cut off
+const MAX_FIT_SIZE: (usize, usize) = (80, 16);
 
 #[derive(Debug)]
 pub struct Popup {
@@ -29,6 +33,15 @@ impl Popup {
         }
     }
 
+    // a popup as large as its content, with the borders around it
+    pub fn fit(content: &str, duration: Duration, color: Color) -> Self {
+        let lines: Vec<&str> = content.lines().take(MAX_FIT_SIZE.1 - 2).collect();
+        let width = lines.iter().map(|l| l.width()).max().unwrap_or(0);
+        let size = ((width + 2).min(MAX_FIT_SIZE.0), lines.len() + 2);
+        Self::new(lines.join("\n"), duration, size, color)
+    }
+
+    #[allow(dead_code)]
     pub fn with_position(mut self, pos: (usize, usize)) -> Self {
         self.position = Some(pos);
         self
@@ -52,7 +65,10 @@ impl Popups {
         self.inner.push(popup);
     }
 
-    pub fn update(&mut self) {
+    // drop the expired popups, returns whether there were any
+    pub fn update(&mut self) -> bool {
+        let count = self.inner.len();
         self.inner.retain(|p| !p.is_expired());
+        self.inner.len() != count
     }
 }
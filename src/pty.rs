@@ -0,0 +1,112 @@
+#![allow(dead_code)]
+// a pseudo terminal with a program on the other end, which takes it for a
+// real terminal: it gets the keys as a terminal sends them and its output
+// is read from the master side
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::ptr;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+pub struct Pty {
+    master: File,
+    // the keys go through a thread, a program which doesn't read them can't
+    // block the editor
+    input: Sender<Vec<u8>>,
+    pub child: Child,
+}
+
+fn window_size(rows: usize, cols: usize) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows.min(u16::MAX as usize) as u16,
+        ws_col: cols.min(u16::MAX as usize) as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+impl Pty {
+    // run `command` with the slave side as its terminal
+    pub fn spawn(mut command: Command, rows: usize, cols: usize) -> io::Result<Pty> {
+        let (mut master, mut slave) = (-1, -1);
+        let size = window_size(rows, cols);
+        // SAFETY: the pointers are to locals which outlive the call
+        let res =
+            unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), &size) };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty opened both and nothing else owns them
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        // the program doesn't get the master side
+        // SAFETY: fcntl on a descriptor this owns
+        unsafe { libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) };
+
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave))
+            .env("TERM", "xterm-256color");
+        // a session of its own with the terminal as the controlling one, so
+        // the shell has job control and Ctrl-c reaches the program
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        let master = File::from(master);
+        let mut writer = master.try_clone()?;
+        let (input, rx) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            for bytes in rx {
+                if writer.write_all(&bytes).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Pty {
+            master,
+            input,
+            child,
+        })
+    }
+
+    // the master side for a reader thread
+    pub fn reader(&self) -> io::Result<File> {
+        self.master.try_clone()
+    }
+
+    // fails once the writer stopped on an error
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.input
+            .send(bytes.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    // the program gets a SIGWINCH
+    pub fn resize(&self, rows: usize, cols: usize) -> io::Result<()> {
+        let size = window_size(rows, cols);
+        // SAFETY: TIOCSWINSZ reads a winsize which lives through the call
+        let res = unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
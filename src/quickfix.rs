@@ -0,0 +1,217 @@
+#![allow(dead_code)]
+// the quickfix list: the diagnostics of all the buffers, or the errors of a
+// build, one per line in a read-only buffer where Enter opens the location
+use crate::buffer::BufferManager;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::error::LayoutError;
+use crate::layout::layout_manager::LayoutManager;
+use crate::layout::tree::SplitDirection;
+use std::path::PathBuf;
+
+// the name of the buffer of the list
+pub const LIST_NAME: &str = "[quickfix]";
+
+#[derive(Debug, Clone)]
+pub struct QuickfixEntry {
+    // the file, a buffer which has none is found by its id
+    pub path: Option<PathBuf>,
+    pub buffer_id: Option<usize>,
+    // (x, y) like the cursor
+    pub pos: (usize, usize),
+    pub severity: Severity,
+    pub message: String,
+}
+
+pub struct Quickfix {
+    pub entries: Vec<QuickfixEntry>,
+    // the entry opened last
+    pub current: Option<usize>,
+    // the buffer showing the list
+    pub buffer_id: Option<usize>,
+    // the pane the locations open in, the one the list was opened from
+    pub origin: usize,
+    // what the entries came from if it's not the diagnostics of the buffers,
+    // the files opened from the list get them as diagnostics
+    pub producer: Option<&'static str>,
+}
+
+impl Quickfix {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![],
+            current: None,
+            buffer_id: None,
+            origin: 0,
+            producer: None,
+        }
+    }
+
+    // the diagnostics of all the buffers, the worst first on each line
+    pub fn collect_diagnostics(&mut self, bm: &BufferManager) {
+        let mut ids: Vec<usize> = bm.buffers.keys().copied().collect();
+        ids.sort_unstable();
+        self.entries = ids
+            .into_iter()
+            .filter(|id| Some(*id) != self.buffer_id)
+            .flat_map(|id| {
+                let buf = &bm.buffers[&id];
+                buf.diagnostics.iter().map(move |d| QuickfixEntry {
+                    path: buf.path.clone(),
+                    buffer_id: Some(id),
+                    pos: d.start,
+                    severity: d.severity,
+                    message: d.message.clone(),
+                })
+            })
+            .collect();
+        self.current = None;
+        self.producer = None;
+    }
+
+    // the line of an entry, `file:line:col: E message`
+    fn line(&self, bm: &BufferManager, entry: &QuickfixEntry) -> String {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let name = match &entry.path {
+            Some(path) => path
+                .strip_prefix(&cwd)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+            None => entry
+                .buffer_id
+                .and_then(|id| bm.get_buffer(id).ok())
+                .map_or("?".to_string(), |buf| buf.name.to_string()),
+        };
+        // the message on one line
+        let message = entry.message.lines().next().unwrap_or_default();
+        format!(
+            "{}:{}:{}: {} {}",
+            name,
+            entry.pos.1 + 1,
+            entry.pos.0 + 1,
+            entry.severity.sign(),
+            message
+        )
+    }
+
+    // write the entries into the list buffer, which is made if it's gone
+    // and `create` is set
+    pub fn refresh(&mut self, bm: &mut BufferManager, create: bool) {
+        let lines: Vec<String> = self.entries.iter().map(|e| self.line(bm, e)).collect();
+        let id = match self.buffer_id.filter(|id| bm.get_buffer(*id).is_ok()) {
+            Some(id) => id,
+            None if create => bm.add_new_buffer(LIST_NAME),
+            None => return,
+        };
+        if let Ok(buf) = bm.get_buffer_mut(id) {
+            buf.set_scratch_content(lines);
+        }
+        self.buffer_id = Some(id);
+    }
+
+    // show the list in a pane below the current one, or in the pane which
+    // shows it already
+    pub fn open(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+    ) -> Result<(), LayoutError> {
+        self.refresh(bm, true);
+        let Some(id) = self.buffer_id else {
+            return Ok(());
+        };
+        if let Some(pane) = lm.pane_showing(id) {
+            lm.focus(pane);
+            return Ok(());
+        }
+        self.origin = lm.current_layout;
+        lm.split(self.origin, Some(id), SplitDirection::Horizontal, bm)?;
+        lm.jump_to(bm, id, (0, 0))
+    }
+
+    // whether buffer `id` is the list
+    pub fn is_list(&self, id: usize) -> bool {
+        self.buffer_id == Some(id)
+    }
+
+    // open the location of entry `n` in the pane the list came from, returns
+    // its message
+    pub fn jump(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        n: usize,
+    ) -> Result<String, LayoutError> {
+        let entry = self.entries.get(n).ok_or(LayoutError::NoEntry)?.clone();
+        let id = match entry.buffer_id.filter(|id| bm.get_buffer(*id).is_ok()) {
+            Some(id) => id,
+            None => {
+                let path = entry.path.as_ref().ok_or(LayoutError::NoEntry)?;
+                let id = bm.find_or_open(path)?;
+                self.add_diagnostics(bm, id, path);
+                id
+            }
+        };
+        // the list stays where it is, a pane is made for the file if the
+        // one it came from is gone
+        let current = lm.get_current_buffer(bm).map(|buf| buf.id).ok();
+        if current.is_some_and(|id| self.is_list(id)) && !lm.focus(self.origin) {
+            lm.split(lm.current_layout, Some(id), SplitDirection::Horizontal, bm)?;
+            self.origin = lm.current_layout;
+        }
+        lm.jump_to(bm, id, entry.pos)?;
+        self.current = Some(n);
+        Ok(format!(
+            "({}/{}) {}",
+            n + 1,
+            self.entries.len(),
+            entry.message.lines().next().unwrap_or_default()
+        ))
+    }
+
+    // the entries of `path` become the diagnostics of buffer `id`, if they
+    // came from a producer and the buffer has none of it yet
+    fn add_diagnostics(&self, bm: &mut BufferManager, id: usize, path: &PathBuf) {
+        let Some(producer) = self.producer else {
+            return;
+        };
+        let Ok(buf) = bm.get_buffer_mut(id) else {
+            return;
+        };
+        if buf.diagnostics.iter().any(|d| d.producer == producer) {
+            return;
+        }
+        let diagnostics = self
+            .entries
+            .iter()
+            .filter(|e| e.path.as_ref() == Some(path))
+            .map(|e| Diagnostic {
+                start: e.pos,
+                end: (e.pos.0 + 1, e.pos.1),
+                severity: e.severity,
+                message: e.message.clone(),
+                source: producer.to_string(),
+                producer: producer.to_string(),
+            })
+            .collect();
+        buf.set_diagnostics(producer, diagnostics);
+    }
+
+    // the entry after or before the one opened last
+    pub fn step(
+        &mut self,
+        bm: &mut BufferManager,
+        lm: &mut LayoutManager,
+        forward: bool,
+    ) -> Result<String, LayoutError> {
+        if self.entries.is_empty() {
+            return Err(LayoutError::NoEntry);
+        }
+        let n = match self.current {
+            Some(n) if forward => (n + 1).min(self.entries.len() - 1),
+            Some(n) => n.saturating_sub(1),
+            None => 0,
+        };
+        self.jump(bm, lm, n)
+    }
+}
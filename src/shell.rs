@@ -0,0 +1,90 @@
+#![allow(dead_code)]
+// run a shell command with some text on its stdin and wait for its output,
+// for `!<cmd>` which inserts it or filters lines through it
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// a command which takes longer is killed, a save waits for its formatter
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// the stdout of `command`, its stderr or exit status when it failed, a
+// command which printed to stderr failed even if it exited with 0
+pub fn run(command: &str, input: Option<String>) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", command, e))?;
+
+    // the pipes are written and read in threads, a command can fill one
+    // before it reads the other
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = vec![];
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut bytes);
+            }
+            bytes
+        })
+    };
+    let stdout = read(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = read(child.stderr.take().map(|p| Box::new(p) as _));
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < TIMEOUT => thread::sleep(POLL_INTERVAL),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{} took more than {}s", command, TIMEOUT.as_secs()));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    let stdout = String::from_utf8_lossy(&stdout.join().unwrap_or_default()).into_owned();
+    let stderr = String::from_utf8_lossy(&stderr.join().unwrap_or_default()).into_owned();
+
+    // the first lines of stderr, KaoCo shows one line
+    let stderr: Vec<&str> = stderr
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .take(3)
+        .collect();
+    match (status.code(), stderr.is_empty()) {
+        (Some(0), true) => Ok(stdout),
+        (Some(0), false) => Err(stderr.join(" | ")),
+        (code, _) => {
+            let code = code.map_or("killed".to_string(), |c| format!("exit {}", c));
+            match stderr.is_empty() {
+                true => Err(format!("{}: {}", command, code)),
+                false => Err(format!("{}: {}", code, stderr.join(" | "))),
+            }
+        }
+    }
+}
+
+// the lines of an output, without the newline at its end
+pub fn output_lines(output: &str) -> Vec<String> {
+    let output = output.strip_suffix('\n').unwrap_or(output);
+    output
+        .split('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l).to_string())
+        .collect()
+}
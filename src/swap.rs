@@ -0,0 +1,73 @@
+// swap files keep a copy of the unsaved content of a buffer next to its file,
+// so the edits can be recovered after a crash
+use crate::fileio;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// how often a modified buffer is written into its swap file
+pub const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+
+const SWAP_HEADER: &str = "smile swap 1";
+
+// `dir/name.rs` -> `dir/.name.rs.swp`
+pub fn swap_path_for(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    Some(path.with_file_name(format!(".{}.swp", name)))
+}
+
+// replaced whole, the last swap stays until the new one is written, and
+// only the user can read it whatever the file allows
+pub fn write_swap(swap: &Path, origin: &Path, content: &[String]) -> io::Result<()> {
+    fileio::atomic_write_private(swap, |writer| {
+        writeln!(writer, "{}", SWAP_HEADER)?;
+        writeln!(writer, "{}", origin.display())?;
+        for line in content {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    })
+}
+
+pub fn read_swap(swap: &Path) -> io::Result<Vec<String>> {
+    let reader = BufReader::new(File::open(swap)?);
+    let mut lines = reader.lines();
+
+    match lines.next() {
+        Some(Ok(header)) if header == SWAP_HEADER => {}
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a smile swap file",
+            ));
+        }
+    }
+    // skip the path of the original file
+    lines.next().transpose()?;
+
+    let mut content = lines.collect::<io::Result<Vec<String>>>()?;
+    if content.is_empty() {
+        content.push(String::new());
+    }
+    Ok(content)
+}
+
+// a swap file is only worth recovering if it was written after the file itself
+pub fn is_swap_newer(swap: &Path, origin: &Path) -> bool {
+    let swap_time = match fs::metadata(swap).and_then(|m| m.modified()) {
+        Ok(t) => t,
+        Err(_) => return false,
+    };
+    match fs::metadata(origin).and_then(|m| m.modified()) {
+        Ok(origin_time) => swap_time >= origin_time,
+        Err(_) => true,
+    }
+}
+
+pub fn remove_swap(swap: &Path) -> io::Result<()> {
+    match fs::remove_file(swap) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,280 @@
+#![allow(dead_code)]
+// the terminals shown in panes: a shell or a command runs on a pty, its
+// output goes through the screen grid and the keys of the focused pane are
+// sent to it the way a terminal sends them
+use crate::event_loop::AppEvent;
+use crate::layout::layout_manager::LayoutManager;
+use crate::layout::tree::{LayoutNode, PaneContent, collect_panes};
+use crate::pty::Pty;
+use crate::vt::Grid;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+// the rows and the columns until the pane is drawn
+const DEFAULT_SIZE: (usize, usize) = (24, 80);
+
+pub struct Terminal {
+    pub id: usize,
+    pub name: String,
+    pty: Pty,
+    pub grid: Grid,
+    // the lines the view is scrolled back from the bottom
+    pub scroll: usize,
+    // how the program ended
+    pub exited: Option<String>,
+}
+
+impl Terminal {
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.exited.is_some() {
+            return Ok(());
+        }
+        self.pty.write(bytes)
+    }
+
+    // send a key to the program, the view goes back to the bottom
+    pub fn send_key(&mut self, key: KeyEvent) -> io::Result<()> {
+        let Some(bytes) = key_bytes(key, self.grid.app_cursor) else {
+            return Ok(());
+        };
+        self.scroll = 0;
+        self.write(&bytes)
+    }
+
+    // scroll the view back through the output, or down again
+    pub fn scroll_by(&mut self, lines: isize) {
+        let scroll = self.scroll as isize + lines;
+        self.scroll = scroll.clamp(0, self.grid.scrollback.len() as isize) as usize;
+    }
+}
+
+pub struct TerminalManager {
+    pub terminals: HashMap<usize, Terminal>,
+    counter: usize,
+    tx: Sender<AppEvent>,
+}
+
+impl TerminalManager {
+    pub fn new(tx: Sender<AppEvent>) -> Self {
+        Self {
+            terminals: HashMap::new(),
+            counter: 1,
+            tx,
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Terminal> {
+        self.terminals.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Terminal> {
+        self.terminals.get_mut(&id)
+    }
+
+    // start `command` in a shell, or the shell of the user, its output is
+    // read in a thread and comes back as events
+    pub fn open(&mut self, command: Option<&str>) -> io::Result<usize> {
+        let (cmd, name) = match command {
+            Some(command) => {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c").arg(command);
+                (cmd, command.to_string())
+            }
+            None => {
+                let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+                let name = Path::new(&shell)
+                    .file_name()
+                    .map_or(shell.clone(), |n| n.to_string_lossy().into_owned());
+                (Command::new(&shell), name)
+            }
+        };
+        let (rows, cols) = DEFAULT_SIZE;
+        let pty = Pty::spawn(cmd, rows, cols)?;
+        let mut reader = pty.reader()?;
+
+        let id = self.counter;
+        self.counter += 1;
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let mut bytes = vec![0; 16 * 1024];
+            loop {
+                match reader.read(&mut bytes) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        let bytes = bytes[..n].to_vec();
+                        if tx.send(AppEvent::TerminalOutput { id, bytes }).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    // EIO once the program and its children closed the pty
+                    Err(_) => break,
+                }
+            }
+            let _ = tx.send(AppEvent::TerminalExited(id));
+        });
+
+        self.terminals.insert(
+            id,
+            Terminal {
+                id,
+                name,
+                pty,
+                grid: Grid::new(rows, cols),
+                scroll: 0,
+                exited: None,
+            },
+        );
+        Ok(id)
+    }
+
+    // the output of terminal `id` into its grid, the answers to the queries
+    // in it are written back
+    pub fn output(&mut self, id: usize, bytes: &[u8]) -> bool {
+        let Some(term) = self.terminals.get_mut(&id) else {
+            return false;
+        };
+        let before = term.grid.scrollback.len();
+        term.grid.feed(bytes);
+        if !term.grid.replies.is_empty() {
+            let replies = std::mem::take(&mut term.grid.replies);
+            let _ = term.write(&replies);
+        }
+        // a view scrolled back stays on the lines it shows
+        if term.scroll > 0 {
+            let added = term.grid.scrollback.len().saturating_sub(before);
+            term.scroll_by(added as isize);
+        }
+        true
+    }
+
+    // the program of terminal `id` ended, returns what to tell about it
+    pub fn exited(&mut self, id: usize) -> Option<String> {
+        let term = self.terminals.get_mut(&id)?;
+        let status = term.pty.child.wait().ok()?;
+        let text = match status.code() {
+            Some(code) => format!("exit {}", code),
+            None => "killed".to_string(),
+        };
+        term.exited = Some(text.clone());
+        Some(format!("{}: {}", term.name, text))
+    }
+
+    // the size of the grids follows the panes drawn last, returns whether
+    // any changed
+    pub fn fit(&mut self, lm: &LayoutManager) -> bool {
+        let mut changed = false;
+        for (pane, id) in terminal_panes(lm) {
+            let (Some(rect), Some(term)) = (lm.pane_rects.get(&pane), self.terminals.get_mut(&id))
+            else {
+                continue;
+            };
+            // inside the borders
+            let rows = rect.height.saturating_sub(2).max(1) as usize;
+            let cols = rect.width.saturating_sub(2).max(1) as usize;
+            if (rows, cols) != (term.grid.rows, term.grid.cols) {
+                term.grid.resize(rows, cols);
+                let _ = term.pty.resize(rows, cols);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // the terminals no pane shows any more are closed, their programs killed
+    pub fn prune(&mut self, lm: &LayoutManager) {
+        if self.terminals.is_empty() {
+            return;
+        }
+        let shown: Vec<usize> = terminal_panes(lm).into_iter().map(|(_, id)| id).collect();
+        self.terminals.retain(|id, _| shown.contains(id));
+    }
+}
+
+// the panes showing terminals, with the ids of the terminals
+pub fn terminal_panes(lm: &LayoutManager) -> Vec<(usize, usize)> {
+    let mut panes = vec![];
+    if let Some(root) = &lm.panes {
+        collect_panes(root, &mut panes);
+    }
+    panes
+        .into_iter()
+        .filter_map(|pane| match pane {
+            LayoutNode::Content {
+                id,
+                content: PaneContent::Terminal(term),
+            } => Some((*id, *term)),
+            _ => None,
+        })
+        .collect()
+}
+
+// the bytes a terminal sends for a key, with the modifiers of xterm
+pub fn key_bytes(key: KeyEvent, app_cursor: bool) -> Option<Vec<u8>> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let mut alt = key.modifiers.contains(KeyModifiers::ALT);
+    let mut bytes = match key.code {
+        KeyCode::Char(c) if ctrl => match c.to_ascii_lowercase() {
+            c @ 'a'..='z' => vec![c as u8 - b'a' + 1],
+            ' ' | '@' | '2' => vec![0],
+            '[' | '3' => vec![0x1b],
+            '\\' | '4' => vec![0x1c],
+            ']' | '5' => vec![0x1d],
+            '^' | '6' => vec![0x1e],
+            '_' | '/' | '7' => vec![0x1f],
+            _ => return None,
+        },
+        KeyCode::Char(c) => c.to_string().into_bytes(),
+        KeyCode::Enter => vec![b'\r'],
+        KeyCode::Backspace => vec![0x7f],
+        KeyCode::Tab => vec![b'\t'],
+        KeyCode::BackTab => b"\x1b[Z".to_vec(),
+        KeyCode::Esc => vec![0x1b],
+        code @ (KeyCode::Up
+        | KeyCode::Down
+        | KeyCode::Right
+        | KeyCode::Left
+        | KeyCode::Home
+        | KeyCode::End) => {
+            let ch = match code {
+                KeyCode::Up => 'A',
+                KeyCode::Down => 'B',
+                KeyCode::Right => 'C',
+                KeyCode::Left => 'D',
+                KeyCode::Home => 'H',
+                _ => 'F',
+            };
+            let modifiers = 1
+                + key.modifiers.contains(KeyModifiers::SHIFT) as u8
+                + 2 * alt as u8
+                + 4 * ctrl as u8;
+            alt = false;
+            match (modifiers, app_cursor) {
+                (1, true) => format!("\x1bO{}", ch).into_bytes(),
+                (1, false) => format!("\x1b[{}", ch).into_bytes(),
+                (m, _) => format!("\x1b[1;{}{}", m, ch).into_bytes(),
+            }
+        }
+        KeyCode::Insert => b"\x1b[2~".to_vec(),
+        KeyCode::Delete => b"\x1b[3~".to_vec(),
+        KeyCode::PageUp => b"\x1b[5~".to_vec(),
+        KeyCode::PageDown => b"\x1b[6~".to_vec(),
+        KeyCode::F(n @ 1..=4) => vec![0x1b, b'O', b'P' + n - 1],
+        KeyCode::F(n @ 5..=12) => {
+            let code = [15, 17, 18, 19, 20, 21, 23, 24][n as usize - 5];
+            format!("\x1b[{}~", code).into_bytes()
+        }
+        _ => return None,
+    };
+    // Alt sends an ESC first
+    if alt {
+        bytes.insert(0, 0x1b);
+    }
+    Some(bytes)
+}
@@ -6,19 +6,32 @@ use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph};
 use std::collections::HashMap;
 use std::io::stdout;
+use std::ops::Range;
 
 use crate::app::{App, Mod, Screen};
 use crate::buffer::*;
 use crate::command::*;
+use crate::completion::Completion;
 use crate::cursor::Cursor;
+use crate::diagnostic::{Severity, count, worst_on_line};
+use crate::diffview::{DiffRow, DiffView, Side};
 use crate::error::*;
+use crate::explorer::Explorer;
+use crate::git::line_change;
 use crate::layout::layout_manager::*;
 use crate::layout::tree::*;
+use crate::options::PaneOptions;
 use crate::popup::Popups;
+#[cfg(unix)]
+use crate::terminal::{Terminal, terminal_panes};
 use crate::utils::*;
+#[cfg(unix)]
+use crate::vt::WIDE_TAIL;
+use crate::wrap::*;
 
 pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
     match app.current_screen {
+        #[allow(clippy::unit_arg)]
         Screen::Welcome => Ok({
             frame.render_widget(Clear, frame.area());
             let root = Layout::default()
@@ -71,9 +84,23 @@ pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
 
             let layout_m = &mut app.layout_manager;
 
-            let mut panes = layout_m.panes.as_mut().ok_or(LayoutError::NoNode)?;
+            // the explorer highlights the file of the pane focused last
+            let path = match layout_m
+                .panes
+                .as_ref()
+                .and_then(|root| root.get_pane(app.explorer.last_pane))
+            {
+                Some(LayoutNode::Pane { buffer_id, .. }) => app
+                    .buf_manager
+                    .get_buffer(*buffer_id)
+                    .ok()
+                    .and_then(|buf| buf.path.as_deref()),
+                _ => None,
+            };
+            app.explorer.show(path);
+            let panes = layout_m.panes.as_mut().ok_or(LayoutError::NoNode)?;
             let editor_rect = render_layout(
-                &mut panes,
+                panes,
                 root[0],
                 frame,
                 &app.buf_manager,
@@ -81,8 +108,25 @@ pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
                 &mut layout_m.pane_rects,
                 layout_m.current_layout,
                 &app.current_mod,
+                app.diff.as_ref(),
+                &mut app.explorer,
             )?
             .ok_or(RenderError::RenderLayoutError)?;
+            // the terminals go in the places left for them
+            #[cfg(unix)]
+            let editor_rect = {
+                let mut focused_rect = editor_rect;
+                for (pane, id) in terminal_panes(layout_m) {
+                    if let Some(rect) = layout_m.pane_rects.get(&pane) {
+                        let focused = pane == layout_m.current_layout;
+                        let inner = render_terminal(app.terminals.get(id), *rect, frame, focused);
+                        if focused {
+                            focused_rect = inner;
+                        }
+                    }
+                }
+                focused_rect
+            };
 
             // command frame include kaomoji and command line
             let cmd = &mut app.command;
@@ -129,7 +173,7 @@ pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
                 .borders(Borders::TOP | Borders::LEFT | Borders::BOTTOM)
                 .border_style(Style::default().fg(command_line_border_color))
                 .style(Style::default().fg(kaomoji_color));
-            let kaomoji = Paragraph::new(kaomoji_to_text(cmd.kmj.clone()))
+            let kaomoji = Paragraph::new(kaomoji_to_text(cmd.kmj))
                 .alignment(Alignment::Center)
                 .block(kaomoji_block);
 
@@ -153,10 +197,26 @@ pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
             // show the cursor
             match app.current_screen {
                 Screen::Editor => {
+                    // the cursor of the program, unless scrolled back
+                    #[cfg(unix)]
+                    if let Some(PaneContent::Terminal(id)) = app.layout_manager.current_content()
+                        && let Some(term) = app.terminals.get(id)
+                        && term.grid.cursor_visible
+                        && term.scroll == 0
+                        && term.exited.is_none()
+                    {
+                        let cursor_x = editor_rect.x + term.grid.cursor.0 as u16;
+                        let cursor_y = editor_rect.y + term.grid.cursor.1 as u16;
+                        if cursor_x < editor_rect.right() && cursor_y < editor_rect.bottom() {
+                            frame.set_cursor_position((cursor_x, cursor_y));
+                        }
+                    }
+                    #[allow(clippy::collapsible_if)]
                     if let Some(LayoutNode::Pane {
                         cursor,
                         scroll_offset,
                         buffer_id,
+                        options,
                         ..
                     }) = app.layout_manager.get_current_pane()
                     {
@@ -164,17 +224,52 @@ pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
                             render_cursor(&cursor)?;
                             let (cx, cy) = cursor.pos;
 
-                            let vx = buf.get_visual_width_upto(cy, cx);
-
-                            let cursor_x =
-                                editor_rect.x + (vx.saturating_sub(scroll_offset.0)) as u16;
-                            //editor_rect.x + (vx.saturating_sub(scroll_offset.0)) as u16;
-                            let cursor_y =
-                                editor_rect.y + (cy.saturating_sub(scroll_offset.1)) as u16 + 1;
-
-                            if cursor_x < editor_rect.right() && cursor_y < editor_rect.bottom() {
+                            let wrap = app
+                                .layout_manager
+                                .get_current_rect()
+                                .and_then(|r| wrap_width(*r, &options));
+                            let side = app.diff.as_ref().and_then(|view| {
+                                Some((view, view.side(app.layout_manager.current_layout)?))
+                            });
+                            let (row, col) = match (side, wrap) {
+                                // the fillers above it take rows too
+                                (Some((view, side)), _) => (
+                                    view.row_of(side, cy).saturating_sub(view.top),
+                                    buf.get_visual_width_upto(cy, cx)
+                                        .saturating_sub(scroll_offset.0),
+                                ),
+                                (None, Some(width)) => buf.wrapped_position(
+                                    cursor.pos,
+                                    scroll_offset.1,
+                                    width,
+                                    &options,
+                                ),
+                                (None, None) => (
+                                    cy.saturating_sub(scroll_offset.1),
+                                    buf.get_visual_width_upto(cy, cx)
+                                        .saturating_sub(scroll_offset.0),
+                                ),
+                            };
+
+                            let cursor_x = editor_rect.x + col as u16;
+                            let cursor_y = editor_rect.y + row as u16 + 1;
+
+                            if app.cursor_shown
+                                && cursor_x < editor_rect.right()
+                                && cursor_y < editor_rect.bottom()
+                            {
                                 frame.set_cursor_position((cursor_x, cursor_y));
                             }
+                            // the menu lines up with the text it completes
+                            if let Some(menu) = &app.completion
+                                && menu.buffer_id == buffer_id
+                            {
+                                let typed = buf
+                                    .get_visual_width_upto(cy, cx)
+                                    .saturating_sub(buf.get_visual_width_upto(cy, menu.start.0));
+                                let x = cursor_x.saturating_sub(typed as u16);
+                                render_completion(menu, frame, (x, cursor_y));
+                            }
                         }
                     }
                 }
@@ -182,13 +277,15 @@ pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
                     let command_line_area = command_line_frame[1];
                     let vx = cmd.get_visual_width_upto(cmd.cursor_pos.0);
                     let cursor_x = command_line_area.x
-                        + get_line_len(&cmd.say.to_string()) as u16
+                        + get_line_len(&cmd.say) as u16
                         + (vx.saturating_sub(cmd.scroll_offset.0)) as u16
                         + 2;
                     let cursor_y = command_line_area.y
                         + (cmd.cursor_pos.1.saturating_sub(cmd.scroll_offset.1)) as u16
                         + 1;
-                    if cursor_x < command_line_area.right() && cursor_y < command_line_area.bottom()
+                    if app.cursor_shown
+                        && cursor_x < command_line_area.right()
+                        && cursor_y < command_line_area.bottom()
                     {
                         frame.set_cursor_position((cursor_x, cursor_y));
                     }
@@ -202,6 +299,7 @@ pub fn ui(frame: &mut Frame, app: &mut App) -> Result<(), RenderError> {
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::only_used_in_recursion)]
 pub fn render_layout(
     node: &mut LayoutNode,
     area: Rect,
@@ -211,6 +309,8 @@ pub fn render_layout(
     pane_rects: &mut HashMap<usize, Rect>,
     current_layout: usize,
     cur_mod: &Mod,
+    diff: Option<&DiffView>,
+    explorer: &mut Explorer,
 ) -> Result<Option<Rect>, RenderError> {
     match node {
         LayoutNode::Pane {
@@ -219,11 +319,11 @@ pub fn render_layout(
             scroll_offset,
             scroll_thres,
             buffer_id,
-            ..
+            options,
         } => {
             let buf = buf_m.get_buffer(*buffer_id)?;
             let res_rect = render_buffer(
-                &buf,
+                buf,
                 area,
                 f,
                 buf_m,
@@ -234,6 +334,8 @@ pub fn render_layout(
                 current_layout,
                 *id,
                 cur_mod,
+                options,
+                diff.and_then(|view| Some((view, view.side(*id)?))),
             )?;
             pane_rects.insert(*id, area);
             if *id == current_layout {
@@ -242,6 +344,33 @@ pub fn render_layout(
                 Ok(None)
             }
         }
+        // the terminals are drawn after the layout, in the area kept here
+        #[cfg(unix)]
+        LayoutNode::Content {
+            id,
+            content: PaneContent::Terminal(_),
+        } => {
+            pane_rects.insert(*id, area);
+            if *id == current_layout {
+                Ok(Some(area))
+            } else {
+                Ok(None)
+            }
+        }
+
+        LayoutNode::Content {
+            id,
+            content: PaneContent::Explorer,
+        } => {
+            let focused = *id == current_layout;
+            let res_rect = render_explorer(explorer, area, f, focused);
+            pane_rects.insert(*id, area);
+            if focused {
+                Ok(Some(res_rect))
+            } else {
+                Ok(None)
+            }
+        }
 
         LayoutNode::Split {
             direc,
@@ -276,6 +405,8 @@ pub fn render_layout(
                 pane_rects,
                 current_layout,
                 cur_mod,
+                diff,
+                explorer,
             )?;
             let res2 = render_layout(
                 second,
@@ -286,6 +417,8 @@ pub fn render_layout(
                 pane_rects,
                 current_layout,
                 cur_mod,
+                diff,
+                explorer,
             )?;
 
             if let Some(r) = res1 {
@@ -300,6 +433,137 @@ pub fn render_layout(
     }
 }
 
+// the tree of the explorer in a pane with borders all around, returns the
+// area inside them
+pub fn render_explorer(
+    explorer: &mut Explorer,
+    rect: Rect,
+    frame: &mut Frame,
+    focused: bool,
+) -> Rect {
+    let border_color = if focused {
+        Color::Rgb(181, 235, 181)
+    } else {
+        Color::Rgb(129, 181, 129)
+    };
+    let dir_color = Color::Rgb(167, 199, 250);
+    let file_color = Color::Rgb(240, 235, 213);
+    let shown_color = Color::Rgb(150, 240, 105);
+    let selected_bg = Color::Rgb(127, 148, 141);
+
+    let name = explorer
+        .root
+        .file_name()
+        .map_or("/".to_string(), |n| n.to_string_lossy().into_owned());
+    let flags = match (explorer.show_hidden, explorer.show_ignored) {
+        (true, true) => " [hidden, ignored]",
+        (true, false) => " [hidden]",
+        (false, true) => " [ignored]",
+        (false, false) => "",
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(border_color))
+        .title(format!(" {}{} ", name, flags));
+    let inner = block.inner(rect);
+    frame.render_widget(block, rect);
+
+    explorer.update_scroll(inner.height as usize);
+    let lines: Vec<Line> = explorer
+        .entries
+        .iter()
+        .enumerate()
+        .skip(explorer.scroll)
+        .take(inner.height as usize)
+        .map(|(i, entry)| {
+            let marker = match (entry.is_dir, explorer.is_open(&entry.path)) {
+                (true, true) => "▾ ",
+                (true, false) => "▸ ",
+                (false, _) => "  ",
+            };
+            let slash = if entry.is_dir { "/" } else { "" };
+            let mut style = match entry.is_dir {
+                true => Style::default().fg(dir_color),
+                false => Style::default().fg(file_color),
+            };
+            if entry.ignored {
+                style = style.fg(Color::DarkGray);
+            }
+            if explorer.shown.as_ref() == Some(&entry.path) {
+                style = style.fg(shown_color).add_modifier(Modifier::BOLD);
+            }
+            if focused && i == explorer.selected {
+                style = style.bg(selected_bg);
+            }
+            let indent = "  ".repeat(entry.depth);
+            let text = format!("{}{}{}{}", indent, marker, entry.name, slash);
+            Line::from(Span::styled(text, style))
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), inner);
+    inner
+}
+
+// the screen of a terminal in a pane with borders all around, returns the
+// area inside them
+#[cfg(unix)]
+pub fn render_terminal(
+    term: Option<&Terminal>,
+    rect: Rect,
+    frame: &mut Frame,
+    focused: bool,
+) -> Rect {
+    let border_color = if focused {
+        Color::Rgb(181, 235, 181)
+    } else {
+        Color::Rgb(129, 181, 129)
+    };
+    let title = match term {
+        Some(term) => {
+            let name = term.grid.title.as_deref().unwrap_or(&term.name);
+            match (&term.exited, term.scroll) {
+                (Some(status), _) => format!(" {} [{}] ", name, status),
+                (None, 0) => format!(" {} ", name),
+                (None, scroll) => format!(" {} [-{}] ", name, scroll),
+            }
+        }
+        None => " [closed] ".to_string(),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(border_color))
+        .title(title);
+    let inner = block.inner(rect);
+    frame.render_widget(block, rect);
+    let Some(term) = term else {
+        return inner;
+    };
+
+    // the screen, or the lines above it when scrolled back
+    let grid = &term.grid;
+    let first = grid.total_lines().saturating_sub(grid.rows + term.scroll);
+    let end = (first + inner.height as usize).min(grid.total_lines());
+    let lines: Vec<Line> = (first..end)
+        .map(|i| {
+            let mut spans: Vec<Span> = vec![];
+            let mut text = String::new();
+            let mut style = Style::default();
+            for cell in grid.line(i).iter().filter(|c| c.ch != WIDE_TAIL) {
+                if cell.style != style && !text.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut text), style));
+                }
+                style = cell.style;
+                text.push(cell.ch);
+            }
+            spans.push(Span::styled(text, style));
+            Line::from(spans)
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), inner);
+    inner
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn render_buffer(
     buf: &Buffer,
     rect: Rect,
@@ -312,6 +576,8 @@ pub fn render_buffer(
     current_layout: usize,
     pane_id: usize,
     cur_mod: &Mod,
+    options: &PaneOptions,
+    diff: Option<(&DiffView, Side)>,
 ) -> Result<Rect, LayoutError> {
     // editor frame include editor and status bar
     let editor_frame = Layout::default()
@@ -322,7 +588,7 @@ pub fn render_buffer(
     // show the editor
     let editor_main = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Length(10), Constraint::Min(0)])
+        .constraints([Constraint::Length(LINE_NUMBER_WIDTH), Constraint::Min(0)])
         .split(editor_frame[0]);
 
     // check the scrolling
@@ -330,15 +596,32 @@ pub fn render_buffer(
     let viewport_width = editor_main[1].width.saturating_sub(2) as usize;
 
     check_cursor_pos(buf_m, cursor_pos, buffer_id)?;
-    update_scroll(
-        buf_m,
-        viewport_height,
-        viewport_width,
-        cursor_pos,
-        scroll_offset,
-        scroll_thres,
-        buffer_id,
-    )?;
+    // the lines of a diff aren't wrapped, they're aligned with the other side
+    // which scrolled them already
+    let wrap = match diff {
+        Some(_) => None,
+        None => wrap_width(rect, options),
+    };
+    match (diff, wrap) {
+        (Some(_), _) => {}
+        (None, Some(width)) => update_scroll_wrapped(
+            buf,
+            viewport_height,
+            width,
+            options,
+            *cursor_pos,
+            scroll_offset,
+        ),
+        (None, None) => update_scroll(
+            buf_m,
+            viewport_height,
+            viewport_width,
+            cursor_pos,
+            scroll_offset,
+            scroll_thres,
+            buffer_id,
+        )?,
+    }
     // editor's color
     let border_color_active = Color::Rgb(181, 235, 181);
     let border_color_no = Color::Rgb(129, 181, 129);
@@ -355,18 +638,110 @@ pub fn render_buffer(
         .borders(Borders::LEFT | Borders::TOP)
         .border_style(Style::default().fg(border_color))
         .style(Style::default().fg(Color::DarkGray));
+    let selection = match cur_mod {
+        Mod::Visual(x, y) if (*x, *y) <= *cursor_pos => Some(((*x, *y), *cursor_pos)),
+        Mod::Visual(x, y) => Some((*cursor_pos, (*x, *y))),
+        _ => None,
+    };
+    let brackets = buf.bracket_pair_near(*cursor_pos);
+
+    // only the lines in the viewport are drawn
     let total_lines = buf.content.len();
-    let line_num: Vec<Line> = (1..=total_lines)
-        .map(|n| {
-            Line::from(Span::styled(
-                format!("{:>3} ", n),
-                Style::default().fg(Color::DarkGray),
-            ))
-        })
-        .collect();
-    let line_num_text = Paragraph::new(Text::from(line_num))
-        .block(line_num_block)
-        .scroll((scroll_offset.1 as u16, 0));
+    let first = scroll_offset.1.min(total_lines);
+    let height = editor_main[1].height as usize;
+    let mut line_num: Vec<Line> = vec![];
+    let mut lines: Vec<Line> = vec![];
+    let rows: Vec<DiffRow> = match diff {
+        Some((view, side)) => view
+            .rows(side, total_lines)
+            .into_iter()
+            .skip(view.top)
+            .take(height)
+            .collect(),
+        None => (first..total_lines)
+            .take(height)
+            .map(DiffRow::Line)
+            .collect(),
+    };
+    for row in rows {
+        if lines.len() >= height {
+            break;
+        }
+        let y = match row {
+            DiffRow::Line(y) => y,
+            // a line of the other side
+            DiffRow::Filler => {
+                line_num.push(Line::from(""));
+                lines.push(Line::styled(
+                    "╱".repeat(editor_main[1].width as usize),
+                    Style::default().fg(Color::DarkGray),
+                ));
+                continue;
+            }
+        };
+        let kind = diff.and_then(|(view, side)| view.kind(side, y));
+        let segments = match wrap {
+            Some(width) => buf.wrap_segments(y, width, options),
+            // the whole line in one row
+            None => std::iter::once(0..buf.line_layout(y).graphemes.len()).collect(),
+        };
+        for (i, segment) in segments.into_iter().enumerate() {
+            if lines.len() >= height {
+                break;
+            }
+            // the number only on the first row of a wrapped line, after the
+            // sign of its worst diagnostic and the change since the git HEAD
+            let (sign, change, num) = if i == 0 {
+                let sign = match worst_on_line(&buf.diagnostics, y) {
+                    Some(d) => {
+                        Span::styled(d.severity.sign(), Style::default().fg(d.severity.color()))
+                    }
+                    None => Span::raw(" "),
+                };
+                let change = match line_change(buf.git_hunks(), y) {
+                    Some(c) => Span::styled(c.sign(), Style::default().fg(c.color())),
+                    None => Span::raw(" "),
+                };
+                (sign, change, format!("{:>3} ", y + 1))
+            } else {
+                (Span::raw(" "), Span::raw(" "), String::new())
+            };
+            line_num.push(Line::from(vec![
+                sign,
+                change,
+                Span::styled(num, Style::default().fg(Color::DarkGray)),
+            ]));
+
+            let mut line = match wrap {
+                Some(width) => render_line(buf, y, segment, 0, width, selection, brackets),
+                None => render_line(
+                    buf,
+                    y,
+                    segment,
+                    scroll_offset.0,
+                    editor_main[1].width as usize,
+                    selection,
+                    brackets,
+                ),
+            };
+            if i > 0 {
+                line.spans.insert(
+                    0,
+                    Span::styled(
+                        options.show_break.clone(),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                );
+            }
+            if let Some(kind) = kind {
+                let pad = (editor_main[1].width as usize).saturating_sub(line.width());
+                line.spans.push(Span::raw(" ".repeat(pad)));
+                line = line.patch_style(Style::default().bg(kind.color()));
+            }
+            lines.push(line);
+        }
+    }
+    let line_num_text = Paragraph::new(Text::from(line_num)).block(line_num_block);
 
     frame.render_widget(line_num_text, editor_main[0]);
 
@@ -377,16 +752,7 @@ pub fn render_buffer(
         .border_style(Style::default().fg(border_color))
         .style(Style::default().fg(font_color));
 
-    //    let lines: Vec<Line> = buf.content.iter().map(|s| Line::from(s.as_str())).collect();
-
-    let lines = match cur_mod {
-        Mod::Visual(x, y) => render_visual(&buf.content, (*x, *y), *cursor_pos),
-        _ => buf.content.iter().map(|s| Line::from(s.as_str())).collect(),
-    };
-
-    let content = Paragraph::new(Text::from(lines))
-        .block(editor_block)
-        .scroll((scroll_offset.1 as u16, scroll_offset.0 as u16));
+    let content = Paragraph::new(Text::from(lines)).block(editor_block);
 
     // render the content
     frame.render_widget(content, editor_main[1]);
@@ -444,7 +810,13 @@ pub fn render_buffer(
         .borders(Borders::BOTTOM)
         .border_style(Style::default().fg(border_color))
         .style(Style::default().fg(status_third_font_color));
-    let status_third = Paragraph::new(format!("{} B", buf_size))
+    let third_text = match &buf.large {
+        Some(large) if large.is_indexing() => format!("{}% loaded", large.progress()),
+        // the large file mode only reads UTF-8
+        None if buf.large_refused => format!("{} B, not UTF-8, read whole", buf_size),
+        _ => format!("{} B", buf_size),
+    };
+    let status_third = Paragraph::new(third_text)
         .alignment(Alignment::Center)
         .block(status_third_block);
 
@@ -452,9 +824,9 @@ pub fn render_buffer(
 
     // show the forth position of status bar
     let buf_fmt = if let Some(info) = &buf.file_info {
-        get_format_text(info.format.clone())
+        info.format_text()
     } else {
-        "-"
+        "-".to_string()
     };
 
     let status_forth_block = Block::default()
@@ -467,8 +839,15 @@ pub fn render_buffer(
 
     frame.render_widget(status_forth, status_bar_main[3]);
 
-    // show the fifth position of status bar
-    let fifth_text = "-";
+    // show the fifth position of status bar, the git branch
+    let fifth_text = match &buf.git {
+        Some(git) => match (&git.branch, &git.commit) {
+            (Some(branch), _) => branch.clone(),
+            (None, Some(commit)) => commit.chars().take(7).collect(),
+            (None, None) => "-".to_string(),
+        },
+        None => "-".to_string(),
+    };
     let status_fifth_block = Block::default()
         .borders(Borders::BOTTOM)
         .border_style(Style::default().fg(border_color))
@@ -484,18 +863,32 @@ pub fn render_buffer(
         .borders(Borders::BOTTOM | Borders::RIGHT)
         .border_style(Style::default().fg(border_color))
         .style(Style::default().fg(status_last_font_color));
-    let is_rd_only = if let Some(info) = &buf.file_info {
-        info.read_only
-    } else {
-        true
-    };
-    let last_text = if is_rd_only { "READONLY" } else { "-" };
-    let status_last = Paragraph::new(last_text)
+    // the errors and the warnings of the buffer, after READONLY
+    let mut last_text = vec![];
+    if buf.read_only {
+        last_text.push(Span::raw("READONLY"));
+    }
+    let (errors, warnings) = count(&buf.diagnostics);
+    for (n, severity) in [(errors, Severity::Error), (warnings, Severity::Warning)] {
+        if n > 0 {
+            if !last_text.is_empty() {
+                last_text.push(Span::raw(" "));
+            }
+            last_text.push(Span::styled(
+                format!("{}{}", severity.sign(), n),
+                Style::default().fg(severity.color()),
+            ));
+        }
+    }
+    if last_text.is_empty() {
+        last_text.push(Span::raw("-"));
+    }
+    let status_last = Paragraph::new(Line::from(last_text))
         .alignment(Alignment::Center)
         .block(status_last_block);
 
     frame.render_widget(status_last, status_bar_main[5]);
-    return Ok(editor_main[1]);
+    Ok(editor_main[1])
 }
 
 pub fn render_popups(popups: &Popups, frame: &mut Frame) {
@@ -523,57 +916,161 @@ pub fn render_popups(popups: &Popups, frame: &mut Frame) {
 
         let paragraph = Paragraph::new(popup.content.to_string()).block(block);
 
+        // the text under it doesn't show through
+        let rect = rect.intersection(area);
+        frame.render_widget(Clear, rect);
         frame.render_widget(paragraph, rect);
     }
 }
 
-fn render_cursor(cursor: &Cursor) -> Result<(), std::io::Error> {
-    execute!(stdout(), cursor.style)?;
-    Ok(())
-}
+// the rows of the completion menu shown at once
+const COMPLETION_ROWS: usize = 8;
+const COMPLETION_MAX_WIDTH: usize = 60;
 
-fn render_visual(content: &Vec<String>, start: (usize, usize), end: (usize, usize)) -> Vec<Line> {
-    let ((sx, sy), (ex, ey)) = if start <= end {
-        (start, end)
+// the completion menu below the text at `at`, or above it when there's no
+// room, the selected item is highlighted
+fn render_completion(menu: &Completion, frame: &mut Frame, at: (u16, u16)) {
+    if menu.shown.is_empty() {
+        return;
+    }
+    let area = frame.area();
+    let rows = menu.shown.len().min(COMPLETION_ROWS);
+    // the selected item stays in the window
+    let first = (menu.selected + 1).saturating_sub(rows);
+    let items: Vec<_> = menu.shown[first..first + rows]
+        .iter()
+        .map(|&i| &menu.items[i])
+        .collect();
+    let label_width = items
+        .iter()
+        .map(|c| get_line_len(&c.label))
+        .max()
+        .unwrap_or(0);
+    let width = (label_width + 7).min(COMPLETION_MAX_WIDTH) as u16;
+    let height = rows as u16 + 2;
+
+    let (x, y) = at;
+    let y = if y + 1 + height <= area.bottom() || y < height {
+        y + 1
     } else {
-        (end, start)
+        y - height
     };
+    let x = x.saturating_sub(1).min(area.right().saturating_sub(width));
+    let rect = Rect::new(x, y, width, height).intersection(area);
+
+    let list: Vec<ListItem> = items
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let style = if first + i == menu.selected {
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Rgb(167, 199, 250))
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<1$} ", c.label, label_width), style),
+                Span::styled(c.source.tag(), style.add_modifier(Modifier::DIM)),
+            ]))
+        })
+        .collect();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("{}/{}", menu.selected + 1, menu.shown.len()))
+        .style(Style::default().fg(Color::Rgb(167, 199, 250)));
+    frame.render_widget(Clear, rect);
+    frame.render_widget(List::new(list).block(block), rect);
+}
 
-    let mut result = Vec::new();
-
-    let bg_color = Color::Rgb(127, 148, 141);
-    for (y, line) in content.iter().enumerate() {
-        let mut spans = Vec::new();
-        let line_len = get_line_len(line);
-
-        for x in 0..line_len {
-            let byte_start = char_to_byte_idx(line, x);
-            let byte_end = char_to_byte_idx(line, x + 1);
-            let ch = &line[byte_start..byte_end];
+fn render_cursor(cursor: &Cursor) -> Result<(), std::io::Error> {
+    execute!(stdout(), cursor.style)?;
+    Ok(())
+}
 
-            let in_range = if y < sy || y > ey {
+// the graphemes `range` of line `y` between the columns `left` and
+// `left + width`, with the selected graphemes and the matching brackets
+// highlighted
+fn render_line(
+    buf: &Buffer,
+    y: usize,
+    range: Range<usize>,
+    left: usize,
+    width: usize,
+    selection: Option<((usize, usize), (usize, usize))>,
+    brackets: Option<[(usize, usize); 2]>,
+) -> Line<'static> {
+    let line = &buf.content[y];
+    let layout = buf.line_layout(y);
+    let in_selection = |x: usize| match selection {
+        Some(((sx, sy), (ex, ey))) => {
+            if y < sy || y > ey {
                 false
             } else if sy == ey {
-                y == sy && x >= sx && x < ex
+                x >= sx && x < ex
             } else if y == sy {
                 x >= sx
             } else if y == ey {
                 x < ex
             } else {
                 true
-            };
+            }
+        }
+        None => false,
+    };
+
+    let selected_style = Style::default().bg(Color::Rgb(127, 148, 141));
+    let bracket_style = Style::default()
+        .fg(Color::Rgb(255, 214, 102))
+        .add_modifier(Modifier::BOLD);
+    let style_at = |x: usize| {
+        if in_selection(x) {
+            selected_style
+        } else if brackets.is_some_and(|b| b.contains(&(x, y))) {
+            bracket_style
+        } else {
+            Style::default()
+        }
+    };
 
-            if in_range {
-                spans.push(Span::styled(ch.to_string(), Style::default().bg(bg_color)));
+    let mut spans: Vec<Span> = vec![];
+    // graphemes next to each other with the same style share a span
+    let mut run = String::new();
+    let mut run_style = Style::default();
+    let mut col = 0;
+    for (x, (_, w)) in layout
+        .graphemes
+        .iter()
+        .enumerate()
+        .skip(range.start)
+        .take(range.len())
+    {
+        if col >= left + width {
+            break;
+        }
+        let style = style_at(x);
+        if style != run_style && !run.is_empty() {
+            spans.push(Span::styled(std::mem::take(&mut run), run_style));
+        }
+        run_style = style;
+        if col >= left {
+            let g = &line[layout.byte_range(x)];
+            if g == "\t" {
+                // as wide as the layout says, up to the next tab stop
+                run.extend(std::iter::repeat_n(' ', *w));
             } else {
-                spans.push(Span::raw(ch.to_string()));
+                run.push_str(g);
             }
+        } else if col + w > left {
+            // a wide grapheme cut by the left edge
+            run.extend(std::iter::repeat_n(' ', col + w - left));
         }
-
-        result.push(Line::from(spans));
+        col += w;
     }
-
-    result
+    if !run.is_empty() {
+        spans.push(Span::styled(run, run_style));
+    }
+    Line::from(spans)
 }
 
 fn get_banner() -> String {
@@ -1,20 +1,80 @@
 #![allow(dead_code)]
-use unicode_width::UnicodeWidthStr;
+use encoding_rs::{Encoding, UTF_8};
 use unicode_segmentation::UnicodeSegmentation;
 use std::fs;
-use color_eyre::Result;
+use std::hash::{DefaultHasher, Hasher};
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy)]
 pub enum FileFormat {
     UNIX,
     DOS,
 }
 
+impl FileFormat {
+    pub fn line_ending(self) -> &'static str {
+        match self {
+            FileFormat::UNIX => "\n",
+            FileFormat::DOS => "\r\n",
+        }
+    }
+}
+
+// the file on the disk when it was read or written by smile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskState {
+    pub mtime: SystemTime,
+    pub size: u64,
+    pub hash: u64,
+}
+
+impl DiskState {
+    pub fn new(path: &Path, bytes: &[u8]) -> Option<Self> {
+        let mtime = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+        Some(Self {
+            mtime,
+            size: bytes.len() as u64,
+            hash: hash_bytes(bytes),
+        })
+    }
+
+    // without the content, for files too large to hash
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            mtime: meta.modified().ok()?,
+            size: meta.len(),
+            hash: 0,
+        })
+    }
+
+    // cheap check, the content is only hashed when the metadata differs
+    pub fn same_metadata(&self, meta: &fs::Metadata) -> bool {
+        meta.len() == self.size && meta.modified().ok() == Some(self.mtime)
+    }
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+const BOM: char = '\u{feff}';
+
 pub struct FileInfo {
     pub size: u64,
     pub read_only: bool,
     pub format: FileFormat,
+    // whether the last line ends with a line ending
+    pub final_newline: bool,
+    // whether the file starts with a BOM
+    pub bom: bool,
+    pub encoding: &'static Encoding,
+    pub disk: Option<DiskState>,
 }
 
 impl FileInfo {
@@ -23,8 +83,70 @@ impl FileInfo {
             size: 0, 
             read_only: false,
             format: FileFormat::UNIX,
+            final_newline: true,
+            bom: false,
+            encoding: UTF_8,
+            disk: None,
         }
     }
+
+    // split the text of a file into lines, and remember how it was written
+    pub fn parse(raw: &str) -> (Vec<String>, Self) {
+        let (text, bom) = match raw.strip_prefix(BOM) {
+            Some(t) => (t, true),
+            None => (raw, false),
+        };
+        let format = detect_line_ending(text);
+        // an empty file is written back empty, `set eol` for text put in it
+        let (text, final_newline) = match text.strip_suffix('\n') {
+            Some(t) => (t, true),
+            None => (text, false),
+        };
+
+        let content = text
+            .split('\n')
+            .map(|l| match format {
+                FileFormat::DOS => l.strip_suffix('\r').unwrap_or(l).to_string(),
+                FileFormat::UNIX => l.to_string(),
+            })
+            .collect();
+
+        let info = Self {
+            size: raw.len() as u64,
+            read_only: false,
+            format,
+            final_newline,
+            bom,
+            encoding: UTF_8,
+            disk: None,
+        };
+        (content, info)
+    }
+
+    // join the lines back into the text of the file
+    pub fn join(&self, content: &[String]) -> String {
+        let eol = self.format.line_ending();
+        let mut text = String::new();
+        if self.bom {
+            text.push(BOM);
+        }
+        text.push_str(&content.join(eol));
+        if self.final_newline {
+            text.push_str(eol);
+        }
+        text
+    }
+
+    pub fn format_text(&self) -> String {
+        let mut text = format!("{} {}", self.encoding.name(), get_format_text(self.format));
+        if self.bom {
+            text.push_str(" BOM");
+        }
+        if !self.final_newline {
+            text.push_str(" noeol");
+        }
+        text
+    }
 }
 
 pub fn char_to_byte_idx(s: &str, char_idx: usize) -> usize {
@@ -34,11 +156,12 @@ pub fn char_to_byte_idx(s: &str, char_idx: usize) -> usize {
         .unwrap_or(s.len())
 }
 
-pub fn get_line_len(line: &String) -> usize {
+pub fn get_line_len(line: &str) -> usize {
     let graphemes = line.graphemes(true).collect::<Vec<&str>>();
     graphemes.len()
 }
 
+#[allow(clippy::if_same_then_else)]
 pub fn detect_line_ending(content: &str) -> FileFormat {
     if content.contains("\r\n") {
         FileFormat::DOS    // CRLF
@@ -63,3 +186,33 @@ pub fn arc_vec_to_string(v: Vec<Arc<str>>) -> Vec<String> {
 pub fn overlap(a_start: u16, a_end: u16, b_start: u16, b_end: u16) -> bool {
     a_start < b_end && b_start < a_end
 }
+
+// `YYYYMMDD-HHMMSS` in UTC
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // civil date from days since 1970-01-01
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
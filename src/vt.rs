@@ -0,0 +1,633 @@
+#![allow(dead_code)]
+// the screen of a terminal fed with the output of a program: the escape
+// sequences of VT100 and the xterm ones shells and most tools use move the
+// cursor, erase and color the cells, the lines scrolled off the top are kept
+use ratatui::style::{Color, Modifier, Style};
+use std::collections::VecDeque;
+use unicode_width::UnicodeWidthChar;
+
+// the lines kept above the screen
+pub const SCROLLBACK: usize = 5000;
+// the second column of a wide character, it isn't drawn
+pub const WIDE_TAIL: char = '\0';
+// longer titles and unknown sequences are cut
+const MAX_SEQUENCE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub style: Style,
+}
+
+impl Cell {
+    // an erased cell keeps the background of the pen
+    fn blank(pen: Style) -> Self {
+        Cell {
+            ch: ' ',
+            style: Style {
+                bg: pen.bg,
+                ..Style::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    // the character set of `ESC (`, only ASCII is used
+    Charset,
+    Csi,
+    Osc,
+    // an ESC in an OSC, the start of its terminator
+    OscEscape,
+}
+
+type Screen = Vec<Vec<Cell>>;
+
+pub struct Grid {
+    pub rows: usize,
+    pub cols: usize,
+    lines: Screen,
+    pub scrollback: VecDeque<Vec<Cell>>,
+    // the main screen and its cursor while the alternate one is shown
+    main_screen: Option<(Screen, (usize, usize))>,
+    // (x, y) on the screen
+    pub cursor: (usize, usize),
+    pub cursor_visible: bool,
+    // the arrows send `ESC O` instead of `ESC [`
+    pub app_cursor: bool,
+    autowrap: bool,
+    // the last column was written, the next character goes on the next line
+    wrap_next: bool,
+    pen: Style,
+    saved_cursor: ((usize, usize), Style),
+    // the lines `top..=bottom` scroll
+    top: usize,
+    bottom: usize,
+    state: State,
+    sequence: String,
+    utf8: Vec<u8>,
+    // the answers to the queries of the program, to be written back to it
+    pub replies: Vec<u8>,
+    pub title: Option<String>,
+}
+
+impl Grid {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        Self {
+            rows,
+            cols,
+            lines: vec![vec![Cell::blank(Style::default()); cols]; rows],
+            scrollback: VecDeque::new(),
+            main_screen: None,
+            cursor: (0, 0),
+            cursor_visible: true,
+            app_cursor: false,
+            autowrap: true,
+            wrap_next: false,
+            pen: Style::default(),
+            saved_cursor: ((0, 0), Style::default()),
+            top: 0,
+            bottom: rows - 1,
+            state: State::Ground,
+            sequence: String::new(),
+            utf8: vec![],
+            replies: vec![],
+            title: None,
+        }
+    }
+
+    // the lines of the scrollback and of the screen
+    pub fn total_lines(&self) -> usize {
+        self.scrollback.len() + self.lines.len()
+    }
+
+    // line `i` of the scrollback followed by the screen
+    pub fn line(&self, i: usize) -> &[Cell] {
+        match i.checked_sub(self.scrollback.len()) {
+            Some(y) => &self.lines[y],
+            None => &self.scrollback[i],
+        }
+    }
+
+    pub fn is_alternate(&self) -> bool {
+        self.main_screen.is_some()
+    }
+
+    // the text of the scrollback and the screen, without the blanks at the
+    // end of the lines and the empty lines at the bottom
+    pub fn text_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = (0..self.total_lines())
+            .map(|i| {
+                let text: String = self
+                    .line(i)
+                    .iter()
+                    .map(|c| c.ch)
+                    .filter(|ch| *ch != WIDE_TAIL)
+                    .collect();
+                text.trim_end().to_string()
+            })
+            .collect();
+        while lines.len() > 1 && lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        if (rows, cols) == (self.rows, self.cols) {
+            return;
+        }
+        let blank = Cell::blank(Style::default());
+        // the lines above the cursor go into the scrollback first, so it
+        // stays on the screen
+        let over = (self.cursor.1 + 1).saturating_sub(rows);
+        let gone: Vec<Vec<Cell>> = self.lines.drain(..over).collect();
+        if !self.is_alternate() {
+            for line in gone {
+                self.push_scrollback(line);
+            }
+        }
+        self.lines.truncate(rows);
+        self.lines.resize(rows, vec![blank; cols]);
+        for line in &mut self.lines {
+            line.resize(cols, blank);
+        }
+        if let Some((lines, cursor)) = &mut self.main_screen {
+            lines.truncate(rows);
+            lines.resize(rows, vec![blank; cols]);
+            for line in lines.iter_mut() {
+                line.resize(cols, blank);
+            }
+            cursor.0 = cursor.0.min(cols - 1);
+            cursor.1 = cursor.1.min(rows - 1);
+        }
+        self.rows = rows;
+        self.cols = cols;
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.cursor.0 = self.cursor.0.min(cols - 1);
+        self.cursor.1 = self.cursor.1.saturating_sub(over).min(rows - 1);
+        self.wrap_next = false;
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b < 0x80 && self.utf8.is_empty() {
+                self.advance(b as char);
+                continue;
+            }
+            self.utf8.push(b);
+            match std::str::from_utf8(&self.utf8) {
+                Ok(s) => {
+                    let ch = s.chars().next().unwrap_or(char::REPLACEMENT_CHARACTER);
+                    self.utf8.clear();
+                    self.advance(ch);
+                }
+                Err(e) if e.error_len().is_some() || self.utf8.len() >= 4 => {
+                    self.utf8.clear();
+                    self.advance(char::REPLACEMENT_CHARACTER);
+                }
+                // the rest of the character is in the next bytes
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn advance(&mut self, ch: char) {
+        match self.state {
+            State::Ground => self.ground(ch),
+            State::Escape => {
+                self.state = State::Ground;
+                self.escape(ch);
+            }
+            State::Charset => self.state = State::Ground,
+            State::Csi => match ch {
+                '\x20'..='\x3f' if self.sequence.len() < MAX_SEQUENCE => self.sequence.push(ch),
+                '\x20'..='\x3f' => {}
+                '\x40'..='\x7e' => {
+                    self.state = State::Ground;
+                    let sequence = std::mem::take(&mut self.sequence);
+                    self.csi(&sequence, ch);
+                }
+                '\x1b' => self.state = State::Escape,
+                // the control characters in a sequence are done at once
+                ch if ch < ' ' => self.ground(ch),
+                _ => {}
+            },
+            State::Osc => match ch {
+                '\x07' => self.osc_end(),
+                '\x1b' => self.state = State::OscEscape,
+                _ => {
+                    if self.sequence.len() < MAX_SEQUENCE {
+                        self.sequence.push(ch);
+                    }
+                }
+            },
+            State::OscEscape => {
+                self.osc_end();
+                if ch != '\\' {
+                    self.state = State::Escape;
+                    self.advance(ch);
+                }
+            }
+        }
+    }
+
+    fn ground(&mut self, ch: char) {
+        match ch {
+            '\x1b' => self.state = State::Escape,
+            '\r' => {
+                self.cursor.0 = 0;
+                self.wrap_next = false;
+            }
+            '\n' | '\x0b' | '\x0c' => self.line_feed(),
+            '\x08' => {
+                self.cursor.0 = self.cursor.0.saturating_sub(1);
+                self.wrap_next = false;
+            }
+            '\t' => {
+                self.cursor.0 = ((self.cursor.0 / 8 + 1) * 8).min(self.cols - 1);
+            }
+            ch if ch < ' ' || ch == '\x7f' => {}
+            ch => self.print(ch),
+        }
+    }
+
+    fn escape(&mut self, ch: char) {
+        match ch {
+            '[' => {
+                self.sequence.clear();
+                self.state = State::Csi;
+            }
+            ']' => {
+                self.sequence.clear();
+                self.state = State::Osc;
+            }
+            '(' | ')' | '*' | '+' => self.state = State::Charset,
+            '7' => self.saved_cursor = (self.cursor, self.pen),
+            '8' => self.restore_cursor(),
+            'D' => self.line_feed(),
+            'E' => {
+                self.cursor.0 = 0;
+                self.line_feed();
+            }
+            'M' => self.reverse_index(),
+            'c' => {
+                let scrollback = std::mem::take(&mut self.scrollback);
+                *self = Grid::new(self.rows, self.cols);
+                self.scrollback = scrollback;
+            }
+            _ => {}
+        }
+    }
+
+    fn osc_end(&mut self) {
+        self.state = State::Ground;
+        let sequence = std::mem::take(&mut self.sequence);
+        // 0 and 2 set the title, the others aren't used
+        if let Some((kind, text)) = sequence.split_once(';')
+            && matches!(kind, "0" | "2")
+        {
+            self.title = Some(text.to_string());
+        }
+    }
+
+    fn print(&mut self, ch: char) {
+        let width = ch.width().unwrap_or(0);
+        // the combining marks aren't kept
+        if width == 0 {
+            return;
+        }
+        if self.wrap_next && self.autowrap {
+            self.cursor.0 = 0;
+            self.line_feed();
+        }
+        self.wrap_next = false;
+        // a wide character which doesn't fit goes to the next line
+        if width == 2 && self.cursor.0 + 1 >= self.cols {
+            if !self.autowrap || self.cols < 2 {
+                return;
+            }
+            let (x, y) = self.cursor;
+            self.lines[y][x] = Cell::blank(self.pen);
+            self.cursor.0 = 0;
+            self.line_feed();
+        }
+        let (x, y) = self.cursor;
+        self.lines[y][x] = Cell {
+            ch,
+            style: self.pen,
+        };
+        if width == 2 {
+            self.lines[y][x + 1] = Cell {
+                ch: WIDE_TAIL,
+                style: self.pen,
+            };
+        }
+        if x + width >= self.cols {
+            self.cursor.0 = self.cols - 1;
+            self.wrap_next = true;
+        } else {
+            self.cursor.0 = x + width;
+        }
+    }
+
+    fn push_scrollback(&mut self, line: Vec<Cell>) {
+        if self.scrollback.len() >= SCROLLBACK {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(line);
+    }
+
+    fn line_feed(&mut self) {
+        self.wrap_next = false;
+        if self.cursor.1 == self.bottom {
+            self.scroll_up(1);
+        } else if self.cursor.1 + 1 < self.rows {
+            self.cursor.1 += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.wrap_next = false;
+        if self.cursor.1 == self.top {
+            self.scroll_down(1);
+        } else {
+            self.cursor.1 = self.cursor.1.saturating_sub(1);
+        }
+    }
+
+    // the lines of the region move up, the ones off the top of the whole
+    // main screen go into the scrollback
+    fn scroll_up(&mut self, n: usize) {
+        let n = n.min(self.bottom + 1 - self.top);
+        let blank = vec![Cell::blank(self.pen); self.cols];
+        for _ in 0..n {
+            let line = self.lines.remove(self.top);
+            self.lines.insert(self.bottom, blank.clone());
+            if self.top == 0 && !self.is_alternate() {
+                self.push_scrollback(line);
+            }
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.bottom + 1 - self.top);
+        let blank = vec![Cell::blank(self.pen); self.cols];
+        for _ in 0..n {
+            self.lines.remove(self.bottom);
+            self.lines.insert(self.top, blank.clone());
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        let ((x, y), pen) = self.saved_cursor;
+        self.cursor = (x.min(self.cols - 1), y.min(self.rows - 1));
+        self.pen = pen;
+        self.wrap_next = false;
+    }
+
+    // erase the cells `from..to` of line `y`
+    fn erase(&mut self, y: usize, from: usize, to: usize) {
+        let blank = Cell::blank(self.pen);
+        let to = to.min(self.cols);
+        if from < to {
+            self.lines[y][from..to].fill(blank);
+        }
+    }
+
+    fn set_alternate(&mut self, on: bool) {
+        match (on, self.main_screen.take()) {
+            (true, None) => {
+                let blank = vec![Cell::blank(Style::default()); self.cols];
+                let main = std::mem::replace(&mut self.lines, vec![blank; self.rows]);
+                self.main_screen = Some((main, self.cursor));
+            }
+            (false, Some((main, cursor))) => {
+                self.lines = main;
+                self.cursor = cursor;
+            }
+            (_, main) => self.main_screen = main,
+        }
+        self.wrap_next = false;
+    }
+
+    fn csi(&mut self, sequence: &str, action: char) {
+        let private = sequence.starts_with(['?', '>', '<', '=']);
+        let body = match private {
+            true => &sequence[1..],
+            false => sequence,
+        };
+        // the sub parameters of `38:2:r:g:b` are taken as parameters
+        let params: Vec<usize> = body
+            .split([';', ':'])
+            .map(|p| p.trim_matches(|c: char| !c.is_ascii_digit()))
+            .map(|p| p.parse().unwrap_or(0))
+            .collect();
+        let param = |i: usize, default: usize| match params.get(i) {
+            Some(0) | None => default,
+            Some(n) => *n,
+        };
+        // the counts are as large as the program sends, they're cut to the
+        // screen where they're used
+        let n = param(0, 1);
+        let (x, y) = self.cursor;
+        if action != 'm' {
+            self.wrap_next = false;
+        }
+        match (action, private) {
+            ('A', false) => {
+                let top = if y >= self.top { self.top } else { 0 };
+                self.cursor.1 = y.saturating_sub(n).max(top);
+            }
+            ('B' | 'e', false) => {
+                let bottom = if y <= self.bottom {
+                    self.bottom
+                } else {
+                    self.rows - 1
+                };
+                self.cursor.1 = y.saturating_add(n).min(bottom);
+            }
+            ('C' | 'a', false) => self.cursor.0 = x.saturating_add(n).min(self.cols - 1),
+            ('D', false) => self.cursor.0 = x.saturating_sub(n),
+            ('E', false) => self.cursor = (0, y.saturating_add(n).min(self.rows - 1)),
+            ('F', false) => self.cursor = (0, y.saturating_sub(n)),
+            ('G' | '`', false) => self.cursor.0 = (n - 1).min(self.cols - 1),
+            ('d', false) => self.cursor.1 = (n - 1).min(self.rows - 1),
+            ('H' | 'f', false) => {
+                self.cursor = (
+                    (param(1, 1) - 1).min(self.cols - 1),
+                    (n - 1).min(self.rows - 1),
+                );
+            }
+            ('J', false) => match param(0, 0) {
+                0 => {
+                    self.erase(y, x, self.cols);
+                    for line in y + 1..self.rows {
+                        self.erase(line, 0, self.cols);
+                    }
+                }
+                1 => {
+                    for line in 0..y {
+                        self.erase(line, 0, self.cols);
+                    }
+                    self.erase(y, 0, x + 1);
+                }
+                all => {
+                    for line in 0..self.rows {
+                        self.erase(line, 0, self.cols);
+                    }
+                    if all == 3 {
+                        self.scrollback.clear();
+                    }
+                }
+            },
+            ('K', false) => match param(0, 0) {
+                0 => self.erase(y, x, self.cols),
+                1 => self.erase(y, 0, x + 1),
+                _ => self.erase(y, 0, self.cols),
+            },
+            // the lines under the cursor in the region move
+            ('L' | 'M', false) if (self.top..=self.bottom).contains(&y) => {
+                let top = std::mem::replace(&mut self.top, y);
+                match action {
+                    'L' => self.scroll_down(n),
+                    _ => {
+                        // deleted lines don't go into the scrollback
+                        let blank = vec![Cell::blank(self.pen); self.cols];
+                        for _ in 0..n.min(self.bottom + 1 - y) {
+                            self.lines.remove(y);
+                            self.lines.insert(self.bottom, blank.clone());
+                        }
+                    }
+                }
+                self.top = top;
+                self.cursor.0 = 0;
+            }
+            ('@', false) => {
+                let blank = Cell::blank(self.pen);
+                let line = &mut self.lines[y];
+                for _ in 0..n.min(self.cols - x) {
+                    line.insert(x, blank);
+                }
+                line.truncate(self.cols);
+            }
+            ('P', false) => {
+                let blank = Cell::blank(self.pen);
+                let line = &mut self.lines[y];
+                line.drain(x..x.saturating_add(n).min(self.cols));
+                line.resize(self.cols, blank);
+            }
+            ('X', false) => self.erase(y, x, x.saturating_add(n)),
+            ('S', false) => self.scroll_up(n),
+            ('T', false) => self.scroll_down(n),
+            ('m', false) => self.sgr(&params),
+            ('r', false) => {
+                let top = param(0, 1) - 1;
+                let bottom = param(1, self.rows).min(self.rows) - 1;
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.cursor = (0, 0);
+                }
+            }
+            ('s', false) => self.saved_cursor = (self.cursor, self.pen),
+            ('u', false) => self.restore_cursor(),
+            ('h' | 'l', true) => {
+                let on = action == 'h';
+                for mode in &params {
+                    match mode {
+                        1 => self.app_cursor = on,
+                        7 => self.autowrap = on,
+                        25 => self.cursor_visible = on,
+                        47 | 1047 => self.set_alternate(on),
+                        1049 => {
+                            if on {
+                                self.saved_cursor = (self.cursor, self.pen);
+                                self.set_alternate(true);
+                            } else {
+                                self.set_alternate(false);
+                                self.restore_cursor();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ('n', false) => match n {
+                5 => self.replies.extend_from_slice(b"\x1b[0n"),
+                6 => self
+                    .replies
+                    .extend(format!("\x1b[{};{}R", y + 1, x + 1).bytes()),
+                _ => {}
+            },
+            ('c', _) if sequence.starts_with('>') => {
+                self.replies.extend_from_slice(b"\x1b[>0;0;0c")
+            }
+            ('c', _) => self.replies.extend_from_slice(b"\x1b[?1;2c"),
+            _ => {}
+        }
+    }
+
+    // the colors and the attributes of the next characters
+    fn sgr(&mut self, params: &[usize]) {
+        if params.is_empty() {
+            self.pen = Style::default();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            let pen = self.pen;
+            self.pen = match params[i] {
+                0 => Style::default(),
+                1 => pen.add_modifier(Modifier::BOLD),
+                2 => pen.add_modifier(Modifier::DIM),
+                3 => pen.add_modifier(Modifier::ITALIC),
+                4 => pen.add_modifier(Modifier::UNDERLINED),
+                5 | 6 => pen.add_modifier(Modifier::SLOW_BLINK),
+                7 => pen.add_modifier(Modifier::REVERSED),
+                8 => pen.add_modifier(Modifier::HIDDEN),
+                9 => pen.add_modifier(Modifier::CROSSED_OUT),
+                21 | 22 => pen.remove_modifier(Modifier::BOLD | Modifier::DIM),
+                23 => pen.remove_modifier(Modifier::ITALIC),
+                24 => pen.remove_modifier(Modifier::UNDERLINED),
+                25 => pen.remove_modifier(Modifier::SLOW_BLINK),
+                27 => pen.remove_modifier(Modifier::REVERSED),
+                28 => pen.remove_modifier(Modifier::HIDDEN),
+                29 => pen.remove_modifier(Modifier::CROSSED_OUT),
+                n @ 30..=37 => pen.fg(Color::Indexed(n as u8 - 30)),
+                n @ 90..=97 => pen.fg(Color::Indexed(n as u8 - 90 + 8)),
+                39 => Style { fg: None, ..pen },
+                n @ 40..=47 => pen.bg(Color::Indexed(n as u8 - 40)),
+                n @ 100..=107 => pen.bg(Color::Indexed(n as u8 - 100 + 8)),
+                49 => Style { bg: None, ..pen },
+                n @ (38 | 48) => {
+                    let color = match params.get(i + 1) {
+                        Some(5) => {
+                            i += 2;
+                            params.get(i).map(|c| Color::Indexed(*c as u8))
+                        }
+                        Some(2) => {
+                            i += 4;
+                            let c = |j: usize| params.get(j).copied().unwrap_or(0) as u8;
+                            Some(Color::Rgb(c(i - 2), c(i - 1), c(i)))
+                        }
+                        _ => None,
+                    };
+                    match (n, color) {
+                        (38, Some(color)) => pen.fg(color),
+                        (_, Some(color)) => pen.bg(color),
+                        _ => pen,
+                    }
+                }
+                _ => pen,
+            };
+            i += 1;
+        }
+    }
+}
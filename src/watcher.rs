@@ -0,0 +1,69 @@
+#![allow(dead_code)]
+// watch the files of the buffers in a thread, and post `AppEvent::FileChanged`
+// when one of them changes on the disk
+use crate::event_loop::AppEvent;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// how often the files are looked at
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// what the metadata said last time, `None` if the file didn't exist
+type Stamp = Option<(SystemTime, u64)>;
+
+pub struct FileWatcher {
+    paths: Arc<Mutex<HashMap<PathBuf, Stamp>>>,
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+impl FileWatcher {
+    pub fn spawn(tx: Sender<AppEvent>) -> Self {
+        let paths: Arc<Mutex<HashMap<PathBuf, Stamp>>> = Arc::new(Mutex::new(HashMap::new()));
+        let watched = paths.clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+                let mut changed = vec![];
+                if let Ok(mut paths) = watched.lock() {
+                    for (path, last) in paths.iter_mut() {
+                        let now = stamp(path);
+                        if now != *last {
+                            *last = now;
+                            changed.push(path.clone());
+                        }
+                    }
+                }
+                for path in changed {
+                    if tx.send(AppEvent::FileChanged(path)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Self { paths }
+    }
+
+    // watch exactly `paths`, the new ones start from their current state
+    pub fn set_paths(&self, paths: Vec<PathBuf>) {
+        let Ok(mut watched) = self.paths.lock() else {
+            return;
+        };
+        watched.retain(|p, _| paths.contains(p));
+        for path in paths {
+            if let Entry::Vacant(entry) = watched.entry(path) {
+                let s = stamp(entry.key());
+                entry.insert(s);
+            }
+        }
+    }
+}
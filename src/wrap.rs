@@ -0,0 +1,179 @@
+#![allow(dead_code)]
+// soft wrapping, a line is split into segments of graphemes, each one is a
+// row on the screen
+use crate::buffer::Buffer;
+use crate::linecache::LineLayout;
+use crate::options::PaneOptions;
+use ratatui::layout::Rect;
+use std::ops::Range;
+use unicode_width::UnicodeWidthStr;
+
+// columns on the left of a pane taken by the line numbers
+pub const LINE_NUMBER_WIDTH: u16 = 10;
+
+// columns of a pane the text can use, the right border takes one
+pub fn text_width(pane: Rect) -> usize {
+    pane.width.saturating_sub(LINE_NUMBER_WIDTH + 1) as usize
+}
+
+// the width the lines of a pane are wrapped at, `None` if they aren't
+pub fn wrap_width(pane: Rect, opts: &PaneOptions) -> Option<usize> {
+    if !opts.wrap {
+        return None;
+    }
+    let width = text_width(pane);
+    if opts.wrap_column > 0 {
+        Some(width.min(opts.wrap_column))
+    } else {
+        Some(width)
+    }
+}
+
+// split a line into the grapheme ranges of its rows, the rows after the
+// first one start with the break indicator
+pub fn wrap_line(
+    layout: &LineLayout,
+    line: &str,
+    width: usize,
+    opts: &PaneOptions,
+) -> Vec<Range<usize>> {
+    let count = layout.graphemes.len();
+    let indicator = opts.show_break.width();
+    let mut segments = vec![];
+    let mut start = 0;
+
+    while start < count {
+        let room = if segments.is_empty() {
+            width
+        } else {
+            width.saturating_sub(indicator)
+        };
+        let mut end = start;
+        let mut used = 0;
+        while end < count && used + layout.graphemes[end].1 <= room {
+            used += layout.graphemes[end].1;
+            end += 1;
+        }
+        if end == count {
+            segments.push(start..end);
+            break;
+        }
+        // at least one grapheme a row, even if it's wider than the pane
+        if end == start {
+            end += 1;
+        } else if opts.linebreak && !line[layout.byte_range(end)].trim().is_empty() {
+            let blank = (start + 1..end)
+                .rev()
+                .find(|&i| line[layout.byte_range(i - 1)].trim().is_empty());
+            if let Some(i) = blank {
+                end = i;
+            }
+        }
+        segments.push(start..end);
+        start = end;
+    }
+    if segments.is_empty() {
+        segments.push(0..0);
+    }
+    segments
+}
+
+// the row of `x` in `segments`, the end of a row is the head of the next one
+pub fn segment_of(segments: &[Range<usize>], x: usize) -> usize {
+    segments.iter().rposition(|s| s.start <= x).unwrap_or(0)
+}
+
+// the grapheme of `segment` under the column `col`, counted from the head of the row
+pub fn x_at_column(layout: &LineLayout, segment: &Range<usize>, last: bool, col: usize) -> usize {
+    let mut used = 0;
+    for x in segment.clone() {
+        let w = layout.graphemes[x].1;
+        if col < used + w {
+            return x;
+        }
+        used += w;
+    }
+    // past the end, only the last row can hold the cursor after its last grapheme
+    if last || segment.is_empty() {
+        segment.end
+    } else {
+        segment.end - 1
+    }
+}
+
+impl Buffer {
+    pub fn wrap_segments(&self, y: usize, width: usize, opts: &PaneOptions) -> Vec<Range<usize>> {
+        wrap_line(&self.line_layout(y), &self.content[y], width, opts)
+    }
+
+    // the row and column of `pos` when the rows start with line `top`
+    pub fn wrapped_position(
+        &self,
+        pos: (usize, usize),
+        top: usize,
+        width: usize,
+        opts: &PaneOptions,
+    ) -> (usize, usize) {
+        let (x, y) = pos;
+        let rows: usize = (top..y)
+            .map(|l| self.wrap_segments(l, width, opts).len())
+            .sum();
+        let segments = self.wrap_segments(y, width, opts);
+        let seg = segment_of(&segments, x);
+        let layout = self.line_layout(y);
+        let mut col = layout.width_upto(x) - layout.width_upto(segments[seg].start);
+        if seg > 0 {
+            col += opts.show_break.width();
+        }
+        (rows + seg, col)
+    }
+
+    // the position one row up or down, keeping the column in the row
+    pub fn wrapped_row_move(
+        &self,
+        pos: (usize, usize),
+        width: usize,
+        opts: &PaneOptions,
+        up: bool,
+    ) -> Option<(usize, usize)> {
+        let (x, y) = pos;
+        let segments = self.wrap_segments(y, width, opts);
+        let seg = segment_of(&segments, x);
+        let layout = self.line_layout(y);
+        let col = layout.width_upto(x) - layout.width_upto(segments[seg].start);
+
+        let (ty, target) = if up && seg > 0 {
+            (y, seg - 1)
+        } else if up && y > 0 {
+            (y - 1, self.wrap_segments(y - 1, width, opts).len() - 1)
+        } else if !up && seg + 1 < segments.len() {
+            (y, seg + 1)
+        } else if !up && y + 1 < self.content.len() {
+            (y + 1, 0)
+        } else {
+            return None;
+        };
+        let segments = self.wrap_segments(ty, width, opts);
+        let last = target + 1 == segments.len();
+        let tx = x_at_column(&self.line_layout(ty), &segments[target], last, col);
+        Some((tx, ty))
+    }
+
+    // the head and the tail of the row `pos` is in
+    pub fn wrapped_row_bounds(
+        &self,
+        pos: (usize, usize),
+        width: usize,
+        opts: &PaneOptions,
+    ) -> (usize, usize) {
+        let (x, y) = pos;
+        let segments = self.wrap_segments(y, width, opts);
+        let seg = segment_of(&segments, x);
+        let row = &segments[seg];
+        if seg + 1 == segments.len() || row.is_empty() {
+            (row.start, row.end)
+        } else {
+            (row.start, row.end - 1)
+        }
+    }
+}